@@ -89,6 +89,16 @@ mod codes {
     pub const ERR_FILE_EXISTS: i32 = -300;
     pub const ERR_FILE_NOT_FOUND: i32 = -301;
     pub const ERR_INVALID_RANGE: i32 = -302;
+    pub const ERR_DIRECTORY_EXISTS: i32 = -303;
+    pub const ERR_DIRECTORY_NOT_FOUND: i32 = -304;
+    pub const ERR_DIRECTORY_NOT_EMPTY: i32 = -305;
+    pub const ERR_VERSION_NOT_FOUND: i32 = -306;
+    pub const ERR_DIGEST_MISMATCH: i32 = -307;
+    pub const ERR_LINK_CYCLE: i32 = -308;
+    pub const ERR_QUOTA_EXCEEDED: i32 = -309;
+    pub const ERR_CONFLICT: i32 = -310;
+    pub const ERR_DIGEST_UNKNOWN: i32 = -311;
+    pub const ERR_INVALID_PATH: i32 = -312;
 
     // App errors
     pub const ERR_NO_SUCH_CONTAINER: i32 = -1002;
@@ -107,6 +117,7 @@ mod codes {
     pub const ERR_INVALID_FILE_CONTEXT_HANDLE: i32 = -1015;
     pub const ERR_INVALID_FILE_MODE: i32 = -1016;
     pub const ERR_INVALID_SIGN_SEC_KEY_HANDLE: i32 = -1017;
+    pub const ERR_INVALID_WATCHER_HANDLE: i32 = -1018;
 
     pub const ERR_UNEXPECTED: i32 = -2000;
 }
@@ -150,6 +161,8 @@ pub enum AppError {
     InvalidSignSecKeyHandle,
     /// Invalid file writer handle
     InvalidFileContextHandle,
+    /// Invalid MutableData watcher handle
+    InvalidWatcherHandle,
 
     /// Error while self-encrypting data
     SelfEncryption(SelfEncryptionError<SelfEncryptionStorageError>),
@@ -202,6 +215,7 @@ impl Display for AppError {
             }
             AppError::InvalidEncryptSecKeyHandle => write!(formatter, "Invalid secret key handle"),
             AppError::InvalidFileContextHandle => write!(formatter, "Invalid file context handle"),
+            AppError::InvalidWatcherHandle => write!(formatter, "Invalid watcher handle"),
             AppError::SelfEncryption(ref error) => {
                 write!(formatter, "Self-encryption error: {}", error)
             }
@@ -347,6 +361,16 @@ impl ErrorCode for AppError {
                     NfsError::InvalidRange => ERR_INVALID_RANGE,
                     NfsError::EncodeDecodeError(_) => ERR_ENCODE_DECODE_ERROR,
                     NfsError::SelfEncryption(_) => ERR_SELF_ENCRYPTION,
+                    NfsError::DirectoryExists => ERR_DIRECTORY_EXISTS,
+                    NfsError::DirectoryNotFound => ERR_DIRECTORY_NOT_FOUND,
+                    NfsError::DirectoryNotEmpty => ERR_DIRECTORY_NOT_EMPTY,
+                    NfsError::VersionNotFound => ERR_VERSION_NOT_FOUND,
+                    NfsError::DigestMismatch => ERR_DIGEST_MISMATCH,
+                    NfsError::LinkCycle => ERR_LINK_CYCLE,
+                    NfsError::QuotaExceeded => ERR_QUOTA_EXCEEDED,
+                    NfsError::Conflict(..) => ERR_CONFLICT,
+                    NfsError::DigestUnknown => ERR_DIGEST_UNKNOWN,
+                    NfsError::InvalidPath => ERR_INVALID_PATH,
                     NfsError::Unexpected(_) => ERR_UNEXPECTED,
                 }
             }
@@ -363,6 +387,7 @@ impl ErrorCode for AppError {
             AppError::InvalidSignSecKeyHandle => ERR_INVALID_SIGN_SEC_KEY_HANDLE,
             AppError::InvalidEncryptSecKeyHandle => ERR_INVALID_ENCRYPT_SEC_KEY_HANDLE,
             AppError::InvalidFileContextHandle => ERR_INVALID_FILE_CONTEXT_HANDLE,
+            AppError::InvalidWatcherHandle => ERR_INVALID_WATCHER_HANDLE,
             AppError::InvalidFileMode => ERR_INVALID_FILE_MODE,
             AppError::SelfEncryption(_) => ERR_SELF_ENCRYPTION,
             AppError::InvalidSelfEncryptorReadOffsets => ERR_INVALID_SELF_ENCRYPTOR_READ_OFFSETS,
@@ -55,6 +55,7 @@ use safe_core::ipc::{AuthGranted, BootstrapConfig};
 use std::ffi::{CStr, CString, OsStr};
 use std::os::raw::{c_char, c_void};
 use std::slice;
+use std::time::Duration;
 
 /// Create unregistered app.
 /// The `user_data` parameter corresponds to the first parameter of the
@@ -243,6 +244,72 @@ pub unsafe extern "C" fn app_reset_object_cache(
     })
 }
 
+/// Aborts all requests of the app which are still in progress, including the
+/// polls of active `MutableData` watchers, which stop. Their callbacks are
+/// called with the `OperationAborted` error. Requests made afterwards are not
+/// affected, so open files and other objects stay usable.
+#[no_mangle]
+pub unsafe extern "C" fn app_cancel_pending_requests(
+    app: *const App,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || -> Result<_, AppError> {
+        let user_data = OpaqueCtx(user_data);
+        (*app).send(move |_, context| {
+            context.cancel_requests();
+            o_cb(user_data.0, FFI_RESULT_OK);
+            None
+        })
+    })
+}
+
+/// Sets the timeout of the requests made by the calls to the app from now on,
+/// in milliseconds. Files opened afterwards keep this timeout for their reads
+/// and writes. Zero restores the default timeout.
+#[no_mangle]
+pub unsafe extern "C" fn app_set_request_timeout(
+    app: *const App,
+    timeout_ms: u64,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || -> Result<_, AppError> {
+        let user_data = OpaqueCtx(user_data);
+        (*app).send(move |_, context| {
+            let timeout = if timeout_ms > 0 {
+                Some(Duration::from_millis(timeout_ms))
+            } else {
+                None
+            };
+            context.set_request_timeout(timeout);
+            o_cb(user_data.0, FFI_RESULT_OK);
+            None
+        })
+    })
+}
+
+/// Enables or disables keeping the usage records of directories updated by the
+/// file operations of the app, and enforcing their quotas. Disabled by
+/// default. Directories with a quota set by `dir_set_quota` should only be
+/// mutated with it enabled, otherwise their usage goes stale.
+#[no_mangle]
+pub unsafe extern "C" fn app_set_usage_tracking(
+    app: *const App,
+    enabled: bool,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || -> Result<_, AppError> {
+        let user_data = OpaqueCtx(user_data);
+        (*app).send(move |client, _| {
+            client.set_usage_tracking(enabled);
+            o_cb(user_data.0, FFI_RESULT_OK);
+            None
+        })
+    })
+}
+
 /// Returns the name of the app's container.
 #[no_mangle]
 pub unsafe extern "C" fn app_container_name(
@@ -16,9 +16,12 @@
 // relating to use of the SAFE Network Software.
 
 use errors::AppError;
+use ffi::mutable_data::{ENTRY_CHANGE_DELETED, ENTRY_CHANGE_INSERTED, ENTRY_CHANGE_UPDATED};
 use ffi::mutable_data::permissions::USER_ANYONE;
 use object_cache::{MDataPermissionsHandle, ObjectCache, SignPubKeyHandle};
 use routing::{PermissionSet, User};
+use safe_core::ffi::MDataEntryChange as FfiMDataEntryChange;
+use safe_core::watcher::EntryChange;
 use std::collections::BTreeMap;
 
 // Retrieve the sign key corresponding to the handle from the object cache and wrap it
@@ -51,3 +54,18 @@ pub fn get_permissions(
 
     Ok(output)
 }
+
+// Returns FFI counterpart of the entry change, borrowing its key.
+pub fn entry_change_as_repr_c(change: &EntryChange) -> FfiMDataEntryChange {
+    let (kind, key) = match *change {
+        EntryChange::Inserted(ref key) => (ENTRY_CHANGE_INSERTED, key),
+        EntryChange::Updated(ref key) => (ENTRY_CHANGE_UPDATED, key),
+        EntryChange::Deleted(ref key) => (ENTRY_CHANGE_DELETED, key),
+    };
+
+    FfiMDataEntryChange {
+        kind,
+        key_ptr: key.as_ptr(),
+        key_len: key.len(),
+    }
+}
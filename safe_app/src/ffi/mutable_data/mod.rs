@@ -25,21 +25,26 @@ mod tests;
 
 use App;
 use errors::AppError;
-use ffi::helper::send;
+use ffi::helper::{send, send_sync};
 use ffi_utils::{FFI_RESULT_OK, FfiResult, OpaqueCtx, ReprC, SafePtr, catch_unwind_cb,
                 vec_clone_from_raw_parts};
-use futures::Future;
+use futures::{Future, Stream};
+use futures::future::{self, Either};
+use futures::sync::oneshot;
 use object_cache::{MDataEntriesHandle, MDataEntryActionsHandle, MDataPermissionsHandle,
-                   NULL_OBJECT_HANDLE, SignPubKeyHandle};
+                   NULL_OBJECT_HANDLE, SignPubKeyHandle, WatcherHandle};
 use routing::MutableData;
 use safe_core::{CoreError, FutureExt, MDataInfo};
+use safe_core::ffi::MDataEntryChange as FfiMDataEntryChange;
 use safe_core::ffi::MDataInfo as FfiMDataInfo;
 use safe_core::ffi::ipc::req::PermissionSet as FfiPermissionSet;
 use safe_core::ffi::ipc::resp::MDataKey as FfiMDataKey;
 use safe_core::ffi::ipc::resp::MDataValue as FfiMDataValue;
 use safe_core::ipc::req::{permission_set_clone_from_repr_c, permission_set_into_repr_c};
 use safe_core::ipc::resp::{MDataKey, MDataValue};
+use safe_core::watcher::WatchConfig;
 use std::os::raw::c_void;
+use std::time::Duration;
 
 /// Special value that represents an empty permission set.
 #[no_mangle]
@@ -49,6 +54,18 @@ pub static PERMISSIONS_EMPTY: u64 = NULL_OBJECT_HANDLE;
 #[no_mangle]
 pub static ENTRIES_EMPTY: u64 = NULL_OBJECT_HANDLE;
 
+/// Kind of an entry change reported by `mdata_watch`: the entry was inserted.
+#[no_mangle]
+pub static ENTRY_CHANGE_INSERTED: u32 = 0;
+
+/// Kind of an entry change reported by `mdata_watch`: the entry was updated.
+#[no_mangle]
+pub static ENTRY_CHANGE_UPDATED: u32 = 1;
+
+/// Kind of an entry change reported by `mdata_watch`: the entry was deleted.
+#[no_mangle]
+pub static ENTRY_CHANGE_DELETED: u32 = 2;
+
 /// Create new mutable data and put it on the network.
 ///
 /// `permissions_h` is a handle to permissions to be set on the mutable data.
@@ -523,3 +540,117 @@ pub unsafe extern "C" fn mdata_del_user_permissions(
         })
     })
 }
+
+/// Watch the mutable data for changes of its entries.
+///
+/// The data is polled, starting every `min_interval_ms` milliseconds and
+/// backing off up to every `max_interval_ms` milliseconds while nothing
+/// changes. Zero intervals mean the defaults.
+///
+/// `o_cb` is called once the watcher is set up, with a handle to be passed to
+/// `mdata_unwatch`. After that, `o_change_cb` is called on every change with
+/// the new version of the data and the list of changed entries. If watching
+/// fails, `o_change_cb` is called with the error and no more changes are
+/// reported.
+///
+/// Callback parameters: user data, error code, watcher handle
+/// Change callback parameters: user data, error code, version, vector of
+/// entry changes, vector size
+#[no_mangle]
+pub unsafe extern "C" fn mdata_watch(
+    app: *const App,
+    info: *const FfiMDataInfo,
+    min_interval_ms: u64,
+    max_interval_ms: u64,
+    user_data: *mut c_void,
+    o_change_cb: extern "C" fn(user_data: *mut c_void,
+                               result: *const FfiResult,
+                               version: u64,
+                               changes: *const FfiMDataEntryChange,
+                               len: usize),
+    o_cb: extern "C" fn(user_data: *mut c_void,
+                        result: *const FfiResult,
+                        watcher_h: WatcherHandle),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let user_data = OpaqueCtx(user_data);
+        let info = MDataInfo::clone_from_repr_c(info)?;
+
+        let mut config = WatchConfig::default();
+        if min_interval_ms > 0 {
+            config.min_interval = Duration::from_millis(min_interval_ms);
+        }
+        if max_interval_ms > 0 {
+            config.max_interval = Duration::from_millis(max_interval_ms);
+        }
+
+        (*app).send(move |client, context| {
+            let context = context.clone();
+
+            client
+                .watch_mdata(vec![(info.name, info.type_tag)], config)
+                .map_err(AppError::from)
+                .then(move |result| {
+                    let stream = match result {
+                        Ok(stream) => stream,
+                        Err(err) => {
+                            call_result_cb!(Err::<(), _>(err), user_data, o_cb);
+                            return Either::A(future::ok(()));
+                        }
+                    };
+
+                    let (cancel_tx, cancel_rx) = oneshot::channel();
+                    let watcher_h = context.object_cache().insert_watcher(cancel_tx);
+                    o_cb(user_data.0, FFI_RESULT_OK, watcher_h);
+
+                    let changes = stream
+                        .for_each(move |change| {
+                            let repr_c: Vec<_> = change
+                                .entries
+                                .iter()
+                                .map(helper::entry_change_as_repr_c)
+                                .collect();
+                            o_change_cb(
+                                user_data.0,
+                                FFI_RESULT_OK,
+                                change.version,
+                                repr_c.as_safe_ptr(),
+                                repr_c.len(),
+                            );
+                            Ok(())
+                        })
+                        .map_err(AppError::from)
+                        .or_else(move |err| {
+                            call_result_cb!(Err::<(), _>(err), user_data, o_change_cb);
+                            Ok::<_, ()>(())
+                        });
+
+                    // Stop on `mdata_unwatch`, or when the handle is dropped
+                    // from the object cache.
+                    let cancelled = cancel_rx.then(|_| Ok::<_, ()>(()));
+                    Either::B(changes.select(cancelled).map(|_| ()).map_err(|_| ()))
+                })
+                .into_box()
+                .into()
+        })
+    })
+}
+
+/// Stop watching the mutable data and free the watcher handle.
+///
+/// Callback parameters: user data, error code
+#[no_mangle]
+pub unsafe extern "C" fn mdata_unwatch(
+    app: *const App,
+    watcher_h: WatcherHandle,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        send_sync(app, user_data, o_cb, move |_, context| {
+            let cancel_tx = context.object_cache().remove_watcher(watcher_h)?;
+            let _ = cancel_tx.send(());
+            Ok(())
+        })
+    })
+}
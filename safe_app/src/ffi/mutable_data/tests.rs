@@ -15,21 +15,25 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 
-use errors::{ERR_ACCESS_DENIED, ERR_INVALID_SUCCESSOR, ERR_NO_SUCH_ENTRY, ERR_NO_SUCH_KEY};
+use errors::{ERR_ACCESS_DENIED, ERR_INVALID_SUCCESSOR, ERR_INVALID_WATCHER_HANDLE,
+             ERR_NO_SUCH_ENTRY, ERR_NO_SUCH_KEY, ERR_OPERATION_ABORTED};
+use ffi::app_cancel_pending_requests;
 use ffi::mdata_info::*;
 use ffi::mutable_data::*;
 use ffi::mutable_data::entries::*;
 use ffi::mutable_data::entry_actions::*;
 use ffi::mutable_data::permissions::*;
 use ffi_utils::{FfiResult, vec_clone_from_raw_parts};
-use ffi_utils::test_utils::{call_0, call_1, call_vec, call_vec_u8, send_via_user_data,
-                            sender_as_user_data};
-use object_cache::MDataPermissionsHandle;
+use ffi_utils::test_utils::{UserData, call_0, call_1, call_1_with_custom, call_vec, call_vec_u8,
+                            send_via_user_data, send_via_user_data_custom, sender_as_user_data};
+use object_cache::{MDataPermissionsHandle, WatcherHandle};
 use permissions::UserPermissionSet;
 use routing::{Action, PermissionSet};
 use safe_core::ffi::ipc::req::PermissionSet as FfiPermissionSet;
 use safe_core::ipc::req::{permission_set_clone_from_repr_c, permission_set_into_repr_c};
+use std::slice;
 use std::sync::mpsc;
+use std::time::Duration;
 use test_utils::create_app;
 
 // The usual test to insert, update, delete and list all permissions from the FFI point of view.
@@ -562,3 +566,179 @@ fn entries_crud_ffi() {
         }
     }
 }
+
+// Watch a mutable data, insert an entry into it and check the change gets
+// reported, then stop watching.
+#[test]
+fn watch_ffi() {
+    let app = create_app();
+
+    const KEY: &[u8] = b"hello";
+    const VALUE: &[u8] = b"world";
+
+    let perms_h: MDataPermissionsHandle =
+        unsafe { unwrap!(call_1(|ud, cb| mdata_permissions_new(&app, ud, cb))) };
+
+    let md_info: MDataInfo =
+        unsafe { unwrap!(call_1(|ud, cb| mdata_info_random_public(10_000, ud, cb))) };
+    let md_info = md_info.into_repr_c();
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| {
+            mdata_put(&app, &md_info, perms_h, ENTRIES_EMPTY, ud, cb)
+        }))
+    };
+
+    // Start watching
+    let (tx, rx) = mpsc::channel::<Result<Vec<(u32, Vec<u8>)>, i32>>();
+    let mut custom_ud: UserData = Default::default();
+    let ptr: *const _ = &tx;
+    custom_ud.custom = ptr as *mut c_void;
+
+    let watcher_h: WatcherHandle = unsafe {
+        unwrap!(call_1_with_custom(&mut custom_ud, |ud, cb| {
+            mdata_watch(&app, &md_info, 10, 40, ud, watch_change_cb, cb)
+        }))
+    };
+
+    // Insert an entry
+    let actions_h: MDataEntryActionsHandle =
+        unsafe { unwrap!(call_1(|ud, cb| mdata_entry_actions_new(&app, ud, cb))) };
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| {
+            mdata_entry_actions_insert(
+                &app,
+                actions_h,
+                KEY.as_ptr(),
+                KEY.len(),
+                VALUE.as_ptr(),
+                VALUE.len(),
+                ud,
+                cb,
+            )
+        }));
+        unwrap!(call_0(|ud, cb| {
+            mdata_mutate_entries(&app, &md_info, actions_h, ud, cb)
+        }))
+    }
+
+    let changes = unwrap!(unwrap!(rx.recv_timeout(Duration::from_secs(15))));
+    assert_eq!(changes, vec![(ENTRY_CHANGE_INSERTED, KEY.to_vec())]);
+
+    // Stop watching. The handle is not valid anymore afterwards.
+    unsafe {
+        unwrap!(call_0(|ud, cb| mdata_unwatch(&app, watcher_h, ud, cb)));
+
+        let res = call_0(|ud, cb| mdata_unwatch(&app, watcher_h, ud, cb));
+        match res {
+            Err(ERR_INVALID_WATCHER_HANDLE) => (),
+            x => panic!("Unexpected {:?}", x),
+        }
+    }
+}
+
+// Watch a mutable data and cancel the pending requests of the app. The watcher
+// should stop with the `OperationAborted` error. Its handle can still be
+// freed, and watching again reports changes as usual.
+#[test]
+fn watch_cancel_ffi() {
+    let app = create_app();
+
+    const KEY: &[u8] = b"hello";
+    const VALUE: &[u8] = b"world";
+
+    let perms_h: MDataPermissionsHandle =
+        unsafe { unwrap!(call_1(|ud, cb| mdata_permissions_new(&app, ud, cb))) };
+
+    let md_info: MDataInfo =
+        unsafe { unwrap!(call_1(|ud, cb| mdata_info_random_public(10_000, ud, cb))) };
+    let md_info = md_info.into_repr_c();
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| {
+            mdata_put(&app, &md_info, perms_h, ENTRIES_EMPTY, ud, cb)
+        }))
+    };
+
+    let (tx, rx) = mpsc::channel::<Result<Vec<(u32, Vec<u8>)>, i32>>();
+    let mut custom_ud: UserData = Default::default();
+    let ptr: *const _ = &tx;
+    custom_ud.custom = ptr as *mut c_void;
+
+    // Start watching, then cancel.
+    let watcher_h: WatcherHandle = unsafe {
+        unwrap!(call_1_with_custom(&mut custom_ud, |ud, cb| {
+            mdata_watch(&app, &md_info, 10, 40, ud, watch_change_cb, cb)
+        }))
+    };
+
+    unsafe { unwrap!(call_0(|ud, cb| app_cancel_pending_requests(&app, ud, cb))) };
+
+    match unwrap!(rx.recv_timeout(Duration::from_secs(15))) {
+        Err(ERR_OPERATION_ABORTED) => (),
+        x => panic!("Unexpected {:?}", x),
+    }
+
+    unsafe { unwrap!(call_0(|ud, cb| mdata_unwatch(&app, watcher_h, ud, cb))) };
+
+    // Watch again and insert an entry.
+    let watcher_h: WatcherHandle = unsafe {
+        unwrap!(call_1_with_custom(&mut custom_ud, |ud, cb| {
+            mdata_watch(&app, &md_info, 10, 40, ud, watch_change_cb, cb)
+        }))
+    };
+
+    let actions_h: MDataEntryActionsHandle =
+        unsafe { unwrap!(call_1(|ud, cb| mdata_entry_actions_new(&app, ud, cb))) };
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| {
+            mdata_entry_actions_insert(
+                &app,
+                actions_h,
+                KEY.as_ptr(),
+                KEY.len(),
+                VALUE.as_ptr(),
+                VALUE.len(),
+                ud,
+                cb,
+            )
+        }));
+        unwrap!(call_0(|ud, cb| {
+            mdata_mutate_entries(&app, &md_info, actions_h, ud, cb)
+        }))
+    }
+
+    let changes = unwrap!(unwrap!(rx.recv_timeout(Duration::from_secs(15))));
+    assert_eq!(changes, vec![(ENTRY_CHANGE_INSERTED, KEY.to_vec())]);
+
+    unsafe { unwrap!(call_0(|ud, cb| mdata_unwatch(&app, watcher_h, ud, cb))) };
+}
+
+// Reports the entry changes of `mdata_watch` through the custom user data.
+extern "C" fn watch_change_cb(
+    user_data: *mut c_void,
+    res: *const FfiResult,
+    _version: u64,
+    changes: *const FfiMDataEntryChange,
+    len: usize,
+) {
+    unsafe {
+        let result: Result<Vec<(u32, Vec<u8>)>, i32> = if (*res).error_code == 0 {
+            Ok(
+                slice::from_raw_parts(changes, len)
+                    .iter()
+                    .map(|change| {
+                        (change.kind, vec_clone_from_raw_parts(change.key_ptr, change.key_len))
+                    })
+                    .collect(),
+            )
+        } else {
+            Err((*res).error_code)
+        };
+
+        send_via_user_data_custom(user_data, result);
+    }
+}
+
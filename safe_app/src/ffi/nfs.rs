@@ -17,24 +17,27 @@
 
 use {App, AppContext};
 use errors::AppError;
-use ffi::helper::send;
+use ffi::helper::{send, send_sync};
 use ffi_utils::{FFI_RESULT_OK, FfiResult, OpaqueCtx, ReprC, SafePtr, catch_unwind_cb, from_c_str,
                 vec_clone_from_raw_parts};
 use futures::Future;
 use futures::future::{self, Either};
 use object_cache::FileContextHandle;
-use safe_core::{FutureExt, MDataInfo};
+use safe_core::{CancelHandle, FutureExt, MDataInfo};
 use safe_core::ffi::MDataInfo as FfiMDataInfo;
-use safe_core::ffi::nfs::File;
-use safe_core::nfs::{Mode, Reader, Writer, file_helper};
+use safe_core::ffi::nfs::{File, FileEntry, FileVersion, TrashEntry, Usage};
+use safe_core::nfs::{Mode, Quota, Reader, Writer, file_helper};
 use safe_core::nfs::File as NativeFile;
+use std::ffi::CString;
 use std::os::raw::{c_char, c_void};
+use std::ptr;
 
 /// Holds context for file operations, depending on the mode.
 pub struct FileContext {
     reader: Option<Reader<AppContext>>,
     writer: Option<Writer<AppContext>>,
     original_file: NativeFile,
+    cancel: CancelHandle,
 }
 
 /// Replaces the entire content of the file when writing data.
@@ -81,6 +84,47 @@ pub unsafe extern "C" fn dir_fetch_file(
     })
 }
 
+/// List all files in the directory, together with their versions.
+///
+/// Callback parameters: user data, error code, file entries vector, vector size
+#[no_mangle]
+pub unsafe extern "C" fn dir_list_files(
+    app: *const App,
+    parent_info: *const FfiMDataInfo,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void,
+                        result: *const FfiResult,
+                        entries_ptr: *const FileEntry,
+                        entries_len: usize),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = MDataInfo::clone_from_repr_c(parent_info)?;
+        let user_data = OpaqueCtx(user_data);
+
+        (*app).send(move |client, _| {
+            file_helper::list(client, &parent_info)
+                .map_err(AppError::from)
+                .and_then(move |files| {
+                    let mut entries = Vec::with_capacity(files.len());
+                    for (name, version, file) in files {
+                        entries.push(FileEntry {
+                            name: CString::new(name)?.into_raw(),
+                            version,
+                            file: file.into_repr_c(),
+                        });
+                    }
+                    o_cb(user_data.0, FFI_RESULT_OK, entries.as_safe_ptr(), entries.len());
+                    Ok(())
+                })
+                .map_err(move |err| {
+                    call_result_cb!(Err::<(), _>(err), user_data, o_cb);
+                })
+                .into_box()
+                .into()
+        })
+    })
+}
+
 /// Insert the file into the parent directory.
 ///
 /// Callback parameters: user data, error code
@@ -129,6 +173,113 @@ pub unsafe extern "C" fn dir_update_file(
     })
 }
 
+/// Replace the file in the parent directory, keeping the replaced file in its
+/// history. At most `max_history` previous versions are kept.
+/// If `version` is 0, the correct version is obtained automatically.
+///
+/// Callback parameters: user data, error code
+#[no_mangle]
+pub unsafe extern "C" fn dir_update_file_versioned(
+    app: *const App,
+    parent_info: *const FfiMDataInfo,
+    file_name: *const c_char,
+    file: *const File,
+    version: u64,
+    max_history: usize,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = MDataInfo::clone_from_repr_c(parent_info)?;
+        let file = NativeFile::clone_from_repr_c(file)?;
+        let file_name = from_c_str(file_name)?;
+
+        send(app, user_data, o_cb, move |client, _| {
+            file_helper::update_versioned(
+                client.clone(),
+                parent_info,
+                file_name,
+                &file,
+                version,
+                max_history,
+            )
+        })
+    })
+}
+
+/// Retrieve the previous versions of the file, oldest first.
+///
+/// Callback parameters: user data, error code, file versions vector, vector size
+#[no_mangle]
+pub unsafe extern "C" fn dir_file_history(
+    app: *const App,
+    parent_info: *const FfiMDataInfo,
+    file_name: *const c_char,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void,
+                        result: *const FfiResult,
+                        versions_ptr: *const FileVersion,
+                        versions_len: usize),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = MDataInfo::clone_from_repr_c(parent_info)?;
+        let file_name = from_c_str(file_name)?;
+        let user_data = OpaqueCtx(user_data);
+
+        (*app).send(move |client, _| {
+            file_helper::history(client, &parent_info, file_name)
+                .map(move |history| {
+                    let versions: Vec<_> = history
+                        .into_iter()
+                        .map(|(version, file)| {
+                            FileVersion {
+                                version,
+                                file: file.into_repr_c(),
+                            }
+                        })
+                        .collect();
+                    o_cb(
+                        user_data.0,
+                        FFI_RESULT_OK,
+                        versions.as_safe_ptr(),
+                        versions.len(),
+                    );
+                })
+                .map_err(AppError::from)
+                .map_err(move |err| {
+                    call_result_cb!(Err::<(), _>(err), user_data, o_cb);
+                })
+                .into_box()
+                .into()
+        })
+    })
+}
+
+/// Restore the previous version of the file with the given entry version,
+/// keeping the replaced file in its history. At most `max_history` previous
+/// versions are kept.
+///
+/// Callback parameters: user data, error code
+#[no_mangle]
+pub unsafe extern "C" fn dir_restore_file(
+    app: *const App,
+    parent_info: *const FfiMDataInfo,
+    file_name: *const c_char,
+    version: u64,
+    max_history: usize,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = MDataInfo::clone_from_repr_c(parent_info)?;
+        let file_name = from_c_str(file_name)?;
+
+        send(app, user_data, o_cb, move |client, _| {
+            file_helper::restore(client.clone(), parent_info, file_name, version, max_history)
+        })
+    })
+}
+
 /// Delete the file in the parent directory.
 ///
 /// Callback parameters: user data, error code
@@ -151,6 +302,243 @@ pub unsafe extern "C" fn dir_delete_file(
     })
 }
 
+/// Rename the file within the parent directory. The old entry is deleted
+/// using `version`; if it is 0, the correct version is obtained automatically.
+///
+/// Callback parameters: user data, error code, version of the new entry
+#[no_mangle]
+pub unsafe extern "C" fn dir_rename_file(
+    app: *const App,
+    parent_info: *const FfiMDataInfo,
+    file_name: *const c_char,
+    new_name: *const c_char,
+    version: u64,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult, new_version: u64),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = MDataInfo::clone_from_repr_c(parent_info)?;
+        let file_name = from_c_str(file_name)?;
+        let new_name = from_c_str(new_name)?;
+
+        send(app, user_data, o_cb, move |client, _| {
+            file_helper::rename(client.clone(), parent_info, file_name, new_name, version)
+        })
+    })
+}
+
+/// Move the file from the parent directory into another directory under
+/// `new_name`. If the operation is interrupted, calling it again with the same
+/// arguments completes the move.
+///
+/// Callback parameters: user data, error code, version of the new entry
+#[no_mangle]
+pub unsafe extern "C" fn dir_move_file(
+    app: *const App,
+    parent_info: *const FfiMDataInfo,
+    file_name: *const c_char,
+    new_parent_info: *const FfiMDataInfo,
+    new_name: *const c_char,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult, new_version: u64),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = MDataInfo::clone_from_repr_c(parent_info)?;
+        let new_parent_info = MDataInfo::clone_from_repr_c(new_parent_info)?;
+        let file_name = from_c_str(file_name)?;
+        let new_name = from_c_str(new_name)?;
+
+        send(app, user_data, o_cb, move |client, _| {
+            file_helper::move_to(
+                client.clone(),
+                parent_info,
+                file_name,
+                new_parent_info,
+                new_name,
+            )
+        })
+    })
+}
+
+/// Move the file in the parent directory to its trash, from which it can be
+/// restored later. If `version` is 0, the correct version is obtained
+/// automatically.
+///
+/// Callback parameters: user data, error code
+#[no_mangle]
+pub unsafe extern "C" fn dir_soft_delete_file(
+    app: *const App,
+    parent_info: *const FfiMDataInfo,
+    file_name: *const c_char,
+    version: u64,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = MDataInfo::clone_from_repr_c(parent_info)?;
+        let file_name = from_c_str(file_name)?;
+
+        send(app, user_data, o_cb, move |client, _| {
+            file_helper::soft_delete(client.clone(), parent_info, file_name, version)
+        })
+    })
+}
+
+/// List the files in the trash of the directory, ordered by name and then by
+/// the time of deletion.
+///
+/// Callback parameters: user data, error code, trash entries vector, vector size
+#[no_mangle]
+pub unsafe extern "C" fn dir_list_trash(
+    app: *const App,
+    parent_info: *const FfiMDataInfo,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void,
+                        result: *const FfiResult,
+                        entries_ptr: *const TrashEntry,
+                        entries_len: usize),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = MDataInfo::clone_from_repr_c(parent_info)?;
+        let user_data = OpaqueCtx(user_data);
+
+        (*app).send(move |client, _| {
+            file_helper::list_trash(client, &parent_info)
+                .map_err(AppError::from)
+                .and_then(move |trash| {
+                    let mut entries = Vec::with_capacity(trash.len());
+                    for (name, trashed) in trash {
+                        entries.push(TrashEntry {
+                            name: CString::new(name)?.into_raw(),
+                            deleted_sec: trashed.deleted.timestamp(),
+                            deleted_nsec: trashed.deleted.timestamp_subsec_nanos(),
+                            file: trashed.file.into_repr_c(),
+                        });
+                    }
+                    o_cb(user_data.0, FFI_RESULT_OK, entries.as_safe_ptr(), entries.len());
+                    Ok(())
+                })
+                .map_err(move |err| {
+                    call_result_cb!(Err::<(), _>(err), user_data, o_cb);
+                })
+                .into_box()
+                .into()
+        })
+    })
+}
+
+/// Move the most recently deleted file with the given name from the trash
+/// back into the parent directory.
+///
+/// Callback parameters: user data, error code
+#[no_mangle]
+pub unsafe extern "C" fn dir_restore_from_trash(
+    app: *const App,
+    parent_info: *const FfiMDataInfo,
+    file_name: *const c_char,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = MDataInfo::clone_from_repr_c(parent_info)?;
+        let file_name = from_c_str(file_name)?;
+
+        send(app, user_data, o_cb, move |client, _| {
+            file_helper::restore_from_trash(client.clone(), parent_info, file_name)
+        })
+    })
+}
+
+/// Irrevocably delete all files in the trash of the directory.
+///
+/// Callback parameters: user data, error code
+#[no_mangle]
+pub unsafe extern "C" fn dir_empty_trash(
+    app: *const App,
+    parent_info: *const FfiMDataInfo,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = MDataInfo::clone_from_repr_c(parent_info)?;
+
+        send(app, user_data, o_cb, move |client, _| {
+            file_helper::empty_trash(client.clone(), parent_info)
+        })
+    })
+}
+
+/// Retrieve the usage of the directory. If the directory doesn't track its
+/// usage, the callback receives a null pointer.
+///
+/// Callback parameters: user data, error code, usage
+#[no_mangle]
+pub unsafe extern "C" fn dir_usage(
+    app: *const App,
+    parent_info: *const FfiMDataInfo,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult, usage: *const Usage),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = MDataInfo::clone_from_repr_c(parent_info)?;
+        let user_data = OpaqueCtx(user_data);
+
+        (*app).send(move |client, _| {
+            file_helper::dir_usage(client, &parent_info)
+                .map(move |usage| match usage {
+                    Some(usage) => {
+                        let ffi_usage = usage.into_repr_c();
+                        o_cb(user_data.0, FFI_RESULT_OK, &ffi_usage)
+                    }
+                    None => o_cb(user_data.0, FFI_RESULT_OK, ptr::null()),
+                })
+                .map_err(AppError::from)
+                .map_err(move |err| {
+                    call_result_cb!(Err::<(), _>(err), user_data, o_cb);
+                })
+                .into_box()
+                .into()
+        })
+    })
+}
+
+/// Set the soft quota of the directory, enabling tracking of its usage if
+/// not enabled yet. Limits with the corresponding `has_*` flag set to `false`
+/// are unlimited.
+///
+/// Callback parameters: user data, error code
+#[no_mangle]
+pub unsafe extern "C" fn dir_set_quota(
+    app: *const App,
+    parent_info: *const FfiMDataInfo,
+    has_max_files: bool,
+    max_files: u64,
+    has_max_size: bool,
+    max_size: u64,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = MDataInfo::clone_from_repr_c(parent_info)?;
+        let quota = Quota {
+            max_files: if has_max_files {
+                Some(max_files)
+            } else {
+                None
+            },
+            max_size: if has_max_size {
+                Some(max_size)
+            } else {
+                None
+            },
+        };
+
+        send(app, user_data, o_cb, move |client, _| {
+            file_helper::set_quota(client.clone(), parent_info, quota)
+        })
+    })
+}
+
 /// Open the file to read of write its contents.
 ///
 /// Callback parameters: user data, error code, file context handle
@@ -172,6 +560,7 @@ pub unsafe extern "C" fn file_open(
         send(app, user_data, o_cb, move |client, context| {
             let context = context.clone();
             let original_file = file.clone();
+            let (client, cancel) = client.cancellable();
 
             // Initialise the reader if OPEN_MODE_READ is requested
             let reader = if open_mode & OPEN_MODE_READ != 0 {
@@ -189,12 +578,8 @@ pub unsafe extern "C" fn file_open(
                 } else {
                     Mode::Overwrite
                 };
-                let fut = file_helper::write(
-                    client.clone(),
-                    file,
-                    writer_mode,
-                    parent_info.enc_key().cloned(),
-                ).map(Some);
+                let fut = file_helper::write_in(client.clone(), &parent_info, file, writer_mode)
+                    .map(Some);
                 Either::A(fut)
             } else {
                 Either::B(future::ok(None))
@@ -205,6 +590,7 @@ pub unsafe extern "C" fn file_open(
                     reader,
                     writer,
                     original_file,
+                    cancel,
                 };
                 context.object_cache().insert_file(file_ctx)
             })
@@ -212,6 +598,27 @@ pub unsafe extern "C" fn file_open(
     })
 }
 
+/// Abort the reads and writes of the file which are still in progress. Their
+/// callbacks are called with the `OperationAborted` error. The file stays open,
+/// and can be read, written and closed afterwards.
+///
+/// Callback parameters: user data, error code
+#[no_mangle]
+pub unsafe extern "C" fn file_cancel(
+    app: *const App,
+    file_h: FileContextHandle,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        send_sync(app, user_data, o_cb, move |_, context| {
+            let file_ctx = context.object_cache().get_file(file_h)?;
+            file_ctx.cancel.cancel();
+            Ok(())
+        })
+    })
+}
+
 /// Get a size of file opened for read.
 ///
 /// Callback parameters: user data, error code, file size
@@ -133,6 +133,102 @@ fn network_status_callback() {
     }
 }
 
+// Test that cancelling pending requests aborts them, but not the requests made
+// afterwards.
+#[cfg(all(test, feature = "use-mock-routing"))]
+#[test]
+fn cancel_pending_requests() {
+    use ffi_utils::test_utils::call_0;
+    use rand;
+    use safe_core::CoreError;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let app = create_app();
+    let app = Box::into_raw(Box::new(app));
+
+    let (tx, rx) = mpsc::channel();
+
+    unsafe {
+        unwrap!((*app).send(move |client, _| {
+            let client2 = client.clone();
+
+            client.set_simulate_timeout(true);
+            client
+                .get_mdata_version(rand::random(), 0)
+                .then(move |result| {
+                    client2.set_simulate_timeout(false);
+                    unwrap!(tx.send(result));
+                    Ok::<_, ()>(())
+                })
+                .into_box()
+                .into()
+        }));
+
+        unwrap!(call_0(|ud, cb| app_cancel_pending_requests(app, ud, cb)));
+    }
+
+    match unwrap!(rx.recv_timeout(Duration::from_secs(15))) {
+        Err(CoreError::OperationAborted) => (),
+        res => panic!("Unexpected {:?}", res),
+    }
+
+    let stats: AccountInfo = unsafe { unwrap!(call_1(|ud, cb| app_account_info(app, ud, cb))) };
+    assert!(stats.mutations_available > 0);
+
+    unsafe { app_free(app) };
+}
+
+// Test that the request timeout set over FFI applies to the requests made
+// afterwards, and that zero restores the default timeout.
+#[cfg(all(test, feature = "use-mock-routing"))]
+#[test]
+fn request_timeout() {
+    use ffi_utils::test_utils::call_0;
+    use rand;
+    use safe_core::CoreError;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let app = create_app();
+    let app = Box::into_raw(Box::new(app));
+
+    let (tx, rx) = mpsc::channel();
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| app_set_request_timeout(app, 100, ud, cb)));
+
+        unwrap!((*app).send(move |client, _| {
+            let client2 = client.clone();
+
+            client.set_simulate_timeout(true);
+            client
+                .get_mdata_version(rand::random(), 0)
+                .then(move |result| {
+                    client2.set_simulate_timeout(false);
+                    unwrap!(tx.send(result));
+                    Ok::<_, ()>(())
+                })
+                .into_box()
+                .into()
+        }));
+    }
+
+    match unwrap!(rx.recv_timeout(Duration::from_secs(15))) {
+        Err(CoreError::RequestTimeout) => (),
+        res => panic!("Unexpected {:?}", res),
+    }
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| app_set_request_timeout(app, 0, ud, cb)));
+
+        let stats: AccountInfo = unwrap!(call_1(|ud, cb| app_account_info(app, ud, cb)));
+        assert!(stats.mutations_available > 0);
+
+        app_free(app);
+    }
+}
+
 // Test getting the app's container name.
 #[test]
 fn test_app_container_name() {
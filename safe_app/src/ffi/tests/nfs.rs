@@ -17,43 +17,64 @@
 
 use App;
 use errors::AppError;
+use ffi::{app_cancel_pending_requests, app_set_usage_tracking};
 use ffi::nfs::*;
-use ffi_utils::ErrorCode;
-use ffi_utils::test_utils::{call_0, call_1, call_2, call_vec_u8};
+use ffi_utils::{ErrorCode, ReprC, from_c_str};
+use ffi_utils::test_utils::{call_0, call_1, call_2, call_vec, call_vec_u8};
 use futures::Future;
 use object_cache::FileContextHandle;
 use safe_core::ffi::MDataInfo;
-use safe_core::ffi::nfs::File;
+use safe_core::ffi::nfs::{File, FileEntry, FileVersion, TrashEntry};
+use safe_core::ffi::nfs::Usage as FfiUsage;
 use safe_core::ipc::Permission;
 use safe_core::nfs::File as NativeFile;
-use safe_core::nfs::NfsError;
+use safe_core::nfs::{NfsError, Quota, Usage};
 use std::collections::HashMap;
 use std::ffi::CString;
 use test_utils::{create_app_with_access, run};
 
 fn setup() -> (App, MDataInfo) {
+    let (app, mut container_infos) = setup_with_containers(&["_videos"]);
+    let container_info = container_infos.remove(0);
+    (app, container_info)
+}
+
+// Create an app with full access to the given containers, and return the
+// infos of the containers in the same order.
+fn setup_with_containers(names: &[&str]) -> (App, Vec<MDataInfo>) {
     let mut container_permissions = HashMap::new();
-    let _ = container_permissions.insert(
-        "_videos".to_string(),
-        btree_set![
-            Permission::Read,
-            Permission::Insert,
-            Permission::Update,
-            Permission::Delete,
-        ],
-    );
+    for name in names {
+        let _ = container_permissions.insert(
+            name.to_string(),
+            btree_set![
+                Permission::Read,
+                Permission::Insert,
+                Permission::Update,
+                Permission::Delete,
+            ],
+        );
+    }
 
     let app = create_app_with_access(container_permissions);
 
-    let container_info = run(&app, move |client, context| {
+    let names: Vec<_> = names.iter().map(|name| name.to_string()).collect();
+    let container_infos = run(&app, move |client, context| {
         context.get_access_info(client).then(move |res| {
             let mut access_info = unwrap!(res);
-            Ok(unwrap!(access_info.remove("_videos")).0)
+            Ok(
+                names
+                    .iter()
+                    .map(|name| unwrap!(access_info.remove(name)).0)
+                    .collect::<Vec<_>>(),
+            )
         })
     });
-    let container_info = container_info.into_repr_c();
+    let container_infos = container_infos
+        .into_iter()
+        .map(|info| info.into_repr_c())
+        .collect();
 
-    (app, container_info)
+    (app, container_infos)
 }
 
 // Test the basics of NFS.
@@ -117,6 +138,366 @@ fn basics() {
     }
 }
 
+// Test listing files in a directory.
+// 1. Insert two empty files into a container.
+// 2. Delete one of them.
+// 3. List the container, only the remaining file should be returned,
+//    together with its version and metadata.
+#[test]
+fn list_files() {
+    let (app, container_info) = setup();
+
+    let ffi_file_name0 = unwrap!(CString::new("file0.txt"));
+    let ffi_file_name1 = unwrap!(CString::new("file1.txt"));
+
+    for ffi_file_name in &[&ffi_file_name0, &ffi_file_name1] {
+        let ffi_file = NativeFile::new(b"metadata".to_vec()).into_repr_c();
+        unsafe {
+            unwrap!(call_0(|ud, cb| {
+                dir_insert_file(
+                    &app,
+                    &container_info,
+                    ffi_file_name.as_ptr(),
+                    &ffi_file,
+                    ud,
+                    cb,
+                )
+            }))
+        }
+    }
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| {
+            dir_delete_file(&app, &container_info, ffi_file_name0.as_ptr(), 1, ud, cb)
+        }))
+    }
+
+    let entries: Vec<NativeFileEntry> =
+        unsafe { unwrap!(call_vec(|ud, cb| dir_list_files(&app, &container_info, ud, cb))) };
+
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name, "file1.txt");
+    assert_eq!(entries[0].version, 0);
+    assert_eq!(entries[0].file.user_metadata(), &b"metadata"[..]);
+}
+
+// Test the trash of a directory.
+// 1. Insert a file and soft-delete it.
+// 2. List the trash, the file should be there.
+// 3. Restore the file, it should be back in the container.
+// 4. Soft-delete the file again and empty the trash.
+#[test]
+fn trash() {
+    let (app, container_info) = setup();
+
+    let ffi_file_name = unwrap!(CString::new("file0.txt"));
+    let ffi_file = NativeFile::new(b"metadata".to_vec()).into_repr_c();
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| {
+            dir_insert_file(
+                &app,
+                &container_info,
+                ffi_file_name.as_ptr(),
+                &ffi_file,
+                ud,
+                cb,
+            )
+        }));
+        unwrap!(call_0(|ud, cb| {
+            dir_soft_delete_file(&app, &container_info, ffi_file_name.as_ptr(), 0, ud, cb)
+        }));
+    }
+
+    let entries: Vec<NativeTrashEntry> =
+        unsafe { unwrap!(call_vec(|ud, cb| dir_list_trash(&app, &container_info, ud, cb))) };
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name, "file0.txt");
+    assert_eq!(entries[0].file.user_metadata(), &b"metadata"[..]);
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| {
+            dir_restore_from_trash(&app, &container_info, ffi_file_name.as_ptr(), ud, cb)
+        }))
+    }
+
+    let (file, version): (NativeFile, u64) = unsafe {
+        unwrap!(call_2(|ud, cb| {
+            dir_fetch_file(&app, &container_info, ffi_file_name.as_ptr(), ud, cb)
+        }))
+    };
+    assert_eq!(file.user_metadata(), &b"metadata"[..]);
+    assert_eq!(version, 2);
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| {
+            dir_soft_delete_file(&app, &container_info, ffi_file_name.as_ptr(), 3, ud, cb)
+        }));
+        unwrap!(call_0(
+            |ud, cb| dir_empty_trash(&app, &container_info, ud, cb),
+        ));
+    }
+
+    let entries: Vec<NativeTrashEntry> =
+        unsafe { unwrap!(call_vec(|ud, cb| dir_list_trash(&app, &container_info, ud, cb))) };
+    assert!(entries.is_empty());
+}
+
+// Test the history of a file.
+// 1. Insert a file, then replace it keeping the replaced version.
+// 2. The history should contain the original file.
+// 3. Restore the original file, it should become the current version.
+// 4. The history should now contain both versions, oldest first.
+#[test]
+fn file_history() {
+    let (app, container_info) = setup();
+
+    let ffi_file_name = unwrap!(CString::new("file0.txt"));
+    let ffi_file0 = NativeFile::new(b"v0".to_vec()).into_repr_c();
+    let ffi_file1 = NativeFile::new(b"v1".to_vec()).into_repr_c();
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| {
+            dir_insert_file(
+                &app,
+                &container_info,
+                ffi_file_name.as_ptr(),
+                &ffi_file0,
+                ud,
+                cb,
+            )
+        }));
+        unwrap!(call_0(|ud, cb| {
+            dir_update_file_versioned(
+                &app,
+                &container_info,
+                ffi_file_name.as_ptr(),
+                &ffi_file1,
+                0,
+                10,
+                ud,
+                cb,
+            )
+        }));
+    }
+
+    let history: Vec<NativeFileVersion> = unsafe {
+        unwrap!(call_vec(|ud, cb| {
+            dir_file_history(&app, &container_info, ffi_file_name.as_ptr(), ud, cb)
+        }))
+    };
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].version, 0);
+    assert_eq!(history[0].file.user_metadata(), &b"v0"[..]);
+
+    // Restoring a version which isn't in the history fails.
+    let res = unsafe {
+        call_0(|ud, cb| {
+            dir_restore_file(&app, &container_info, ffi_file_name.as_ptr(), 5, 10, ud, cb)
+        })
+    };
+    match res {
+        Err(code) if code == AppError::from(NfsError::VersionNotFound).error_code() => (),
+        Err(x) => panic!("Unexpected: {:?}", x),
+        Ok(()) => panic!("Unexpected success"),
+    }
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| {
+            dir_restore_file(&app, &container_info, ffi_file_name.as_ptr(), 0, 10, ud, cb)
+        }))
+    }
+
+    let (file, version): (NativeFile, u64) = unsafe {
+        unwrap!(call_2(|ud, cb| {
+            dir_fetch_file(&app, &container_info, ffi_file_name.as_ptr(), ud, cb)
+        }))
+    };
+    assert_eq!(file.user_metadata(), &b"v0"[..]);
+    assert_eq!(version, 2);
+
+    let history: Vec<NativeFileVersion> = unsafe {
+        unwrap!(call_vec(|ud, cb| {
+            dir_file_history(&app, &container_info, ffi_file_name.as_ptr(), ud, cb)
+        }))
+    };
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].version, 0);
+    assert_eq!(history[0].file.user_metadata(), &b"v0"[..]);
+    assert_eq!(history[1].version, 1);
+    assert_eq!(history[1].file.user_metadata(), &b"v1"[..]);
+}
+
+// Test renaming and moving files.
+// 1. Insert a file and rename it, it should be found under the new name only.
+// 2. Move it into another container under yet another name.
+// 3. It should be found there only.
+#[test]
+fn rename_and_move_file() {
+    let (app, container_infos) = setup_with_containers(&["_videos", "_music"]);
+    let container_info = &container_infos[0];
+    let other_container_info = &container_infos[1];
+
+    let ffi_file_name0 = unwrap!(CString::new("file0.txt"));
+    let ffi_file_name1 = unwrap!(CString::new("file1.txt"));
+    let ffi_file_name2 = unwrap!(CString::new("file2.txt"));
+    let ffi_file = NativeFile::new(b"metadata".to_vec()).into_repr_c();
+
+    let new_version: u64 = unsafe {
+        unwrap!(call_0(|ud, cb| {
+            dir_insert_file(
+                &app,
+                container_info,
+                ffi_file_name0.as_ptr(),
+                &ffi_file,
+                ud,
+                cb,
+            )
+        }));
+        unwrap!(call_1(|ud, cb| {
+            dir_rename_file(
+                &app,
+                container_info,
+                ffi_file_name0.as_ptr(),
+                ffi_file_name1.as_ptr(),
+                0,
+                ud,
+                cb,
+            )
+        }))
+    };
+
+    let (file, version): (NativeFile, u64) = unsafe {
+        unwrap!(call_2(|ud, cb| {
+            dir_fetch_file(&app, container_info, ffi_file_name1.as_ptr(), ud, cb)
+        }))
+    };
+    assert_eq!(file.user_metadata(), &b"metadata"[..]);
+    assert_eq!(version, new_version);
+    assert_file_not_found(&app, container_info, &ffi_file_name0);
+
+    let new_version: u64 = unsafe {
+        unwrap!(call_1(|ud, cb| {
+            dir_move_file(
+                &app,
+                container_info,
+                ffi_file_name1.as_ptr(),
+                other_container_info,
+                ffi_file_name2.as_ptr(),
+                ud,
+                cb,
+            )
+        }))
+    };
+
+    let (file, version): (NativeFile, u64) = unsafe {
+        unwrap!(call_2(|ud, cb| {
+            dir_fetch_file(&app, other_container_info, ffi_file_name2.as_ptr(), ud, cb)
+        }))
+    };
+    assert_eq!(file.user_metadata(), &b"metadata"[..]);
+    assert_eq!(version, new_version);
+    assert_file_not_found(&app, container_info, &ffi_file_name1);
+
+    fn assert_file_not_found(app: &App, container_info: &MDataInfo, file_name: &CString) {
+        let res: Result<(NativeFile, u64), i32> = unsafe {
+            call_2(|ud, cb| {
+                dir_fetch_file(app, container_info, file_name.as_ptr(), ud, cb)
+            })
+        };
+        match res {
+            Err(code) if code == AppError::from(NfsError::FileNotFound).error_code() => (),
+            Err(x) => panic!("Unexpected: {:?}", x),
+            Ok(_) => panic!("Unexpected success"),
+        }
+    }
+}
+
+// Test the usage and quota of a directory.
+// 1. The usage isn't tracked until a quota is set.
+// 2. Set a quota of one file, the usage should be empty.
+// 3. Insert a file, the usage should account for it.
+// 4. Inserting another file should exceed the quota.
+#[test]
+fn usage_and_quota() {
+    let (app, container_info) = setup();
+
+    unsafe { unwrap!(call_0(|ud, cb| app_set_usage_tracking(&app, true, ud, cb))) }
+
+    let usage: NativeUsage =
+        unsafe { unwrap!(call_1(|ud, cb| dir_usage(&app, &container_info, ud, cb))) };
+    assert_eq!(usage.0, None);
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| {
+            dir_set_quota(&app, &container_info, true, 1, false, 0, ud, cb)
+        }))
+    }
+
+    let usage: NativeUsage =
+        unsafe { unwrap!(call_1(|ud, cb| dir_usage(&app, &container_info, ud, cb))) };
+    let quota = Quota {
+        max_files: Some(1),
+        max_size: None,
+    };
+    assert_eq!(
+        usage.0,
+        Some(Usage {
+            files: 0,
+            size: 0,
+            quota,
+        })
+    );
+
+    let ffi_file_name0 = unwrap!(CString::new("file0.txt"));
+    let ffi_file_name1 = unwrap!(CString::new("file1.txt"));
+    let file = NativeFile::new(Vec::new());
+    let size = file.size();
+    let ffi_file = file.into_repr_c();
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| {
+            dir_insert_file(
+                &app,
+                &container_info,
+                ffi_file_name0.as_ptr(),
+                &ffi_file,
+                ud,
+                cb,
+            )
+        }))
+    }
+
+    let usage: NativeUsage =
+        unsafe { unwrap!(call_1(|ud, cb| dir_usage(&app, &container_info, ud, cb))) };
+    assert_eq!(
+        usage.0,
+        Some(Usage {
+            files: 1,
+            size,
+            quota,
+        })
+    );
+
+    let res = unsafe {
+        call_0(|ud, cb| {
+            dir_insert_file(
+                &app,
+                &container_info,
+                ffi_file_name1.as_ptr(),
+                &ffi_file,
+                ud,
+                cb,
+            )
+        })
+    };
+    match res {
+        Err(code) if code == AppError::from(NfsError::QuotaExceeded).error_code() => (),
+        Err(x) => panic!("Unexpected: {:?}", x),
+        Ok(()) => panic!("Unexpected success"),
+    }
+}
+
 // Test NFS functions for writing and updating file contents.
 // 1. Create an empty file, open it for writing, write contents.
 // 2. Insert file into a container.
@@ -828,6 +1209,70 @@ fn file_write_chunks() {
     assert_eq!(retrieved_content, vec![0u8; 2 * GOAL_SIZE]);
 }
 
+// Test that open files stay usable after cancelling pending requests.
+// 1. Open a new file for writing and write to it.
+// 2. Cancel the pending requests of the app, then those of the file.
+// 3. Write more contents and close the file, it should succeed.
+// 4. Open the file for reading and cancel again.
+// 5. Read the file contents back, they should be complete.
+#[test]
+fn cancel_then_reuse_file() {
+    let (app, container_info) = setup();
+
+    let ffi_file = NativeFile::new(Vec::new()).into_repr_c();
+    let content0 = b"hello ";
+    let content1 = b"world";
+
+    let write_h = unsafe {
+        unwrap!(call_1(|ud, cb| {
+            file_open(
+                &app,
+                &container_info,
+                &ffi_file,
+                OPEN_MODE_OVERWRITE,
+                ud,
+                cb,
+            )
+        }))
+    };
+
+    let written_file: NativeFile = unsafe {
+        unwrap!(call_0(|ud, cb| {
+            file_write(&app, write_h, content0.as_ptr(), content0.len(), ud, cb)
+        }));
+        unwrap!(call_0(|ud, cb| app_cancel_pending_requests(&app, ud, cb)));
+        unwrap!(call_0(|ud, cb| file_cancel(&app, write_h, ud, cb)));
+        unwrap!(call_0(|ud, cb| {
+            file_write(&app, write_h, content1.as_ptr(), content1.len(), ud, cb)
+        }));
+        unwrap!(call_1(|ud, cb| file_close(&app, write_h, ud, cb)))
+    };
+
+    let read_h = unsafe {
+        unwrap!(call_1(|ud, cb| {
+            file_open(
+                &app,
+                &container_info,
+                &written_file.into_repr_c(),
+                OPEN_MODE_READ,
+                ud,
+                cb,
+            )
+        }))
+    };
+
+    let retrieved_content = unsafe {
+        unwrap!(call_0(|ud, cb| app_cancel_pending_requests(&app, ud, cb)));
+        unwrap!(call_0(|ud, cb| file_cancel(&app, read_h, ud, cb)));
+        unwrap!(call_vec_u8(|ud, cb| {
+            file_read(&app, read_h, 0, FILE_READ_TO_END, ud, cb)
+        }))
+    };
+    assert_eq!(retrieved_content, b"hello world");
+
+    let _: NativeFile = unsafe { unwrap!(call_1(|ud, cb| file_close(&app, read_h, ud, cb))) };
+}
+
 // Helper function for writing to a file in chunks.
 fn write_chunks(
     app: &App,
@@ -871,3 +1316,93 @@ fn write_chunks(
         unwrap!(call_1(|ud, cb| file_close(app, write_h, ud, cb)))
     }
 }
+
+// Native copy of `FileEntry`, used to retrieve the results of `dir_list_files`.
+struct NativeFileEntry {
+    name: String,
+    version: u64,
+    file: NativeFile,
+}
+
+impl ReprC for NativeFileEntry {
+    type C = *const FileEntry;
+    type Error = AppError;
+
+    unsafe fn clone_from_repr_c(repr_c: Self::C) -> Result<Self, Self::Error> {
+        Ok(NativeFileEntry {
+            name: from_c_str((*repr_c).name)?,
+            version: (*repr_c).version,
+            file: NativeFile::clone_from_repr_c(&(*repr_c).file)?,
+        })
+    }
+}
+
+// Native copy of `TrashEntry`, used to retrieve the results of `dir_list_trash`.
+struct NativeTrashEntry {
+    name: String,
+    file: NativeFile,
+}
+
+impl ReprC for NativeTrashEntry {
+    type C = *const TrashEntry;
+    type Error = AppError;
+
+    unsafe fn clone_from_repr_c(repr_c: Self::C) -> Result<Self, Self::Error> {
+        Ok(NativeTrashEntry {
+            name: from_c_str((*repr_c).name)?,
+            file: NativeFile::clone_from_repr_c(&(*repr_c).file)?,
+        })
+    }
+}
+
+// Native copy of `FileVersion`, used to retrieve the results of
+// `dir_file_history`.
+struct NativeFileVersion {
+    version: u64,
+    file: NativeFile,
+}
+
+impl ReprC for NativeFileVersion {
+    type C = *const FileVersion;
+    type Error = AppError;
+
+    unsafe fn clone_from_repr_c(repr_c: Self::C) -> Result<Self, Self::Error> {
+        Ok(NativeFileVersion {
+            version: (*repr_c).version,
+            file: NativeFile::clone_from_repr_c(&(*repr_c).file)?,
+        })
+    }
+}
+
+// Native copy of the usage passed to the callback of `dir_usage`, which is
+// null if the directory doesn't track its usage.
+struct NativeUsage(Option<Usage>);
+
+impl ReprC for NativeUsage {
+    type C = *const FfiUsage;
+    type Error = AppError;
+
+    unsafe fn clone_from_repr_c(repr_c: Self::C) -> Result<Self, Self::Error> {
+        if repr_c.is_null() {
+            return Ok(NativeUsage(None));
+        }
+
+        let usage = &*repr_c;
+        Ok(NativeUsage(Some(Usage {
+            files: usage.files,
+            size: usage.size,
+            quota: Quota {
+                max_files: if usage.has_max_files {
+                    Some(usage.max_files)
+                } else {
+                    None
+                },
+                max_size: if usage.has_max_size {
+                    Some(usage.max_size)
+                } else {
+                    None
+                },
+            },
+        })))
+    }
+}
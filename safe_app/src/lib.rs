@@ -95,18 +95,19 @@ use futures::stream::Stream;
 use futures::sync::mpsc as futures_mpsc;
 use maidsafe_utilities::serialisation::deserialise;
 use maidsafe_utilities::thread::{self, Joiner};
-use safe_core::{Client, ClientKeys, CoreMsg, CoreMsgTx, FutureExt, NetworkEvent, NetworkTx,
-                event_loop, utils};
+use safe_core::{CancelHandle, Client, ClientKeys, CoreMsg, CoreMsgTx, FutureExt, NetworkEvent,
+                NetworkTx, event_loop, utils};
 #[cfg(feature = "use-mock-routing")]
 use safe_core::MockRouting as Routing;
 use safe_core::crypto::shared_secretbox;
 use safe_core::ipc::{AccessContInfo, AppKeys, AuthGranted, BootstrapConfig};
 use safe_core::ipc::resp::{AccessContainerEntry, access_container_enc_key};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::Mutex;
 use std::sync::mpsc as std_mpsc;
+use std::time::Duration;
 #[cfg(feature = "testing")]
 pub use test_utils::{test_create_app, test_create_app_with_access};
 use tokio_core::reactor::{Core, Handle};
@@ -291,14 +292,19 @@ impl App {
         })
     }
 
-    /// Send a message to app's event loop
+    /// Send a message to app's event loop. Requests the message makes are
+    /// aborted by `AppContext::cancel_requests` and time out after the
+    /// duration set with `AppContext::set_request_timeout`.
     pub fn send<F>(&self, f: F) -> Result<(), AppError>
     where
         F: FnOnce(&Client<AppContext>, &AppContext) -> Option<Box<Future<Item = (), Error = ()>>>
             + Send
             + 'static,
     {
-        let msg = CoreMsg::new(f);
+        let msg = CoreMsg::new(move |client, context| {
+            let client = context.request_client(client);
+            f(&client, context)
+        });
         let core_tx = unwrap!(self.core_tx.lock());
         core_tx.unbounded_send(msg).map_err(AppError::from)
     }
@@ -330,14 +336,23 @@ pub enum AppContext {
     Registered(Rc<Registered>),
 }
 
+// Cancel handle and timeout of the requests made through `App::send`.
+#[derive(Default)]
+struct Requests {
+    cancel: CancelHandle,
+    timeout: Cell<Option<Duration>>,
+}
+
 #[allow(missing_docs)]
 pub struct Unregistered {
     object_cache: ObjectCache,
+    requests: Requests,
 }
 
 #[allow(missing_docs)]
 pub struct Registered {
     object_cache: ObjectCache,
+    requests: Requests,
     app_id: String,
     sym_enc_key: shared_secretbox::Key,
     access_container_info: AccessContInfo,
@@ -346,7 +361,10 @@ pub struct Registered {
 
 impl AppContext {
     fn unregistered() -> Self {
-        AppContext::Unregistered(Rc::new(Unregistered { object_cache: ObjectCache::new() }))
+        AppContext::Unregistered(Rc::new(Unregistered {
+            object_cache: ObjectCache::new(),
+            requests: Requests::default(),
+        }))
     }
 
     fn registered(
@@ -356,6 +374,7 @@ impl AppContext {
     ) -> Self {
         AppContext::Registered(Rc::new(Registered {
             object_cache: ObjectCache::new(),
+            requests: Requests::default(),
             app_id: app_id,
             sym_enc_key: sym_enc_key,
             access_container_info: access_container_info,
@@ -371,6 +390,40 @@ impl AppContext {
         }
     }
 
+    /// Abort all requests made through `App::send` which are still in
+    /// progress, including the polls of active `MutableData` watchers. They
+    /// fail with `CoreError::OperationAborted`. Requests made afterwards,
+    /// even through objects such as open files, are not affected.
+    pub fn cancel_requests(&self) {
+        self.requests().cancel.cancel();
+    }
+
+    /// Set the timeout of the requests made through `App::send` from now on.
+    /// Objects such as open files keep the timeout set when they were created.
+    /// `None` restores the default timeout of the client.
+    pub fn set_request_timeout(&self, timeout: Option<Duration>) {
+        self.requests().timeout.set(timeout);
+    }
+
+    // Returns a `Client` sharing the connection with `client`, whose requests
+    // are aborted by `cancel_requests` and time out as set with
+    // `set_request_timeout`.
+    fn request_client(&self, client: &Client<AppContext>) -> Client<AppContext> {
+        let requests = self.requests();
+        let client = client.with_cancel_handle(&requests.cancel);
+        match requests.timeout.get() {
+            Some(timeout) => client.with_timeout(timeout),
+            None => client,
+        }
+    }
+
+    fn requests(&self) -> &Requests {
+        match *self {
+            AppContext::Unregistered(ref context) => &context.requests,
+            AppContext::Registered(ref context) => &context.requests,
+        }
+    }
+
     /// Symmetric encryption/decryption key.
     pub fn sym_enc_key(&self) -> Result<&shared_secretbox::Key, AppError> {
         Ok(&self.as_registered()?.sym_enc_key)
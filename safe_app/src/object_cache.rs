@@ -26,6 +26,7 @@ use routing::{EntryAction, PermissionSet, User, Value};
 use rust_sodium::crypto::{box_, sign};
 use safe_core::SelfEncryptionStorage;
 use safe_core::crypto::{shared_box, shared_sign};
+use futures::sync::oneshot;
 use self_encryption::{SelfEncryptor, SequentialEncryptor};
 use std::cell::{Cell, RefCell, RefMut};
 use std::collections::{BTreeMap, HashMap};
@@ -69,6 +70,8 @@ pub type SignPubKeyHandle = ObjectHandle;
 pub type SignSecKeyHandle = ObjectHandle;
 /// Disambiguating `ObjectHandle`
 pub type FileContextHandle = ObjectHandle;
+/// Disambiguating `ObjectHandle`
+pub type WatcherHandle = ObjectHandle;
 
 /// Contains session object cache
 pub struct ObjectCache {
@@ -84,6 +87,7 @@ pub struct ObjectCache {
     pub_sign_key: Store<sign::PublicKey>,
     sec_sign_key: Store<shared_sign::SecretKey>,
     file: Store<FileContext>,
+    watcher: Store<oneshot::Sender<()>>,
 }
 
 impl ObjectCache {
@@ -102,6 +106,7 @@ impl ObjectCache {
             pub_sign_key: Store::new(),
             sec_sign_key: Store::new(),
             file: Store::new(),
+            watcher: Store::new(),
         }
     }
 
@@ -119,6 +124,7 @@ impl ObjectCache {
         self.pub_sign_key.clear();
         self.sec_sign_key.clear();
         self.file.clear();
+        self.watcher.clear();
     }
 }
 
@@ -234,6 +240,13 @@ impl_cache!(file,
             get_file,
             insert_file,
             remove_file);
+impl_cache!(watcher,
+            oneshot::Sender<()>,
+            WatcherHandle,
+            InvalidWatcherHandle,
+            get_watcher,
+            insert_watcher,
+            remove_watcher);
 
 impl Default for ObjectCache {
     fn default() -> Self {
@@ -90,6 +90,16 @@ mod codes {
     pub const ERR_FILE_EXISTS: i32 = -300;
     pub const ERR_FILE_NOT_FOUND: i32 = -301;
     pub const ERR_INVALID_RANGE: i32 = -302;
+    pub const ERR_DIRECTORY_EXISTS: i32 = -303;
+    pub const ERR_DIRECTORY_NOT_FOUND: i32 = -304;
+    pub const ERR_DIRECTORY_NOT_EMPTY: i32 = -305;
+    pub const ERR_VERSION_NOT_FOUND: i32 = -306;
+    pub const ERR_DIGEST_MISMATCH: i32 = -307;
+    pub const ERR_LINK_CYCLE: i32 = -308;
+    pub const ERR_QUOTA_EXCEEDED: i32 = -309;
+    pub const ERR_CONFLICT: i32 = -310;
+    pub const ERR_DIGEST_UNKNOWN: i32 = -311;
+    pub const ERR_INVALID_PATH: i32 = -312;
 
     // Authenticator errors
     pub const ERR_IO_ERROR: i32 = -1013;
@@ -253,6 +263,16 @@ impl ErrorCode for AuthError {
                     NfsError::InvalidRange => ERR_INVALID_RANGE,
                     NfsError::EncodeDecodeError(_) => ERR_ENCODE_DECODE_ERROR,
                     NfsError::SelfEncryption(_) => ERR_SELF_ENCRYPTION,
+                    NfsError::DirectoryExists => ERR_DIRECTORY_EXISTS,
+                    NfsError::DirectoryNotFound => ERR_DIRECTORY_NOT_FOUND,
+                    NfsError::DirectoryNotEmpty => ERR_DIRECTORY_NOT_EMPTY,
+                    NfsError::VersionNotFound => ERR_VERSION_NOT_FOUND,
+                    NfsError::DigestMismatch => ERR_DIGEST_MISMATCH,
+                    NfsError::LinkCycle => ERR_LINK_CYCLE,
+                    NfsError::QuotaExceeded => ERR_QUOTA_EXCEEDED,
+                    NfsError::Conflict(..) => ERR_CONFLICT,
+                    NfsError::DigestUnknown => ERR_DIGEST_UNKNOWN,
+                    NfsError::InvalidPath => ERR_INVALID_PATH,
                     NfsError::Unexpected(_) => ERR_UNEXPECTED,
                 }
             }
@@ -0,0 +1,446 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Two-way sync between a local directory and a SAFE directory.
+//!
+//! Run against the mock vault with:
+//! `cargo run --example nfs_sync --features use-mock-routing -- <args>`
+
+// For explanation of lint checks, run `rustc -W help` or see
+// https://github.
+// com/maidsafe/QA/blob/master/Documentation/Rust%20Lint%20Checks.md
+#![forbid(bad_style, exceeding_bitshifts, mutable_transmutes, no_mangle_const_items,
+          unknown_crate_types, warnings)]
+#![deny(deprecated, improper_ctypes, missing_docs,
+        non_shorthand_field_patterns, overflowing_literals, plugin_as_library,
+        private_no_mangle_fns, private_no_mangle_statics, stable_features, unconditional_recursion,
+        unknown_lints, unsafe_code, unused, unused_allocation, unused_attributes,
+        unused_comparisons, unused_features, unused_parens, while_true)]
+#![warn(trivial_casts, trivial_numeric_casts, unused_extern_crates, unused_import_braces,
+        unused_qualifications, unused_results)]
+#![allow(box_pointers, missing_copy_implementations, missing_debug_implementations,
+         variant_size_differences)]
+
+#![cfg_attr(feature="cargo-clippy", deny(clippy, clippy_pedantic))]
+#![cfg_attr(feature="cargo-clippy", allow(use_debug, print_stdout, missing_docs_in_private_items))]
+
+extern crate chrono;
+extern crate docopt;
+extern crate futures;
+extern crate maidsafe_utilities;
+extern crate rustc_serialize;
+#[macro_use]
+extern crate safe_core;
+extern crate tiny_keccak;
+extern crate tokio_core;
+#[macro_use]
+extern crate unwrap;
+
+use chrono::{DateTime, Utc};
+use docopt::Docopt;
+use futures::Future;
+use futures::stream::{self, Stream};
+use futures::sync::mpsc;
+use maidsafe_utilities::serialisation::{deserialise, serialise};
+use safe_core::{Client, CoreMsg, DIR_TAG, FutureExt, MDataInfo, event_loop};
+use safe_core::nfs::{File, Mode, NfsError, NfsFuture, create_dir, file_helper};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use tiny_keccak::Keccak;
+use tokio_core::reactor::Core;
+
+// Size of the buffer in which local files are read when hashing them.
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+static USAGE: &'static str = "
+Usage:
+  nfs_sync [options] <local-dir> <dir-info-file>
+
+Synchronises the files in <local-dir> with a SAFE directory in both directions.
+The SAFE directory is identified by the info stored in <dir-info-file>. If the
+file doesn't exist, a new directory is created and its info is saved there.
+A file present on one side only is copied to the other one. A file present on
+both sides but differing in size or content hash is overwritten by the more
+recently modified one. Subdirectories are not synchronised: they are reported
+and skipped. Deletions are not propagated either: a file deleted on one side is
+copied back from the other one on the next run.
+
+Options:
+  --locator <locator>     Account locator [default: nfs_sync].
+  --password <password>   Account password [default: nfs_sync].
+  --invite <invite>       Create a new account using the given invite.
+  --dry-run               Only print what would be done.
+  -h, --help              Display this help message and exit.
+";
+
+#[derive(Debug, RustcDecodable)]
+struct Args {
+    arg_local_dir: String,
+    arg_dir_info_file: String,
+    flag_locator: String,
+    flag_password: String,
+    flag_invite: Option<String>,
+    flag_dry_run: bool,
+    flag_help: bool,
+}
+
+struct LocalFile {
+    path: PathBuf,
+    size: u64,
+    modified: DateTime<Utc>,
+}
+
+enum Action {
+    Upload {
+        name: String,
+        path: PathBuf,
+        remote: Option<(u64, File)>,
+    },
+    Download {
+        name: String,
+        path: PathBuf,
+        file: File,
+    },
+}
+
+fn main() {
+    unwrap!(maidsafe_utilities::log::init(true));
+
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|docopt| docopt.decode())
+        .unwrap_or_else(|error| error.exit());
+
+    let local_dir = PathBuf::from(&args.arg_local_dir);
+    let dir_info_path = PathBuf::from(&args.arg_dir_info_file);
+    let dry_run = args.flag_dry_run;
+
+    let local = unwrap!(list_local(&local_dir));
+    let dir = if dir_info_path.exists() {
+        Some(unwrap!(deserialise::<MDataInfo>(
+            &unwrap!(read_local(&dir_info_path)),
+        )))
+    } else {
+        None
+    };
+
+    let el = unwrap!(Core::new());
+    let el_h = el.handle();
+
+    let (core_tx, core_rx) = mpsc::unbounded();
+    let (net_tx, _net_rx) = mpsc::unbounded();
+
+    let client = if let Some(invitation) = args.flag_invite {
+        unwrap!(Client::registered(
+            &args.flag_locator,
+            &args.flag_password,
+            &invitation,
+            el_h,
+            core_tx.clone(),
+            net_tx,
+        ))
+    } else {
+        unwrap!(Client::login(
+            &args.flag_locator,
+            &args.flag_password,
+            el_h,
+            core_tx.clone(),
+            net_tx,
+        ))
+    };
+    let core_tx_clone = core_tx.clone();
+
+    unwrap!(core_tx.unbounded_send(CoreMsg::new(move |client, _| {
+        let client = client.clone();
+        let client2 = client.clone();
+
+        let remote_fut = match dir {
+            Some(ref dir) => list_remote(&client, dir),
+            None => ok!(BTreeMap::new()),
+        };
+
+        remote_fut
+            .and_then(move |remote| {
+                let actions = plan(&local_dir, local, remote);
+                for action in &actions {
+                    match *action {
+                        Action::Upload { ref name, .. } => println!("upload   {}", name),
+                        Action::Download { ref name, .. } => println!("download {}", name),
+                    }
+                }
+                if actions.is_empty() {
+                    println!("Already in sync");
+                }
+                if dry_run {
+                    return ok!(());
+                }
+
+                open_dir(&client, dir, dir_info_path)
+                    .and_then(move |dir| {
+                        stream::iter_ok(actions).for_each(move |action| {
+                            execute(&client2, &dir, action)
+                        })
+                    })
+                    .into_box()
+            })
+            .then(move |res| -> Result<(), ()> {
+                if let Err(error) = res {
+                    println!("Error: {:?}", error);
+                }
+                unwrap!(core_tx_clone.unbounded_send(CoreMsg::build_terminator()));
+                Ok(())
+            })
+            .into_box()
+            .into()
+    })));
+
+    event_loop::run(el, &client, &(), core_rx);
+}
+
+// List the regular files in the local directory. Subdirectories aren't
+// synchronised, so they are only reported.
+fn list_local(dir: &Path) -> io::Result<BTreeMap<String, LocalFile>> {
+    let mut files = BTreeMap::new();
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            println!("Skipping {:?}: subdirectories are not synchronised", entry.path());
+            continue;
+        }
+        if !metadata.is_file() {
+            continue;
+        }
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(name) => {
+                println!("Skipping {:?}: the name is not valid UTF-8", name);
+                continue;
+            }
+        };
+
+        let _ = files.insert(
+            name,
+            LocalFile {
+                path: entry.path(),
+                size: metadata.len(),
+                modified: DateTime::from(metadata.modified()?),
+            },
+        );
+    }
+
+    Ok(files)
+}
+
+// List the files in the SAFE directory, skipping the ones which can't be
+// stored locally under the same name.
+fn list_remote(
+    client: &Client<()>,
+    dir: &MDataInfo,
+) -> Box<NfsFuture<BTreeMap<String, (u64, File)>>> {
+    file_helper::list(client, dir)
+        .map(|files| {
+            files
+                .into_iter()
+                .filter(|&(ref name, _, _)| {
+                    let valid = is_valid_name(name);
+                    if !valid {
+                        println!("Skipping {:?}: not a valid local file name", name);
+                    }
+                    valid
+                })
+                .map(|(name, version, file)| (name, (version, file)))
+                .collect()
+        })
+        .into_box()
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains('/') &&
+        !name.contains('\\')
+}
+
+// Decide what needs to be copied in which direction.
+fn plan(
+    local_dir: &Path,
+    local: BTreeMap<String, LocalFile>,
+    mut remote: BTreeMap<String, (u64, File)>,
+) -> Vec<Action> {
+    let mut actions = Vec::new();
+
+    for (name, local_file) in local {
+        match remote.remove(&name) {
+            None => {
+                actions.push(Action::Upload {
+                    name,
+                    path: local_file.path,
+                    remote: None,
+                })
+            }
+            Some((version, file)) => {
+                if is_in_sync(&local_file, &file) {
+                    continue;
+                }
+                if local_file.modified > *file.modified_time() {
+                    actions.push(Action::Upload {
+                        name,
+                        path: local_file.path,
+                        remote: Some((version, file)),
+                    })
+                } else {
+                    actions.push(Action::Download {
+                        name,
+                        path: local_file.path,
+                        file,
+                    })
+                }
+            }
+        }
+    }
+
+    for (name, (_, file)) in remote {
+        let path = local_dir.join(&name);
+        actions.push(Action::Download { name, path, file });
+    }
+
+    actions
+}
+
+fn is_in_sync(local: &LocalFile, remote: &File) -> bool {
+    if local.size != remote.size() {
+        return false;
+    }
+
+    match remote.digest() {
+        Some(digest) => {
+            match hash_local(&local.path) {
+                Ok(local_digest) => local_digest == *digest,
+                Err(_) => false,
+            }
+        }
+        // Files written before digests were introduced can only be compared
+        // by their modification times.
+        None => local.modified == *remote.modified_time(),
+    }
+}
+
+// Open the SAFE directory, creating it if it doesn't exist yet.
+fn open_dir(
+    client: &Client<()>,
+    dir: Option<MDataInfo>,
+    dir_info_path: PathBuf,
+) -> Box<NfsFuture<MDataInfo>> {
+    if let Some(dir) = dir {
+        return ok!(dir);
+    }
+
+    let dir = fry!(MDataInfo::random_private(DIR_TAG));
+    let encoded = fry!(serialise(&dir));
+
+    create_dir(client, &dir, btree_map![], btree_map![])
+        .and_then(move |_| {
+            write_local(&dir_info_path, &encoded)?;
+            println!("Created directory, its info saved to {:?}", dir_info_path);
+            Ok(dir)
+        })
+        .into_box()
+}
+
+fn execute(client: &Client<()>, dir: &MDataInfo, action: Action) -> Box<NfsFuture<()>> {
+    let client2 = client.clone();
+    let dir = dir.clone();
+
+    match action {
+        Action::Upload { name, path, remote } => {
+            let content = match read_local(&path) {
+                Ok(content) => content,
+                Err(error) => return err!(local_error(&path, &error)),
+            };
+            let (file, version) = match remote {
+                Some((version, file)) => (file, Some(version)),
+                None => (File::new(Vec::new()), None),
+            };
+
+            file_helper::write_in(client.clone(), &dir, file, Mode::Overwrite)
+                .and_then(move |writer| {
+                    writer.write(&content).and_then(move |_| writer.close())
+                })
+                .and_then(move |file| {
+                    let fut = match version {
+                        Some(version) => {
+                            file_helper::update(client2, dir, name.clone(), &file, version + 1)
+                        }
+                        None => file_helper::insert(client2, dir, name.clone(), &file),
+                    };
+                    fut.map(move |_| println!("Uploaded {}", name))
+                })
+                .into_box()
+        }
+        Action::Download { name, path, file } => {
+            file_helper::read(client.clone(), &file, dir.enc_key().cloned())
+                .and_then(|reader| {
+                    let size = reader.size();
+                    reader.read(0, size)
+                })
+                .and_then(move |content| {
+                    write_local(&path, &content)?;
+                    println!("Downloaded {}", name);
+                    Ok(())
+                })
+                .into_box()
+        }
+    }
+}
+
+fn read_local(path: &Path) -> io::Result<Vec<u8>> {
+    let mut content = Vec::new();
+    let _ = fs::File::open(path)?.read_to_end(&mut content)?;
+    Ok(content)
+}
+
+// Compute the SHA3-256 digest of the local file without reading it into
+// memory as a whole.
+fn hash_local(path: &Path) -> io::Result<[u8; 32]> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Keccak::new_sha3_256();
+    let mut buffer = vec![0; HASH_BUFFER_SIZE];
+
+    loop {
+        let length = file.read(&mut buffer)?;
+        if length == 0 {
+            break;
+        }
+        hasher.update(&buffer[..length]);
+    }
+
+    let mut digest = [0; 32];
+    hasher.finalize(&mut digest);
+    Ok(digest)
+}
+
+fn write_local(path: &Path, content: &[u8]) -> Result<(), NfsError> {
+    fs::File::create(path)
+        .and_then(|mut file| file.write_all(content))
+        .map_err(|error| local_error(path, &error))
+}
+
+fn local_error(path: &Path, error: &io::Error) -> NfsError {
+    NfsError::Unexpected(format!("{}: {}", path.display(), error))
+}
@@ -0,0 +1,71 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use errors::CoreError;
+use event_loop::CoreFuture;
+use futures::Future;
+use futures::future::{self, Either, Shared};
+use futures::sync::oneshot;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Handle to cancel the requests made through the `Client` returned together
+/// with it by `Client::cancellable`, and through the clones of that `Client`.
+///
+/// Cancelling only aborts the requests in progress. Requests made afterwards
+/// are not affected, so objects holding the `Client` (e.g. file readers and
+/// writers) stay usable.
+#[derive(Clone, Default)]
+pub struct CancelHandle(Rc<RefCell<Option<(oneshot::Sender<()>, CancelSignal)>>>);
+
+impl CancelHandle {
+    /// Cancel the requests in progress. Those in flight or waiting to be sent
+    /// fail with `CoreError::OperationAborted`.
+    pub fn cancel(&self) {
+        let current = self.0.borrow_mut().take();
+        if let Some((tx, _)) = current {
+            let _ = tx.send(());
+        }
+    }
+}
+
+// Resolves once the requests are cancelled.
+pub type CancelSignal = Shared<oneshot::Receiver<()>>;
+
+// Returns the signal of the requests in progress, which resolves once they are
+// cancelled. Each cancellation starts a new signal.
+pub fn signal(handle: &CancelHandle) -> CancelSignal {
+    let mut current = handle.0.borrow_mut();
+    if let Some((_, ref signal)) = *current {
+        return signal.clone();
+    }
+
+    let (tx, rx) = oneshot::channel();
+    let signal = rx.shared();
+    *current = Some((tx, signal.clone()));
+    signal
+}
+
+// Fails with `OperationAborted` once the signal resolves because of a call to
+// `CancelHandle::cancel`. If the handle is dropped without cancelling, the
+// requests are left to complete, so it never resolves.
+pub fn aborted<T: 'static>(signal: CancelSignal) -> Box<CoreFuture<T>> {
+    Box::new(signal.then(|result| match result {
+        Ok(_) => Either::A(future::err(CoreError::OperationAborted)),
+        Err(_) => Either::B(future::empty()),
+    }))
+}
@@ -0,0 +1,408 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use config_handler::{self, ChunkCacheConfig};
+use errors::CoreError;
+use event_loop::CoreFuture;
+use futures::Future;
+use futures_cpupool::{Builder, CpuPool};
+use lru_cache::LruCache;
+use routing::{ImmutableData, XorName};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::UNIX_EPOCH;
+use std::usize;
+use utils::FutureExt;
+
+// Extension of the files chunks are written to before being renamed.
+const TMP_EXTENSION: &'static str = "tmp";
+
+lazy_static! {
+    // Caches currently open in the process, by their directory.
+    static ref OPEN_CACHES: Mutex<HashMap<PathBuf, Weak<Mutex<Inner>>>> =
+        Mutex::new(HashMap::new());
+
+    // Thread doing the file IO of all caches, so it doesn't block the event
+    // loop. Having a single one keeps the operations in the order issued.
+    static ref POOL: CpuPool = Builder::new()
+        .pool_size(1)
+        .name_prefix("chunk-cache-")
+        .create();
+
+    // Cache configured in the config file, opened when first needed.
+    static ref CONFIGURED: Option<ChunkCache> = open_configured();
+}
+
+/// Persistent, size-bounded cache of `ImmutableData` chunks. Each chunk is
+/// stored in its own file named after the hex-encoded name of the chunk, so
+/// the cache survives restarts. Chunks are verified against their names when
+/// loaded and dropped from the cache if corrupted. When the total size of the
+/// chunks exceeds the limit, the least recently used ones are evicted (after
+/// a restart, the least recently written ones).
+///
+/// All clients of the process opening the same directory share one instance,
+/// so the size limit holds for the directory as a whole. The limit of the
+/// instance is the one it was first opened with.
+///
+/// Chunks are read and written on a background thread, in the order the
+/// operations are issued. Only opening the cache is done on the calling thread.
+#[derive(Clone)]
+pub struct ChunkCache {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    dir: PathBuf,
+    max_size: u64,
+    size: u64,
+    // Sizes of the cached chunks, least recently used first.
+    entries: LruCache<XorName, u64>,
+}
+
+impl ChunkCache {
+    /// Open the cache in the given directory, creating the directory if it
+    /// doesn't exist yet. Chunks already present in it are picked up, while
+    /// leftovers of interrupted writes are removed. If the cache is already
+    /// open in the process, the open instance is returned. This blocks while
+    /// the directory is scanned, so it shouldn't be called on the event loop.
+    pub fn open<P: Into<PathBuf>>(dir: P, max_size: u64) -> Result<Self, CoreError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let dir = fs::canonicalize(&dir)?;
+
+        let mut open_caches = unwrap!(OPEN_CACHES.lock());
+        if let Some(inner) = open_caches.get(&dir).and_then(|inner| inner.upgrade()) {
+            return Ok(ChunkCache { inner });
+        }
+
+        let inner = Arc::new(Mutex::new(Inner::open(dir.clone(), max_size)?));
+        let _ = open_caches.insert(dir, Arc::downgrade(&inner));
+        Ok(ChunkCache { inner })
+    }
+
+    /// Total size of the cached chunks, in bytes, once all the operations
+    /// issued before have completed.
+    pub fn size(&self) -> Box<CoreFuture<u64>> {
+        let inner = self.inner.clone();
+        POOL.spawn_fn(move || -> Result<_, CoreError> { Ok(unwrap!(inner.lock()).size) }).into_box()
+    }
+
+    /// Get the chunk from the cache, verifying its content.
+    pub fn get(&self, name: &XorName) -> Box<CoreFuture<Option<ImmutableData>>> {
+        let inner = self.inner.clone();
+        let name = *name;
+        POOL.spawn_fn(move || -> Result<_, CoreError> {
+            Ok(unwrap!(inner.lock()).get(&name))
+        }).into_box()
+    }
+
+    /// Put the chunk into the cache in the background, evicting the least
+    /// recently used chunks if needed. Chunks larger than the whole cache are
+    /// not stored.
+    pub fn put(&self, data: &ImmutableData) {
+        let inner = self.inner.clone();
+        let data = data.clone();
+        POOL.spawn_fn(move || -> Result<(), CoreError> {
+            unwrap!(inner.lock()).put(&data);
+            Ok(())
+        }).forget();
+    }
+}
+
+impl Inner {
+    fn open(dir: PathBuf, max_size: u64) -> Result<Self, CoreError> {
+        let mut found = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().map_or(false, |ext| ext == TMP_EXTENSION) {
+                let _ = fs::remove_file(path);
+                continue;
+            }
+            let name = match entry.file_name().to_str().and_then(decode_name) {
+                Some(name) => name,
+                None => continue,
+            };
+            let metadata = entry.metadata()?;
+            let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+            found.push((modified, name, metadata.len()));
+        }
+        found.sort_by_key(|&(modified, _, _)| modified);
+
+        let mut inner = Inner {
+            dir,
+            max_size,
+            size: 0,
+            entries: LruCache::new(usize::MAX),
+        };
+        for (_, name, size) in found {
+            inner.size += size;
+            let _ = inner.entries.insert(name, size);
+        }
+        inner.evict();
+
+        Ok(inner)
+    }
+
+    fn get(&mut self, name: &XorName) -> Option<ImmutableData> {
+        if self.entries.get_mut(name).is_none() {
+            return None;
+        }
+
+        let data = match self.read(name) {
+            Ok(value) => ImmutableData::new(value),
+            Err(error) => {
+                warn!("Failed to read chunk {:?} from the cache: {:?}", name, error);
+                self.remove(name);
+                return None;
+            }
+        };
+
+        if data.name() != name {
+            warn!("Chunk {:?} in the cache is corrupted.", name);
+            self.remove(name);
+            return None;
+        }
+
+        Some(data)
+    }
+
+    fn put(&mut self, data: &ImmutableData) {
+        let name = *data.name();
+        let size = data.value().len() as u64;
+
+        if size > self.max_size || self.entries.get_mut(&name).is_some() {
+            return;
+        }
+
+        if let Err(error) = self.write(&name, data.value()) {
+            warn!("Failed to write chunk {:?} to the cache: {:?}", name, error);
+            return;
+        }
+
+        self.size += size;
+        let _ = self.entries.insert(name, size);
+        self.evict();
+    }
+
+    fn read(&self, name: &XorName) -> Result<Vec<u8>, CoreError> {
+        let mut file = File::open(self.path(name))?;
+        let mut value = Vec::new();
+        let _ = file.read_to_end(&mut value)?;
+        Ok(value)
+    }
+
+    // The chunk is written to a temporary file first, so an interrupted write
+    // doesn't leave a truncated chunk behind. The file isn't synced: a chunk
+    // lost or corrupted by a crash is just fetched again, since chunks are
+    // verified when loaded.
+    fn write(&self, name: &XorName, value: &[u8]) -> Result<(), CoreError> {
+        let path = self.path(name);
+        let tmp_path = path.with_extension(TMP_EXTENSION);
+
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(value)?;
+        fs::rename(&tmp_path, &path)?;
+
+        Ok(())
+    }
+
+    fn remove(&mut self, name: &XorName) {
+        if let Some(size) = self.entries.remove(name) {
+            self.size -= size;
+        }
+        let _ = fs::remove_file(self.path(name));
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.max_size {
+            match self.entries.remove_lru() {
+                Some((name, size)) => {
+                    trace!("Evicting chunk {:?} from the cache.", name);
+                    self.size -= size;
+                    let _ = fs::remove_file(self.path(&name));
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn path(&self, name: &XorName) -> PathBuf {
+        self.dir.join(encode_name(name))
+    }
+}
+
+/// The chunk cache configured in the `safe_core` config file, if any. The
+/// config is read and the cache opened only once in the process, by the first
+/// call. Failure to open it is logged and treated as if no cache was
+/// configured.
+pub fn from_config() -> Option<ChunkCache> {
+    CONFIGURED.clone()
+}
+
+fn open_configured() -> Option<ChunkCache> {
+    let ChunkCacheConfig { path, max_size } = match config_handler::get_config().chunk_cache {
+        Some(config) => config,
+        None => return None,
+    };
+
+    match ChunkCache::open(path, max_size) {
+        Ok(cache) => Some(cache),
+        Err(error) => {
+            warn!("Failed to open the chunk cache: {:?}", error);
+            None
+        }
+    }
+}
+
+fn encode_name(name: &XorName) -> String {
+    name.0.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_name(encoded: &str) -> Option<XorName> {
+    if encoded.len() != 64 {
+        return None;
+    }
+
+    let mut name = [0; 32];
+    for (i, byte) in name.iter_mut().enumerate() {
+        *byte = match u8::from_str_radix(&encoded[2 * i..2 * i + 2], 16) {
+            Ok(byte) => byte,
+            Err(_) => return None,
+        };
+    }
+    Some(XorName(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand;
+    use std::env;
+    use utils;
+
+    fn random_chunk(size: usize) -> ImmutableData {
+        ImmutableData::new(unwrap!(utils::generate_random_vector(size)))
+    }
+
+    fn temp_dir() -> PathBuf {
+        let name: XorName = rand::random();
+        env::temp_dir().join(format!("chunk_cache_{}", encode_name(&name)))
+    }
+
+    // Put chunks into the cache, get them back and check they survive
+    // reopening the cache.
+    #[test]
+    fn put_get_and_reopen() {
+        let dir = temp_dir();
+        let chunk0 = random_chunk(100);
+        let chunk1 = random_chunk(200);
+
+        {
+            let cache = unwrap!(ChunkCache::open(dir.clone(), 1000));
+            cache.put(&chunk0);
+            cache.put(&chunk1);
+            assert_eq!(unwrap!(cache.size().wait()), 300);
+            assert_eq!(unwrap!(cache.get(chunk0.name()).wait()), Some(chunk0.clone()));
+            assert!(unwrap!(cache.get(&rand::random()).wait()).is_none());
+        }
+
+        let cache = unwrap!(ChunkCache::open(dir.clone(), 1000));
+        assert_eq!(unwrap!(cache.size().wait()), 300);
+        assert_eq!(unwrap!(cache.get(chunk0.name()).wait()), Some(chunk0));
+        assert_eq!(unwrap!(cache.get(chunk1.name()).wait()), Some(chunk1));
+
+        unwrap!(fs::remove_dir_all(dir));
+    }
+
+    // Check that the least recently used chunks are evicted and chunks larger
+    // than the cache are not stored.
+    #[test]
+    fn eviction() {
+        let dir = temp_dir();
+        let chunk0 = random_chunk(400);
+        let chunk1 = random_chunk(400);
+        let chunk2 = random_chunk(400);
+
+        let cache = unwrap!(ChunkCache::open(dir.clone(), 1000));
+        cache.put(&chunk0);
+        cache.put(&chunk1);
+        assert!(unwrap!(cache.get(chunk0.name()).wait()).is_some());
+        cache.put(&chunk2);
+
+        assert_eq!(unwrap!(cache.size().wait()), 800);
+        assert!(unwrap!(cache.get(chunk0.name()).wait()).is_some());
+        assert!(unwrap!(cache.get(chunk1.name()).wait()).is_none());
+        assert!(unwrap!(cache.get(chunk2.name()).wait()).is_some());
+
+        cache.put(&random_chunk(1001));
+        assert_eq!(unwrap!(cache.size().wait()), 800);
+
+        unwrap!(fs::remove_dir_all(dir));
+    }
+
+    // Check that caches opened in the same directory share their chunks and
+    // size accounting, and that leftover temporary files are removed.
+    #[test]
+    fn shared_and_cleaned_up() {
+        let dir = temp_dir();
+        unwrap!(fs::create_dir_all(&dir));
+        let tmp_path = dir.join(encode_name(&rand::random())).with_extension("tmp");
+        unwrap!(unwrap!(File::create(&tmp_path)).write_all(&[0; 100]));
+
+        let cache0 = unwrap!(ChunkCache::open(dir.clone(), 1000));
+        let cache1 = unwrap!(ChunkCache::open(dir.clone(), 1000));
+        assert!(!tmp_path.exists());
+
+        let chunk0 = random_chunk(600);
+        let chunk1 = random_chunk(600);
+        cache0.put(&chunk0);
+        assert_eq!(unwrap!(cache1.get(chunk0.name()).wait()), Some(chunk0.clone()));
+
+        // The second chunk evicts the first one from both.
+        cache1.put(&chunk1);
+        assert_eq!(unwrap!(cache0.size().wait()), 600);
+        assert!(unwrap!(cache0.get(chunk0.name()).wait()).is_none());
+        assert_eq!(unwrap!(cache0.get(chunk1.name()).wait()), Some(chunk1));
+
+        unwrap!(fs::remove_dir_all(dir));
+    }
+
+    // Check that corrupted chunks are detected and dropped.
+    #[test]
+    fn corruption() {
+        let dir = temp_dir();
+        let chunk = random_chunk(100);
+
+        let cache = unwrap!(ChunkCache::open(dir.clone(), 1000));
+        cache.put(&chunk);
+        // Wait for the chunk to be written.
+        assert_eq!(unwrap!(cache.size().wait()), 100);
+
+        let mut file = unwrap!(File::create(dir.join(encode_name(chunk.name()))));
+        unwrap!(file.write_all(&[0; 100]));
+
+        assert!(unwrap!(cache.get(chunk.name()).wait()).is_none());
+        assert_eq!(unwrap!(cache.size().wait()), 0);
+        assert!(!dir.join(encode_name(chunk.name())).exists());
+
+        unwrap!(fs::remove_dir_all(dir));
+    }
+}
@@ -0,0 +1,127 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use lru_cache::LruCache;
+use routing::{Value, XorName};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Default number of `MutableData` kept in the cache.
+pub const DEFAULT_MDATA_CACHE_CAPACITY: usize = 100;
+/// Default maximum age of the cached entries, in seconds.
+pub const DEFAULT_MDATA_CACHE_MAX_AGE_SECS: u64 = 30;
+
+/// Configuration of the `MutableData` cache of `Client`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MDataCacheConfig {
+    /// Maximum number of `MutableData` to keep entries of.
+    pub capacity: usize,
+    /// Maximum age of the cached entries. Mutating the entries doesn't bump
+    /// the version of `MutableData`, so changes made by other clients are only
+    /// picked up once the entries are this old.
+    pub max_age: Duration,
+}
+
+impl Default for MDataCacheConfig {
+    fn default() -> Self {
+        MDataCacheConfig {
+            capacity: DEFAULT_MDATA_CACHE_CAPACITY,
+            max_age: Duration::from_secs(DEFAULT_MDATA_CACHE_MAX_AGE_SECS),
+        }
+    }
+}
+
+struct Cached {
+    version: u64,
+    entries: Arc<BTreeMap<Vec<u8>, Value>>,
+    fetched: Instant,
+}
+
+// Entries of `MutableData` together with the version of the data they were
+// fetched at.
+pub struct MDataCache {
+    max_age: Duration,
+    data: LruCache<(XorName, u64), Cached>,
+    // Bumped on every invalidation, so that entries fetched concurrently with
+    // a mutation aren't cached.
+    generation: u64,
+}
+
+impl MDataCache {
+    pub fn new(config: MDataCacheConfig) -> Self {
+        MDataCache {
+            max_age: config.max_age,
+            data: LruCache::new(config.capacity),
+            generation: 0,
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // Returns the cached version and entries, unless they are too old. The
+    // entries are shared with the cache rather than copied.
+    pub fn get(
+        &mut self,
+        name: XorName,
+        tag: u64,
+    ) -> Option<(u64, Arc<BTreeMap<Vec<u8>, Value>>)> {
+        let expired = match self.data.get_mut(&(name, tag)) {
+            Some(cached) => {
+                if cached.fetched.elapsed() < self.max_age {
+                    return Some((cached.version, cached.entries.clone()));
+                }
+                true
+            }
+            None => false,
+        };
+        if expired {
+            let _ = self.data.remove(&(name, tag));
+        }
+        None
+    }
+
+    // Caches the entries, provided nothing has been invalidated since
+    // `generation`.
+    pub fn insert(
+        &mut self,
+        name: XorName,
+        tag: u64,
+        version: u64,
+        entries: Arc<BTreeMap<Vec<u8>, Value>>,
+        generation: u64,
+    ) {
+        if generation != self.generation {
+            return;
+        }
+        let _ = self.data.insert(
+            (name, tag),
+            Cached {
+                version,
+                entries,
+                fetched: Instant::now(),
+            },
+        );
+    }
+
+    pub fn invalidate(&mut self, name: XorName, tag: u64) {
+        self.generation += 1;
+        let _ = self.data.remove(&(name, tag));
+    }
+}
@@ -1109,6 +1109,7 @@ fn low_balance_check() {
                 mock_in_memory_storage: true,
                 mock_vault_path: None,
             }),
+            chunk_cache: None,
         });
         let owner_key = *full_id.public_id().signing_public_key();
         let client_mgr = create_account(&mut routing, &routing_rx, owner_key);
@@ -1202,6 +1203,7 @@ fn invalid_config_mock_vault_path() {
             mock_in_memory_storage: false,
             mock_vault_path: Some(String::from("./this_path_should_not_exist")),
         }),
+        chunk_cache: None,
     });
     let owner_key = *full_id.public_id().signing_public_key();
 
@@ -1233,6 +1235,7 @@ fn config_mock_vault_path() {
             mock_in_memory_storage: false,
             mock_vault_path: Some(String::from("./tmp")),
         }),
+        chunk_cache: None,
     });
     let owner_key = *full_id.public_id().signing_public_key();
     let client_mgr = create_account(&mut routing, &routing_rx, owner_key);
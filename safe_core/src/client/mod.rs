@@ -19,14 +19,28 @@
 pub mod mdata_info;
 /// Operations with recovery.
 pub mod recovery;
+/// Change notifications for `MutableData`.
+pub mod watcher;
 
 mod account;
+mod cancel;
+mod chunk_cache;
+mod mdata_cache;
 #[cfg(feature = "use-mock-routing")]
 mod mock;
+mod retry;
 mod routing_event_loop;
+mod scheduler;
 
 use self::account::Account;
 pub use self::account::ClientKeys;
+pub use self::cancel::CancelHandle;
+use self::cancel::CancelSignal;
+pub use self::chunk_cache::ChunkCache;
+use self::mdata_cache::MDataCache;
+pub use self::mdata_cache::MDataCacheConfig;
+use self::scheduler::{DEFAULT_MAX_IN_FLIGHT, Scheduler};
+pub use self::scheduler::RequestStats;
 pub use self::mdata_info::MDataInfo;
 #[cfg(feature = "use-mock-routing")]
 pub use self::mock::Routing as MockRouting;
@@ -34,20 +48,22 @@ pub use self::mock::Routing as MockRouting;
 use self::mock::Routing;
 #[cfg(feature = "use-mock-routing")]
 pub use self::mock::vault::file_store_path as mock_vault_path;
+pub use self::retry::RetryPolicy;
+use self::watcher::{WatchConfig, WatchStream};
 use crypto::{shared_box, shared_secretbox, shared_sign};
 use errors::CoreError;
 use event::{CoreEvent, NetworkEvent, NetworkTx};
 use event_loop::{CoreFuture, CoreMsgTx};
 use futures::{Complete, Future};
-use futures::future::{self, Either, FutureResult, Loop, Then};
+use futures::future::{self, Either, FutureResult, Loop, Shared, Then};
 use futures::sync::oneshot;
 use ipc::BootstrapConfig;
 use lru_cache::LruCache;
 use maidsafe_utilities::serialisation::{deserialise, serialise};
 use maidsafe_utilities::thread::{self, Joiner};
-use routing::{ACC_LOGIN_ENTRY_KEY, AccountInfo, AccountPacket, Authority, EntryAction, Event,
-              FullId, ImmutableData, InterfaceError, MessageId, MutableData, PermissionSet,
-              Response, TYPE_TAG_SESSION_PACKET, User, Value, XorName};
+use routing::{ACC_LOGIN_ENTRY_KEY, AccountInfo, AccountPacket, Authority, ClientError,
+              EntryAction, Event, FullId, ImmutableData, InterfaceError, MessageId, MutableData,
+              PermissionSet, Response, TYPE_TAG_SESSION_PACKET, User, Value, XorName};
 #[cfg(not(feature = "use-mock-routing"))]
 use routing::Client as Routing;
 use rust_sodium::crypto::box_;
@@ -56,7 +72,8 @@ use std::cell::{Ref, RefCell, RefMut};
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt;
 use std::io;
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
 use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
 use std::time::Duration;
 use tiny_keccak::sha3_256;
@@ -67,7 +84,6 @@ const CONNECTION_TIMEOUT_SECS: u64 = 40;
 const REQUEST_TIMEOUT_SECS: u64 = 180;
 const SEED_SUBPARTS: usize = 4;
 const IMMUT_DATA_CACHE_SIZE: usize = 300;
-const RETRY_DELAY_MS: u64 = 800;
 
 macro_rules! match_event {
     ($r:ident, $event:path) => {
@@ -113,6 +129,9 @@ macro_rules! wait_for_response {
 /// an asynchronous API using the futures abstraction from the futures-rs crate
 pub struct Client<T> {
     inner: Rc<RefCell<Inner<T>>>,
+    retry_policy: Option<RetryPolicy>,
+    timeout: Option<Duration>,
+    cancel: Vec<CancelHandle>,
 }
 
 struct Inner<T> {
@@ -120,6 +139,13 @@ struct Inner<T> {
     routing: Routing,
     hooks: HashMap<MessageId, Complete<CoreEvent>>,
     cache: LruCache<XorName, ImmutableData>,
+    chunk_cache: Option<ChunkCache>,
+    mdata_cache: Option<MDataCache>,
+    usage_tracking: bool,
+    scheduler: Rc<RefCell<Scheduler>>,
+    pending_gets: HashMap<(XorName, Duration), PendingGet>,
+    next_pending_get: u64,
+    retry_policy: RetryPolicy,
     client_type: ClientType,
     timeout: Duration,
     joiner: Joiner,
@@ -130,7 +156,12 @@ struct Inner<T> {
 
 impl<T> Clone for Client<T> {
     fn clone(&self) -> Self {
-        Client { inner: Rc::clone(&self.inner) }
+        Client {
+            inner: Rc::clone(&self.inner),
+            retry_policy: self.retry_policy,
+            timeout: self.timeout,
+            cancel: self.cancel.clone(),
+        }
     }
 }
 
@@ -154,6 +185,13 @@ impl<T: 'static> Client<T> {
             routing: routing,
             hooks: HashMap::with_capacity(10),
             cache: LruCache::new(IMMUT_DATA_CACHE_SIZE),
+            chunk_cache: chunk_cache::from_config(),
+            mdata_cache: None,
+            usage_tracking: false,
+            scheduler: Rc::new(RefCell::new(Scheduler::new(DEFAULT_MAX_IN_FLIGHT))),
+            pending_gets: HashMap::new(),
+            next_pending_get: 0,
+            retry_policy: RetryPolicy::default(),
             client_type: ClientType::unreg(config),
             timeout: Duration::from_secs(REQUEST_TIMEOUT_SECS),
             joiner: joiner,
@@ -304,6 +342,13 @@ impl<T: 'static> Client<T> {
             routing: routing,
             hooks: HashMap::with_capacity(10),
             cache: LruCache::new(IMMUT_DATA_CACHE_SIZE),
+            chunk_cache: chunk_cache::from_config(),
+            mdata_cache: None,
+            usage_tracking: false,
+            scheduler: Rc::new(RefCell::new(Scheduler::new(DEFAULT_MAX_IN_FLIGHT))),
+            pending_gets: HashMap::new(),
+            next_pending_get: 0,
+            retry_policy: RetryPolicy::default(),
             client_type: ClientType::reg(acc, acc_loc, user_cred, cm_addr),
             timeout: Duration::from_secs(REQUEST_TIMEOUT_SECS),
             joiner: joiner,
@@ -424,6 +469,13 @@ impl<T: 'static> Client<T> {
             routing: routing,
             hooks: HashMap::with_capacity(10),
             cache: LruCache::new(IMMUT_DATA_CACHE_SIZE),
+            chunk_cache: chunk_cache::from_config(),
+            mdata_cache: None,
+            usage_tracking: false,
+            scheduler: Rc::new(RefCell::new(Scheduler::new(DEFAULT_MAX_IN_FLIGHT))),
+            pending_gets: HashMap::new(),
+            next_pending_get: 0,
+            retry_policy: RetryPolicy::default(),
             client_type: ClientType::reg(acc, acc_loc, user_cred, cm_addr),
             timeout: Duration::from_secs(REQUEST_TIMEOUT_SECS),
             joiner: joiner,
@@ -478,6 +530,13 @@ impl<T: 'static> Client<T> {
             routing: routing,
             hooks: HashMap::with_capacity(10),
             cache: LruCache::new(IMMUT_DATA_CACHE_SIZE),
+            chunk_cache: chunk_cache::from_config(),
+            mdata_cache: None,
+            usage_tracking: false,
+            scheduler: Rc::new(RefCell::new(Scheduler::new(DEFAULT_MAX_IN_FLIGHT))),
+            pending_gets: HashMap::new(),
+            next_pending_get: 0,
+            retry_policy: RetryPolicy::default(),
             client_type: ClientType::from_keys(keys, owner, config),
             timeout: Duration::from_secs(REQUEST_TIMEOUT_SECS),
             joiner: joiner,
@@ -515,7 +574,12 @@ impl<T: 'static> Client<T> {
     }
 
     fn new(inner: Inner<T>) -> Self {
-        Client { inner: Rc::new(RefCell::new(inner)) }
+        Client {
+            inner: Rc::new(RefCell::new(inner)),
+            retry_policy: None,
+            timeout: None,
+            cancel: Vec::new(),
+        }
     }
 
     /// Set request timeout.
@@ -523,6 +587,105 @@ impl<T: 'static> Client<T> {
         self.inner_mut().timeout = duration;
     }
 
+    /// Set the maximum number of requests in flight at once. Further requests
+    /// wait until some of the earlier ones complete.
+    pub fn set_max_in_flight(&self, max_in_flight: usize) {
+        let scheduler = Rc::clone(&self.inner().scheduler);
+        scheduler::set_max_in_flight(&scheduler, max_in_flight);
+    }
+
+    /// Returns statistics of the requests in flight and waiting to be sent.
+    pub fn request_stats(&self) -> RequestStats {
+        self.inner().scheduler.borrow().stats()
+    }
+
+    /// Set the policy for retrying failed reads and idempotent mutations. By
+    /// default, requests aren't retried.
+    pub fn set_retry_policy(&self, policy: RetryPolicy) {
+        self.inner_mut().retry_policy = policy;
+    }
+
+    /// Returns the retry policy requests made through this `Client` use.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry_policy.unwrap_or_else(
+            || self.inner().retry_policy,
+        )
+    }
+
+    /// Returns a `Client` sharing the connection and state with this one, but
+    /// retrying requests made through it according to `policy` instead of the
+    /// policy set with `set_retry_policy`.
+    pub fn with_retry_policy(&self, policy: RetryPolicy) -> Self {
+        let mut client = self.clone();
+        client.retry_policy = Some(policy);
+        client
+    }
+
+    /// Returns a `Client` sharing the connection and state with this one, but
+    /// whose requests time out after `duration` instead of the timeout set
+    /// with `set_timeout`.
+    pub fn with_timeout(&self, duration: Duration) -> Self {
+        let mut client = self.clone();
+        client.timeout = Some(duration);
+        client
+    }
+
+    /// Returns a `Client` sharing the connection and state with this one,
+    /// together with a handle which cancels the requests made through it that
+    /// are in progress. Cancelled requests fail with
+    /// `CoreError::OperationAborted`. The requests are still cancelled by the
+    /// handles of this `Client` too.
+    pub fn cancellable(&self) -> (Self, CancelHandle) {
+        let handle = CancelHandle::default();
+        (self.with_cancel_handle(&handle), handle)
+    }
+
+    /// Returns a `Client` sharing the connection and state with this one,
+    /// whose requests in progress are cancelled by `handle` as well as by the
+    /// handles of this `Client`.
+    pub fn with_cancel_handle(&self, handle: &CancelHandle) -> Self {
+        let mut client = self.clone();
+        client.cancel.push(handle.clone());
+        client
+    }
+
+    /// Replace the persistent chunk cache configured in the `safe_core` config
+    /// file. `None` disables it.
+    pub fn set_chunk_cache(&self, chunk_cache: Option<ChunkCache>) {
+        self.inner_mut().chunk_cache = chunk_cache;
+    }
+
+    /// Enable the cache of `MutableData` entries, or disable it with `None`.
+    /// The cache is disabled by default. While enabled, `list_mdata_entries`
+    /// serves the cached entries as long as the version of the data hasn't
+    /// changed and the entries are younger than `max_age`. `get_mdata_value`
+    /// serves a value from the cached entries without any request, skipping
+    /// the version check, as long as they are younger than `max_age`; values of
+    /// data without cached entries are fetched as usual and not cached.
+    /// Mutations done through this client invalidate the cached entries of the
+    /// mutated data, but mutating entries doesn't bump the version of the data,
+    /// so entries changed by other clients can be served stale for up to
+    /// `max_age`. Only the version check of `list_mdata_entries` catches
+    /// changes to the permissions or owners made elsewhere.
+    pub fn set_mdata_cache(&self, config: Option<MDataCacheConfig>) {
+        self.inner_mut().mdata_cache = config.map(MDataCache::new);
+    }
+
+    /// Enable keeping the usage records of nfs directories (see
+    /// `nfs::file_helper::set_quota`) updated by the mutations of their files
+    /// done through this client, and enforcing their quotas. Disabled by
+    /// default, as it costs fetching the usage record and the changed files
+    /// before every mutation. Directories with a quota have to be mutated only
+    /// by clients which enable it, as the others leave their usage stale.
+    pub fn set_usage_tracking(&self, enabled: bool) {
+        self.inner_mut().usage_tracking = enabled;
+    }
+
+    /// Returns `true` if usage tracking is enabled with `set_usage_tracking`.
+    pub fn usage_tracking(&self) -> bool {
+        self.inner().usage_tracking
+    }
+
     /// Restart the routing client and reconnect to the network.
     pub fn restart_routing(&self) -> Result<(), CoreError> {
         let opt_id = match self.inner().client_type {
@@ -579,7 +742,8 @@ impl<T: 'static> Client<T> {
 
     /// Get immutable data from the network. If the data exists locally in the cache
     /// then it will be immediately be returned without making an actual network
-    /// request.
+    /// request. The persistent chunk cache, if configured, is consulted next,
+    /// off the event loop.
     pub fn get_idata(&self, name: XorName) -> Box<CoreFuture<ImmutableData>> {
         trace!("GetIData for {:?}", name);
 
@@ -588,16 +752,22 @@ impl<T: 'static> Client<T> {
             return future::ok(data.clone()).into_box();
         }
 
-        let inner = Rc::downgrade(&self.inner);
-        self.send(move |routing, msg_id| {
-            routing.get_idata(Authority::NaeManager(name), name, msg_id)
-        }).and_then(|event| match_event!(event, CoreEvent::GetIData))
-            .map(move |data| {
-                if let Some(inner) = inner.upgrade() {
-                    // Put to cache
-                    let _ = inner.borrow_mut().cache.insert(*data.name(), data.clone());
+        let chunk_cache = self.inner().chunk_cache.clone();
+        let chunk_cache = match chunk_cache {
+            Some(chunk_cache) => chunk_cache,
+            None => return self.fetch_idata(name),
+        };
+        let client = self.clone();
+
+        chunk_cache
+            .get(&name)
+            .and_then(move |cached| match cached {
+                Some(data) => {
+                    trace!("ImmutableData found in chunk cache.");
+                    let _ = client.inner_mut().cache.insert(name, data.clone());
+                    ok!(data)
                 }
-                data
+                None => client.fetch_idata(name),
             })
             .into_box()
     }
@@ -609,9 +779,11 @@ impl<T: 'static> Client<T> {
     pub fn put_idata(&self, data: ImmutableData) -> Box<CoreFuture<()>> {
         trace!("PutIData for {:?}", data);
 
-        self.send_mutation(move |routing, dst, msg_id| {
-            routing.put_idata(dst, data.clone(), msg_id)
-        })
+        // Putting the same `ImmutableData` again succeeds, so it's safe to retry.
+        let dst = fry!(self.cm_addr());
+        self.send(move |routing, msg_id| routing.put_idata(dst, data.clone(), msg_id))
+            .and_then(|event| match_event!(event, CoreEvent::Mutation))
+            .into_box()
     }
 
     /// Put `MutableData` onto the network.
@@ -634,7 +806,7 @@ impl<T: 'static> Client<T> {
         trace!("PutMData for {:?}", name);
 
         let requester = fry!(self.public_signing_key());
-        self.send_mutation(move |routing, dst, msg_id| {
+        self.send_mdata_mutation(name, tag, move |routing, dst, msg_id| {
             routing.mutate_mdata_entries(dst, name, tag, actions.clone(), msg_id, requester)
         })
     }
@@ -677,9 +849,18 @@ impl<T: 'static> Client<T> {
     ) -> Box<CoreFuture<BTreeMap<Vec<u8>, Value>>> {
         trace!("ListMDataEntries for {:?}", name);
 
-        self.send(move |routing, msg_id| {
-            routing.list_mdata_entries(Authority::NaeManager(name), name, tag, msg_id)
-        }).and_then(|event| match_event!(event, CoreEvent::ListMDataEntries))
+        if self.inner().mdata_cache.is_none() {
+            return self.send_list_mdata_entries(name, tag);
+        }
+
+        let client = self.clone();
+        self.cached_mdata_entries(name, tag)
+            .and_then(move |cached| match cached {
+                Some(entries) => ok!(Arc::try_unwrap(entries).unwrap_or_else(|entries| {
+                    (*entries).clone()
+                })),
+                None => client.fetch_mdata_entries(name, tag),
+            })
             .into_box()
     }
 
@@ -707,10 +888,33 @@ impl<T: 'static> Client<T> {
     pub fn get_mdata_value(&self, name: XorName, tag: u64, key: Vec<u8>) -> Box<CoreFuture<Value>> {
         trace!("GetMDataValue for {:?}", name);
 
-        self.send(move |routing, msg_id| {
-            routing.get_mdata_value(Authority::NaeManager(name), name, tag, key.clone(), msg_id)
-        }).and_then(|event| match_event!(event, CoreEvent::GetMDataValue))
-            .into_box()
+        // Unlike `list_mdata_entries`, the version isn't checked, as that
+        // would cost as much as fetching the value itself.
+        let cached = match self.inner_mut().mdata_cache {
+            Some(ref mut mdata_cache) => mdata_cache.get(name, tag),
+            None => None,
+        };
+        if let Some((_, entries)) = cached {
+            trace!("MutableData entry found in cache.");
+            return match entries.get(&key) {
+                Some(value) => ok!(value.clone()),
+                None => err!(CoreError::RoutingClientError(ClientError::NoSuchEntry)),
+            };
+        }
+
+        self.send_get_mdata_value(name, tag, key)
+    }
+
+    /// Watch the given `MutableData` for changes. Resolves once the current
+    /// state of all the targets is known, to a stream of their subsequent
+    /// changes.
+    pub fn watch_mdata(
+        &self,
+        targets: Vec<(XorName, u64)>,
+        config: WatchConfig,
+    ) -> Box<CoreFuture<WatchStream>> {
+        trace!("Watching {} MutableData", targets.len());
+        watcher::watch(self, targets, config)
     }
 
     /// Get data from the network.
@@ -767,7 +971,7 @@ impl<T: 'static> Client<T> {
         trace!("SetMDataUserPermissions for {:?}", name);
 
         let requester = fry!(self.public_signing_key());
-        self.send_mutation(move |routing, dst, msg_id| {
+        self.send_mdata_mutation(name, tag, move |routing, dst, msg_id| {
             routing.set_mdata_user_permissions(
                 dst,
                 name,
@@ -792,7 +996,7 @@ impl<T: 'static> Client<T> {
         trace!("DelMDataUserPermissions for {:?}", name);
 
         let requester = fry!(self.public_signing_key());
-        self.send_mutation(move |routing, dst, msg_id| {
+        self.send_mdata_mutation(name, tag, move |routing, dst, msg_id| {
             routing.del_mdata_user_permissions(dst, name, tag, user, version, msg_id, requester)
         })
     }
@@ -807,7 +1011,7 @@ impl<T: 'static> Client<T> {
     ) -> Box<CoreFuture<()>> {
         trace!("ChangeMDataOwner for {:?}", name);
 
-        self.send_mutation(move |routing, dst, msg_id| {
+        self.send_mdata_mutation(name, tag, move |routing, dst, msg_id| {
             routing.change_mdata_owner(dst, name, tag, btree_set![new_owner], version, msg_id)
         })
     }
@@ -1007,48 +1211,325 @@ impl<T: 'static> Client<T> {
         self.mutate_mdata_entries(data_name, TYPE_TAG_SESSION_PACKET, update)
     }
 
-    /// Sends a request and returns a future that resolves to the response.
+    /// Sends a request and returns a future that resolves to the response,
+    /// retrying it according to the retry policy. Only use for requests that
+    /// are safe to repeat.
     fn send<F>(&self, req: F) -> Box<CoreFuture<CoreEvent>>
     where
         F: Fn(&mut Routing, MessageId) -> Result<(), InterfaceError> + 'static,
     {
+        let policy = self.retry_policy();
+        let req = Rc::new(req);
+        let client = self.clone();
+
+        future::loop_fn(1, move |attempt| {
+            let req = Rc::clone(&req);
+            let client2 = client.clone();
+
+            client
+                .send_once(move |routing, msg_id| req(routing, msg_id))
+                .then(move |result| {
+                    let retry = match result {
+                        Ok(ref event) => retry::event_error(event),
+                        Err(ref error) => Some(error),
+                    }.map_or(false, |error| policy.should_retry(attempt, error));
+
+                    if !retry {
+                        return Either::A(future::result(result.map(Loop::Break)));
+                    }
+
+                    let delay = timeout(policy.delay(attempt), &client2.inner().el_handle);
+                    Either::B(delay.then(move |_| Ok(Loop::Continue(attempt + 1))))
+                })
+        }).into_box()
+    }
+
+    /// Sends a request once, and returns a future that resolves to the
+    /// response.
+    fn send_once<F>(&self, req: F) -> Box<CoreFuture<CoreEvent>>
+    where
+        F: Fn(&mut Routing, MessageId) -> Result<(), InterfaceError> + 'static,
+    {
+        let policy = self.retry_policy();
+        let duration = self.timeout();
         let inner = Rc::downgrade(&self.inner);
-        let func = move |_| if let Some(inner) = inner.upgrade() {
+        let func = move |attempt| if let Some(inner) = inner.upgrade() {
             let msg_id = MessageId::new();
             if let Err(error) = req(&mut inner.borrow_mut().routing, msg_id) {
                 return future::err(CoreError::from(error)).into_box();
             }
+            inner.borrow().scheduler.borrow_mut().record_sent();
 
             let (hook, rx) = oneshot::channel();
             let _ = inner.borrow_mut().hooks.insert(msg_id, hook);
+            let guard = HookGuard {
+                inner: Rc::downgrade(&inner),
+                msg_id,
+            };
 
             let rx = rx.map_err(|_| CoreError::OperationAborted);
-            let rx = setup_timeout_and_retry_delay(&inner, msg_id, rx);
-            let rx = rx.map(|event| if let CoreEvent::RateLimitExceeded = event {
-                Loop::Continue(())
-            } else {
-                Loop::Break(event)
+            let rx = setup_retry_delay(&inner, policy.delay(attempt), rx);
+            let rx = rx.then(move |result| {
+                drop(guard);
+                result.map(|event| if let CoreEvent::RateLimitExceeded = event {
+                    Loop::Continue(attempt + 1)
+                } else {
+                    Loop::Break(event)
+                })
             });
             rx.into_box()
         } else {
             future::err(CoreError::OperationAborted).into_box()
         };
 
-        future::loop_fn((), func).into_box()
+        // Wait for a free slot if too many requests are in flight already.
+        // The wait counts towards the timeout of the request.
+        let future = scheduler::acquire(&self.inner().scheduler)
+            .and_then(move |permit| {
+                future::loop_fn(1, func).then(move |result| {
+                    drop(permit);
+                    result
+                })
+            })
+            .select(timeout(duration, &self.inner().el_handle))
+            .then(|result| match result {
+                Ok((a, _)) => Ok(a),
+                Err((a, _)) => Err(a),
+            })
+            .into_box();
+        self.abortable(future)
+    }
+
+    // Returns the timeout of the requests made through this `Client`.
+    fn timeout(&self) -> Duration {
+        self.timeout.unwrap_or_else(|| self.inner().timeout)
+    }
+
+    // Makes the future fail with `OperationAborted` as soon as the requests of
+    // this `Client` in progress are cancelled. The future is dropped then,
+    // which removes the hook of its request.
+    fn abortable<U: 'static>(&self, future: Box<CoreFuture<U>>) -> Box<CoreFuture<U>> {
+        self.cancel_signals().into_iter().fold(
+            future,
+            |future, signal| {
+                future
+                    .select(cancel::aborted(signal))
+                    .then(|result| match result {
+                        Ok((a, _)) => Ok(a),
+                        Err((a, _)) => Err(a),
+                    })
+                    .into_box()
+            },
+        )
+    }
+
+    // Returns the signals which resolve once the requests of this `Client`
+    // currently in progress are cancelled.
+    fn cancel_signals(&self) -> Vec<CancelSignal> {
+        self.cancel.iter().map(cancel::signal).collect()
     }
 
-    /// Sends a mutation request.
+    /// Sends a mutation request. It is not retried, as repeating a mutation
+    /// which succeeded but timed out would fail.
     fn send_mutation<F>(&self, req: F) -> Box<CoreFuture<()>>
     where
         F: Fn(&mut Routing, Authority<XorName>, MessageId) -> Result<(), InterfaceError> + 'static,
     {
         let dst = fry!(self.cm_addr());
 
-        self.send(move |routing, msg_id| req(routing, dst, msg_id))
+        self.send_once(move |routing, msg_id| req(routing, dst, msg_id))
             .and_then(|event| match_event!(event, CoreEvent::Mutation))
             .into_box()
     }
 
+    // Like `send_mutation`, additionally invalidating the cached entries of the
+    // mutated `MutableData` once the mutation completes.
+    fn send_mdata_mutation<F>(&self, name: XorName, tag: u64, req: F) -> Box<CoreFuture<()>>
+    where
+        F: Fn(&mut Routing, Authority<XorName>, MessageId) -> Result<(), InterfaceError> + 'static,
+    {
+        let inner = Rc::downgrade(&self.inner);
+        self.send_mutation(req)
+            .then(move |result| {
+                if let Some(inner) = inner.upgrade() {
+                    if let Some(ref mut mdata_cache) = inner.borrow_mut().mdata_cache {
+                        mdata_cache.invalidate(name, tag);
+                    }
+                }
+                result
+            })
+            .into_box()
+    }
+
+    // Returns the cached entries of the `MutableData`, provided its version
+    // hasn't changed since they were fetched. Changes to the entries alone
+    // don't bump the version, so those are only caught once the cached
+    // entries expire.
+    fn cached_mdata_entries(
+        &self,
+        name: XorName,
+        tag: u64,
+    ) -> Box<CoreFuture<Option<Arc<BTreeMap<Vec<u8>, Value>>>>> {
+        let cached = self.inner_mut().mdata_cache.as_mut().and_then(
+            |mdata_cache| mdata_cache.get(name, tag),
+        );
+        let (version, entries) = match cached {
+            Some(cached) => cached,
+            None => return ok!(None),
+        };
+
+        let inner = Rc::downgrade(&self.inner);
+        self.get_mdata_version(name, tag)
+            .map(move |current| if current == version {
+                trace!("MutableData entries found in cache.");
+                Some(entries)
+            } else {
+                if let Some(inner) = inner.upgrade() {
+                    if let Some(ref mut mdata_cache) = inner.borrow_mut().mdata_cache {
+                        mdata_cache.invalidate(name, tag);
+                    }
+                }
+                None
+            })
+            .into_box()
+    }
+
+    // Fetches the version and entries of the `MutableData` and caches them.
+    fn fetch_mdata_entries(
+        &self,
+        name: XorName,
+        tag: u64,
+    ) -> Box<CoreFuture<BTreeMap<Vec<u8>, Value>>> {
+        let generation = match self.inner().mdata_cache {
+            Some(ref mdata_cache) => mdata_cache.generation(),
+            None => return self.send_list_mdata_entries(name, tag),
+        };
+
+        let client = self.clone();
+        let inner = Rc::downgrade(&self.inner);
+
+        self.get_mdata_version(name, tag)
+            .and_then(move |version| {
+                client.send_list_mdata_entries(name, tag).map(
+                    move |entries| (version, entries),
+                )
+            })
+            .map(move |(version, entries)| {
+                let inner = match inner.upgrade() {
+                    Some(inner) => inner,
+                    None => return entries,
+                };
+                let mut inner = inner.borrow_mut();
+                match inner.mdata_cache {
+                    Some(ref mut mdata_cache) => {
+                        let entries = Arc::new(entries);
+                        mdata_cache.insert(name, tag, version, Arc::clone(&entries), generation);
+                        // Only copy the entries if the cache kept them.
+                        Arc::try_unwrap(entries).unwrap_or_else(|entries| (*entries).clone())
+                    }
+                    None => entries,
+                }
+            })
+            .into_box()
+    }
+
+    // Get immutable data from the network, unless the same request is already
+    // in flight.
+    fn fetch_idata(&self, name: XorName) -> Box<CoreFuture<ImmutableData>> {
+        // Join an identical request already in flight, unless it times out
+        // differently.
+        let key = (name, self.timeout());
+        let pending = self.inner().pending_gets.get(&key).map(|pending| {
+            (pending.id, pending.future.clone())
+        });
+        if let Some((id, pending)) = pending {
+            trace!("GetIData for {:?} already in flight.", name);
+            self.inner().scheduler.borrow_mut().record_coalesced();
+            return self.abortable(self.join_pending_get(key, id, pending));
+        }
+
+        // The request is shared, so cancelling this `Client` only aborts its
+        // own wait for the result, not the request itself.
+        let mut client = self.clone();
+        client.cancel.clear();
+
+        let inner = Rc::downgrade(&self.inner);
+        let pending = client.send(move |routing, msg_id| {
+            routing.get_idata(Authority::NaeManager(name), name, msg_id)
+        }).and_then(|event| match_event!(event, CoreEvent::GetIData))
+            .map(move |data| {
+                if let Some(inner) = inner.upgrade() {
+                    // Put to cache
+                    let mut inner = inner.borrow_mut();
+                    if let Some(ref chunk_cache) = inner.chunk_cache {
+                        chunk_cache.put(&data);
+                    }
+                    let _ = inner.cache.insert(*data.name(), data.clone());
+                }
+                data
+            })
+            .into_box()
+            .shared();
+
+        let id = {
+            let mut inner = self.inner_mut();
+            let id = inner.next_pending_get;
+            inner.next_pending_get += 1;
+            let _ = inner.pending_gets.insert(
+                key,
+                PendingGet {
+                    id,
+                    future: pending.clone(),
+                    joiners: 0,
+                },
+            );
+            id
+        };
+        self.abortable(self.join_pending_get(key, id, pending))
+    }
+
+    // Resolves with the result of the pending GET, once it completes.
+    fn join_pending_get(
+        &self,
+        key: (XorName, Duration),
+        id: u64,
+        pending: Shared<Box<CoreFuture<ImmutableData>>>,
+    ) -> Box<CoreFuture<ImmutableData>> {
+        let guard = PendingGetGuard::new(&self.inner, key, id);
+        pending
+            .then(move |result| {
+                guard.forget();
+                match result {
+                    Ok(data) => Ok((*data).clone()),
+                    Err(error) => Err(scheduler::share_error(&*error)),
+                }
+            })
+            .into_box()
+    }
+
+    fn send_list_mdata_entries(
+        &self,
+        name: XorName,
+        tag: u64,
+    ) -> Box<CoreFuture<BTreeMap<Vec<u8>, Value>>> {
+        self.send(move |routing, msg_id| {
+            routing.list_mdata_entries(Authority::NaeManager(name), name, tag, msg_id)
+        }).and_then(|event| match_event!(event, CoreEvent::ListMDataEntries))
+            .into_box()
+    }
+
+    fn send_get_mdata_value(
+        &self,
+        name: XorName,
+        tag: u64,
+        key: Vec<u8>,
+    ) -> Box<CoreFuture<Value>> {
+        self.send(move |routing, msg_id| {
+            routing.get_mdata_value(Authority::NaeManager(name), name, tag, key.clone(), msg_id)
+        }).and_then(|event| match_event!(event, CoreEvent::GetMDataValue))
+            .into_box()
+    }
+
     fn inner(&self) -> Ref<Inner<T>> {
         self.inner.borrow()
     }
@@ -1141,9 +1622,9 @@ impl<T> fmt::Debug for Client<T> {
     }
 }
 
-fn setup_timeout_and_retry_delay<T, F>(
+fn setup_retry_delay<T, F>(
     inner: &Rc<RefCell<Inner<T>>>,
-    msg_id: MessageId,
+    delay: Duration,
     future: F,
 ) -> Box<CoreFuture<CoreEvent>>
 where
@@ -1152,36 +1633,105 @@ where
 {
     // Delay after rate limit exceeded.
     let inner_weak = Rc::downgrade(inner);
-    let future = future.and_then(move |event| {
-        if let CoreEvent::RateLimitExceeded = event {
-            if let Some(inner) = inner_weak.upgrade() {
-                let delay = Duration::from_millis(RETRY_DELAY_MS);
-                let fut = timeout(delay, &inner.borrow().el_handle).or_else(move |_| Ok(event));
-                return Either::A(fut);
+    future
+        .and_then(move |event| {
+            if let CoreEvent::RateLimitExceeded = event {
+                if let Some(inner) = inner_weak.upgrade() {
+                    let fut =
+                        timeout(delay, &inner.borrow().el_handle).or_else(move |_| Ok(event));
+                    return Either::A(fut);
+                }
+            }
+
+            Either::B(future::ok(event))
+        })
+        .into_box()
+}
+
+// Removes the hook of a request once the request completes, times out or is
+// dropped, so no longer awaited responses don't pile up in `Inner::hooks`.
+struct HookGuard<T> {
+    inner: Weak<RefCell<Inner<T>>>,
+    msg_id: MessageId,
+}
+
+impl<T> Drop for HookGuard<T> {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.upgrade() {
+            if let Ok(mut inner) = inner.try_borrow_mut() {
+                let _ = inner.hooks.remove(&self.msg_id);
             }
         }
+    }
+}
 
-        Either::B(future::ok(event))
-    });
+// A GET of `ImmutableData` shared by all the concurrent requests for it.
+struct PendingGet {
+    id: u64,
+    future: Shared<Box<CoreFuture<ImmutableData>>>,
+    joiners: usize,
+}
 
-    // Fail if no response received within the timeout.
-    let duration = inner.borrow().timeout;
-    let inner_weak = Rc::downgrade(inner);
-    let timeout = timeout(duration, &inner.borrow().el_handle).then(move |result| {
-        if let Some(inner) = inner_weak.upgrade() {
-            let _ = inner.borrow_mut().hooks.remove(&msg_id);
+// Counts a caller waiting for a pending GET. The GET is forgotten once it
+// completes or all its callers are gone, whether they got the result, failed
+// or were cancelled, so later GETs send a fresh request.
+struct PendingGetGuard<T> {
+    inner: Weak<RefCell<Inner<T>>>,
+    key: (XorName, Duration),
+    id: u64,
+}
+
+impl<T> PendingGetGuard<T> {
+    fn new(inner: &Rc<RefCell<Inner<T>>>, key: (XorName, Duration), id: u64) -> Self {
+        if let Some(pending) = inner.borrow_mut().pending_gets.get_mut(&key) {
+            if pending.id == id {
+                pending.joiners += 1;
+            }
         }
 
-        result
-    });
+        PendingGetGuard {
+            inner: Rc::downgrade(inner),
+            key,
+            id,
+        }
+    }
 
-    future
-        .select(timeout)
-        .then(|result| match result {
-            Ok((a, _)) => Ok(a),
-            Err((a, _)) => Err(a),
-        })
-        .into_box()
+    // Forgets the pending GET once it has completed.
+    fn forget(&self) {
+        if let Some(inner) = self.inner.upgrade() {
+            if let Ok(mut inner) = inner.try_borrow_mut() {
+                let current = inner.pending_gets.get(&self.key).map_or(false, |pending| {
+                    pending.id == self.id
+                });
+                if current {
+                    let _ = inner.pending_gets.remove(&self.key);
+                }
+            }
+        }
+    }
+}
+
+impl<T> Drop for PendingGetGuard<T> {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.upgrade() {
+            if let Ok(mut inner) = inner.try_borrow_mut() {
+                let unused = match inner.pending_gets.get_mut(&self.key) {
+                    Some(pending) => {
+                        if pending.id == self.id {
+                            pending.joiners -= 1;
+                            pending.joiners == 0
+                        } else {
+                            false
+                        }
+                    }
+                    None => false,
+                };
+                if unused {
+                    let _ = inner.pending_gets.remove(&self.key);
+                }
+            }
+        }
+    }
 }
 
 // Create a future that resolves into `CoreError::RequestTimeout` after the given time interval.
@@ -1435,7 +1985,6 @@ mod tests {
     use errors::CoreError;
     use futures::Future;
     use futures::sync::mpsc;
-    #[cfg(feature = "use-mock-routing")]
     use rand;
     use routing::{ClientError, ImmutableData};
     use tokio_core::reactor::Core;
@@ -1696,6 +2245,372 @@ mod tests {
         );
     }
 
+    // Test that fetched immutable data is stored in the persistent chunk cache.
+    #[test]
+    fn chunk_cache() {
+        use std::env;
+        use std::fs;
+
+        let suffix: Vec<u64> = unwrap!(utils::generate_random_vector(1));
+        let dir = env::temp_dir().join(format!("chunk_cache_{}", suffix[0]));
+        let dir2 = dir.clone();
+
+        let data = random_client(move |client| {
+            let client2 = client.clone();
+            client.set_chunk_cache(Some(unwrap!(ChunkCache::open(dir2, 1000))));
+
+            let data = ImmutableData::new(unwrap!(utils::generate_random_vector(100)));
+            let name = *data.name();
+
+            client.put_idata(data).and_then(
+                move |_| client2.get_idata(name),
+            )
+        });
+
+        let chunk_cache = unwrap!(ChunkCache::open(dir.clone(), 1000));
+        assert_eq!(unwrap!(chunk_cache.get(data.name()).wait()), Some(data));
+
+        unwrap!(fs::remove_dir_all(dir));
+    }
+
+    // Test that `MutableData` entries are cached and invalidated by mutations,
+    // and that single values are served from the cache without any request.
+    #[test]
+    fn mdata_cache() {
+        use routing::{EntryActions, MutableData};
+
+        random_client(|client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+            let client4 = client.clone();
+            let client5 = client.clone();
+
+            client.set_mdata_cache(Some(MDataCacheConfig::default()));
+
+            let name = rand::random();
+            let owner = unwrap!(client.owner_key());
+            let entries = btree_map![
+                vec![0] => Value { content: vec![1], entry_version: 0 }
+            ];
+            let data = unwrap!(MutableData::new(
+                name,
+                DIR_TAG,
+                btree_map![],
+                entries,
+                btree_set![owner],
+            ));
+
+            client
+                .put_mdata(data)
+                .then(move |res| {
+                    unwrap!(res);
+                    client2.list_mdata_entries(name, DIR_TAG)
+                })
+                .then(move |res| {
+                    let entries = unwrap!(res);
+                    assert_eq!(entries.len(), 1);
+                    assert!(cached(&client3, name).is_some());
+
+                    let sent = client3.request_stats().sent;
+                    client3
+                        .get_mdata_value(name, DIR_TAG, vec![0])
+                        .join(client3.get_mdata_value(name, DIR_TAG, vec![2]).then(Ok))
+                        .map(move |values| (values, sent))
+                })
+                .then(move |res| {
+                    let ((value, missing), sent) = unwrap!(res);
+                    assert_eq!(value.content, vec![1]);
+                    match missing {
+                        Err(CoreError::RoutingClientError(ClientError::NoSuchEntry)) => (),
+                        x => panic!("Unexpected {:?}", x),
+                    }
+                    // Both values have been served without any request.
+                    assert_eq!(client4.request_stats().sent, sent);
+
+                    let actions = EntryActions::new().ins(vec![2], vec![3], 0).into();
+                    client4.mutate_mdata_entries(name, DIR_TAG, actions)
+                })
+                .then(move |res| {
+                    unwrap!(res);
+                    assert!(cached(&client4, name).is_none());
+                    client4.list_mdata_entries(name, DIR_TAG)
+                })
+                .then(move |res| -> Result<_, CoreError> {
+                    let entries = unwrap!(res);
+                    assert_eq!(entries.len(), 2);
+                    assert!(cached(&client5, name).is_some());
+                    Ok(())
+                })
+        });
+
+        fn cached(
+            client: &Client<()>,
+            name: XorName,
+        ) -> Option<(u64, Arc<BTreeMap<Vec<u8>, Value>>)> {
+            client.inner_mut().mdata_cache.as_mut().and_then(|mdata_cache| {
+                mdata_cache.get(name, DIR_TAG)
+            })
+        }
+    }
+
+    // Test that requests over the in-flight cap are queued and that identical
+    // concurrent GETs are coalesced into one request.
+    #[test]
+    fn request_scheduling() {
+        random_client(|client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+
+            let data = ImmutableData::new(unwrap!(utils::generate_random_vector(100)));
+            let name = *data.name();
+
+            client
+                .put_idata(data)
+                .then(move |res| {
+                    unwrap!(res);
+                    client2.set_max_in_flight(1);
+
+                    let get0 = client2.get_idata(name);
+                    let get1 = client2.get_idata(name);
+                    let get2 = client2.get_idata(rand::random()).then(|res| {
+                        assert!(res.is_err());
+                        Ok(())
+                    });
+                    let get3 = client2.get_idata(rand::random()).then(|res| {
+                        assert!(res.is_err());
+                        Ok(())
+                    });
+
+                    let stats = client2.request_stats();
+                    assert_eq!(stats.in_flight, 1);
+                    assert_eq!(stats.queued, 2);
+                    assert_eq!(stats.coalesced, 1);
+
+                    get0.join4(get1, get2, get3)
+                })
+                .then(move |res| -> Result<_, CoreError> {
+                    let (data0, data1, (), ()) = unwrap!(res);
+                    assert_eq!(data0, data1);
+
+                    let stats = client3.request_stats();
+                    assert_eq!(stats.in_flight, 0);
+                    assert_eq!(stats.queued, 0);
+                    assert_eq!(stats.max_queued, 2);
+                    Ok(())
+                })
+        });
+    }
+
+    // Test that GETs with different timeouts aren't coalesced, and that
+    // pending GETs are forgotten once their callers are gone.
+    #[test]
+    fn pending_gets() {
+        use std::time::Duration;
+
+        random_client(|client| {
+            let client2 = client.clone();
+            let name = rand::random();
+
+            let get0 = client.get_idata(name);
+            let get1 = client.with_timeout(Duration::from_secs(10)).get_idata(name);
+            assert_eq!(client.request_stats().coalesced, 0);
+            assert_eq!(client.inner().pending_gets.len(), 2);
+
+            drop(get0);
+            drop(get1);
+            assert!(client.inner().pending_gets.is_empty());
+
+            let (cancellable, handle) = client.cancellable();
+            let get = cancellable.get_idata(name);
+            assert_eq!(client.inner().pending_gets.len(), 1);
+            handle.cancel();
+
+            get.then(move |result| -> Result<_, CoreError> {
+                match result {
+                    Err(CoreError::OperationAborted) => (),
+                    res => panic!("Unexpected {:?}", res),
+                }
+                assert!(client2.inner().pending_gets.is_empty());
+                Ok(())
+            })
+        });
+    }
+
+    // Test that the time a request waits for a free slot counts towards its
+    // timeout.
+    #[cfg(feature = "use-mock-routing")]
+    #[test]
+    fn queued_timeout() {
+        use std::time::Duration;
+
+        random_client(|client| {
+            client.set_simulate_timeout(true);
+            client.set_retry_policy(RetryPolicy::none());
+            client.set_max_in_flight(1);
+
+            let slow = client
+                .with_timeout(Duration::from_secs(5))
+                .get_mdata_version(rand::random(), 0);
+            let queued = client
+                .with_timeout(Duration::from_millis(100))
+                .get_mdata_version(rand::random(), 0);
+
+            queued.select2(slow).then(|result| -> Result<_, CoreError> {
+                match result {
+                    Err(Either::A((CoreError::RequestTimeout, _))) => (),
+                    Ok(_) | Err(_) => panic!("Unexpected result"),
+                }
+                Ok(())
+            })
+        });
+    }
+
+    // Test that reads are retried according to the client's retry policy,
+    // unless overridden for the call.
+    #[cfg(feature = "use-mock-routing")]
+    #[test]
+    fn retry_policy() {
+        use std::sync::atomic::{ATOMIC_USIZE_INIT, AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        static RETRIES: AtomicUsize = ATOMIC_USIZE_INIT;
+
+        fn retryable(error: &CoreError) -> bool {
+            match *error {
+                CoreError::RequestTimeout => {
+                    let _ = RETRIES.fetch_add(1, Ordering::SeqCst);
+                    true
+                }
+                _ => false,
+            }
+        }
+
+        random_client(|client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+
+            client.set_simulate_timeout(true);
+            client.set_timeout(Duration::from_millis(100));
+            client.set_retry_policy(RetryPolicy {
+                max_attempts: 3,
+                initial_delay: Duration::from_millis(10),
+                retryable,
+                ..Default::default()
+            });
+
+            client
+                .get_idata(rand::random())
+                .then(move |result| {
+                    match result {
+                        Err(CoreError::RequestTimeout) => (),
+                        res => panic!("Unexpected {:?}", res),
+                    }
+                    assert_eq!(RETRIES.load(Ordering::SeqCst), 2);
+
+                    client2
+                        .with_retry_policy(RetryPolicy::none())
+                        .get_mdata_version(rand::random(), 0)
+                })
+                .then(move |result| -> Result<_, CoreError> {
+                    match result {
+                        Err(CoreError::RequestTimeout) => (),
+                        res => panic!("Unexpected {:?}", res),
+                    }
+                    assert_eq!(RETRIES.load(Ordering::SeqCst), 2);
+                    assert_eq!(client3.retry_policy().max_attempts, 3);
+                    Ok(())
+                })
+        });
+    }
+
+    // Test that requests time out after the per-call timeout, that cancelled
+    // requests fail with `OperationAborted` and leave no hooks behind, and that
+    // the client stays usable after cancelling.
+    #[cfg(feature = "use-mock-routing")]
+    #[test]
+    fn timeout_and_cancel() {
+        use std::time::Duration;
+
+        random_client(|client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+
+            client.set_simulate_timeout(true);
+            client.set_retry_policy(RetryPolicy::none());
+
+            client
+                .with_timeout(Duration::from_millis(100))
+                .get_mdata_version(rand::random(), 0)
+                .then(move |result| {
+                    match result {
+                        Err(CoreError::RequestTimeout) => (),
+                        res => panic!("Unexpected {:?}", res),
+                    }
+                    assert!(client2.inner().hooks.is_empty());
+
+                    let (cancellable, handle) = client2.cancellable();
+                    let get = cancellable.get_mdata_version(rand::random(), 0);
+
+                    let delay = timeout(Duration::from_millis(100), &client2.inner().el_handle);
+                    let cancel = delay.then(move |_| {
+                        handle.cancel();
+                        Ok::<_, CoreError>(())
+                    });
+
+                    get.then(Ok::<_, CoreError>).join(cancel).map(
+                        move |result| (result, cancellable),
+                    )
+                })
+                .then(move |result| {
+                    let ((get, ()), cancellable) = unwrap!(result);
+                    match get {
+                        Err(CoreError::OperationAborted) => (),
+                        res => panic!("Unexpected {:?}", res),
+                    }
+                    assert!(client3.inner().hooks.is_empty());
+
+                    // Requests made after cancelling aren't aborted.
+                    client3.set_simulate_timeout(false);
+                    cancellable.get_mdata_version(rand::random(), 0)
+                })
+                .then(|result| -> Result<_, CoreError> {
+                    match result {
+                        Err(CoreError::RoutingClientError(ClientError::NoSuchData)) => (),
+                        res => panic!("Unexpected {:?}", res),
+                    }
+                    Ok(())
+                })
+        });
+    }
+
+    // Test that dropping the cancel handle together with the client it was
+    // returned with doesn't abort their requests in progress.
+    #[test]
+    fn drop_cancel_handle() {
+        random_client(|client| {
+            let client2 = client.clone();
+
+            let data = ImmutableData::new(unwrap!(utils::generate_random_vector(100)));
+            let name = *data.name();
+
+            client
+                .put_idata(data)
+                .then(move |res| {
+                    unwrap!(res);
+                    let (cancellable, handle) = client2.cancellable();
+                    let get = cancellable.get_idata(name);
+                    drop(handle);
+                    drop(cancellable);
+                    get
+                })
+                .then(move |res| -> Result<_, CoreError> {
+                    let data = unwrap!(res);
+                    assert_eq!(*data.name(), name);
+                    Ok(())
+                })
+        });
+    }
+
     // Test that a `RequestTimeout` error is returned on network timeout.
     #[cfg(feature = "use-mock-routing")]
     #[test]
@@ -244,8 +244,8 @@ fn update_mdata_permissions<T: 'static>(
     }).into_box()
 }
 
-// Modify the given entry actions to fix the entry errors.
-fn fix_entry_actions(
+/// Modify the given entry actions to fix the entry errors.
+pub fn fix_entry_actions(
     actions: BTreeMap<Vec<u8>, EntryAction>,
     errors: &BTreeMap<Vec<u8>, EntryError>,
 ) -> BTreeMap<Vec<u8>, EntryAction> {
@@ -0,0 +1,191 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use errors::CoreError;
+use event::CoreEvent;
+use rand::{self, Rng};
+use std::cmp;
+use std::fmt;
+use std::time::Duration;
+
+/// Default maximum number of attempts of a request. Retries are opt-in, as
+/// repeating a request multiplies the time it can take to fail.
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 1;
+/// Default delay before the first retry, in milliseconds.
+pub const DEFAULT_INITIAL_DELAY_MS: u64 = 800;
+/// Default upper bound of the delay between retries, in milliseconds.
+pub const DEFAULT_MAX_DELAY_MS: u64 = 10_000;
+
+/// Policy for retrying failed requests of `Client`.
+///
+/// The delay between retries starts at `initial_delay` and is multiplied by
+/// `multiplier` after each retry, up to `max_delay`. Each delay is then
+/// shortened by a random fraction of up to `jitter`, so that clients which
+/// failed at the same time don't all retry at the same time too.
+///
+/// The default policy sends requests once; set `max_attempts` to enable
+/// retries.
+#[derive(Clone, Copy)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one. `1` disables
+    /// retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry.
+    pub initial_delay: Duration,
+    /// Upper bound of the delay between retries.
+    pub max_delay: Duration,
+    /// Factor the delay grows by after each retry.
+    pub multiplier: u32,
+    /// Fraction of the delay (between `0.0` and `1.0`) to randomise.
+    pub jitter: f64,
+    /// Decides whether a request failing with the given error is retried.
+    pub retryable: fn(&CoreError) -> bool,
+}
+
+impl RetryPolicy {
+    /// Policy which never retries. Same as the default policy.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the delay to wait for after the given (1-based) failed attempt.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let mut delay = cmp::min(self.initial_delay, self.max_delay);
+        for _ in 1..attempt {
+            delay = match delay.checked_mul(self.multiplier) {
+                Some(delay) if delay < self.max_delay => delay,
+                _ => return jitter(self.max_delay, self.jitter),
+            };
+        }
+        jitter(delay, self.jitter)
+    }
+
+    /// Returns whether a request should be retried after failing the given
+    /// (1-based) attempt with `error`.
+    pub fn should_retry(&self, attempt: u32, error: &CoreError) -> bool {
+        attempt < self.max_attempts && (self.retryable)(error)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            initial_delay: Duration::from_millis(DEFAULT_INITIAL_DELAY_MS),
+            max_delay: Duration::from_millis(DEFAULT_MAX_DELAY_MS),
+            multiplier: 2,
+            jitter: 0.5,
+            retryable: is_timeout,
+        }
+    }
+}
+
+// Written by hand as `retryable` doesn't implement `Debug`.
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_delay", &self.initial_delay)
+            .field("max_delay", &self.max_delay)
+            .field("multiplier", &self.multiplier)
+            .field("jitter", &self.jitter)
+            .finish()
+    }
+}
+
+/// Returns `true` for `CoreError::RequestTimeout`. This is what the default
+/// policy retries.
+fn is_timeout(error: &CoreError) -> bool {
+    match *error {
+        CoreError::RequestTimeout => true,
+        _ => false,
+    }
+}
+
+// Returns the error the response event carries, if any.
+pub fn event_error(event: &CoreEvent) -> Option<&CoreError> {
+    match *event {
+        CoreEvent::GetAccountInfo(Err(ref error)) |
+        CoreEvent::Mutation(Err(ref error)) |
+        CoreEvent::GetIData(Err(ref error)) |
+        CoreEvent::GetMDataVersion(Err(ref error)) |
+        CoreEvent::ListMDataEntries(Err(ref error)) |
+        CoreEvent::ListMDataKeys(Err(ref error)) |
+        CoreEvent::ListMDataValues(Err(ref error)) |
+        CoreEvent::GetMDataValue(Err(ref error)) |
+        CoreEvent::ListMDataPermissions(Err(ref error)) |
+        CoreEvent::ListMDataUserPermissions(Err(ref error)) |
+        CoreEvent::ListAuthKeysAndVersion(Err(ref error)) |
+        CoreEvent::GetMDataShell(Err(ref error)) |
+        CoreEvent::GetMData(Err(ref error)) => Some(error),
+        _ => None,
+    }
+}
+
+// Shortens the delay by a random fraction of up to `jitter`.
+fn jitter(delay: Duration, jitter: f64) -> Duration {
+    let jitter = jitter.max(0.0).min(1.0);
+    if jitter == 0.0 {
+        return delay;
+    }
+
+    let millis = delay.as_secs() * 1000 + u64::from(delay.subsec_nanos() / 1_000_000);
+    let factor = 1.0 - jitter * rand::thread_rng().gen::<f64>();
+    Duration::from_millis((millis as f64 * factor) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that delays grow exponentially up to the maximum.
+    #[test]
+    fn exponential_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(500),
+            jitter: 0.0,
+            ..Default::default()
+        };
+
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+        assert_eq!(policy.delay(4), Duration::from_millis(500));
+        assert_eq!(policy.delay(40), Duration::from_millis(500));
+
+        let policy = RetryPolicy {
+            jitter: 0.5,
+            ..policy
+        };
+
+        for _ in 0..100 {
+            let delay = policy.delay(2);
+            assert!(delay >= Duration::from_millis(100));
+            assert!(delay <= Duration::from_millis(200));
+        }
+
+        assert!(policy.should_retry(2, &CoreError::RequestTimeout));
+        assert!(!policy.should_retry(3, &CoreError::RequestTimeout));
+        assert!(!policy.should_retry(1, &CoreError::OperationAborted));
+        assert!(!RetryPolicy::default().should_retry(1, &CoreError::RequestTimeout));
+    }
+}
@@ -0,0 +1,173 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use errors::CoreError;
+use event_loop::CoreFuture;
+use futures::Future;
+use futures::sync::oneshot;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::{Rc, Weak};
+use utils::FutureExt;
+
+/// Default maximum number of requests a `Client` has in flight at once.
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 32;
+
+/// Statistics of the request scheduler of `Client`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RequestStats {
+    /// Number of requests sent to the network and awaiting response.
+    pub in_flight: usize,
+    /// Number of requests waiting for other requests to complete.
+    pub queued: usize,
+    /// Highest number of requests waiting at once so far.
+    pub max_queued: usize,
+    /// Number of GETs which joined an identical request already in flight
+    /// instead of sending their own.
+    pub coalesced: u64,
+    /// Number of requests sent to the network so far, including retries.
+    pub sent: u64,
+}
+
+// Caps the number of requests in flight. Requests over the cap wait in a
+// queue and are sent in order of arrival as the earlier ones complete.
+pub struct Scheduler {
+    max_in_flight: usize,
+    in_flight: usize,
+    queue: VecDeque<oneshot::Sender<Permit>>,
+    max_queued: usize,
+    coalesced: u64,
+    sent: u64,
+}
+
+impl Scheduler {
+    pub fn new(max_in_flight: usize) -> Self {
+        Scheduler {
+            max_in_flight,
+            in_flight: 0,
+            queue: VecDeque::new(),
+            max_queued: 0,
+            coalesced: 0,
+            sent: 0,
+        }
+    }
+
+    pub fn stats(&self) -> RequestStats {
+        RequestStats {
+            in_flight: self.in_flight,
+            queued: self.queue.len(),
+            max_queued: self.max_queued,
+            coalesced: self.coalesced,
+            sent: self.sent,
+        }
+    }
+
+    pub fn record_coalesced(&mut self) {
+        self.coalesced += 1;
+    }
+
+    pub fn record_sent(&mut self) {
+        self.sent += 1;
+    }
+}
+
+// Slot for a request in flight. Dropping it passes the slot on to the first
+// request in the queue, or frees it if the queue is empty.
+pub struct Permit(Option<Weak<RefCell<Scheduler>>>);
+
+impl Permit {
+    fn new(scheduler: &Rc<RefCell<Scheduler>>) -> Self {
+        Permit(Some(Rc::downgrade(scheduler)))
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(scheduler) = self.0.take().and_then(|weak| weak.upgrade()) {
+            grant(&scheduler);
+        }
+    }
+}
+
+// Returns a future resolving into a permit to send a request, once the
+// number of requests in flight drops below the cap.
+pub fn acquire(scheduler: &Rc<RefCell<Scheduler>>) -> Box<CoreFuture<Permit>> {
+    let mut s = scheduler.borrow_mut();
+
+    if s.in_flight < s.max_in_flight {
+        s.in_flight += 1;
+        return ok!(Permit::new(scheduler));
+    }
+
+    let (tx, rx) = oneshot::channel();
+    s.queue.push_back(tx);
+    s.max_queued = s.max_queued.max(s.queue.len());
+
+    rx.map_err(|_| CoreError::OperationAborted).into_box()
+}
+
+// Change the cap, sending queued requests if it has been raised.
+pub fn set_max_in_flight(scheduler: &Rc<RefCell<Scheduler>>, max_in_flight: usize) {
+    scheduler.borrow_mut().max_in_flight = max_in_flight;
+
+    loop {
+        {
+            let mut s = scheduler.borrow_mut();
+            if s.in_flight >= s.max_in_flight || s.queue.is_empty() {
+                return;
+            }
+            s.in_flight += 1;
+        }
+        grant(scheduler);
+    }
+}
+
+// Hands a slot over to the first live request in the queue. If there is none,
+// the slot is freed.
+fn grant(scheduler: &Rc<RefCell<Scheduler>>) {
+    loop {
+        // Don't keep the scheduler borrowed while sending, as a permit
+        // dropped along with an abandoned receiver borrows it as well.
+        let next = scheduler.borrow_mut().queue.pop_front();
+        match next {
+            Some(tx) => {
+                match tx.send(Permit::new(scheduler)) {
+                    Ok(()) => return,
+                    // The request has been abandoned while queued. The slot
+                    // is still ours, so don't let the permit release it.
+                    Err(mut permit) => permit.0 = None,
+                }
+            }
+            None => {
+                let mut s = scheduler.borrow_mut();
+                s.in_flight = s.in_flight.saturating_sub(1);
+                return;
+            }
+        }
+    }
+}
+
+// `CoreError` can't be cloned in general, but the errors a GET fails with
+// can, so they are passed on to each of the coalesced requests.
+pub fn share_error(error: &CoreError) -> CoreError {
+    match *error {
+        CoreError::RoutingClientError(ref error) => CoreError::RoutingClientError(error.clone()),
+        CoreError::RequestTimeout => CoreError::RequestTimeout,
+        CoreError::OperationAborted => CoreError::OperationAborted,
+        ref error => CoreError::Unexpected(error.to_string()),
+    }
+}
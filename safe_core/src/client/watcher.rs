@@ -0,0 +1,353 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Change notifications for `MutableData`. The network doesn't push changes
+//! to clients, so they are detected by periodically polling the data.
+
+use client::{Client, cancel};
+use errors::CoreError;
+use event_loop::CoreFuture;
+use futures::{Future, Stream, future, stream};
+use futures::future::Loop;
+use routing::{Value, XorName};
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::Duration;
+use tokio_core::reactor::Timeout;
+use utils::FutureExt;
+
+/// Default interval between polls while the watched data keeps changing.
+pub const DEFAULT_MIN_INTERVAL_MS: u64 = 1000;
+/// Default upper bound of the interval between polls.
+pub const DEFAULT_MAX_INTERVAL_MS: u64 = 60_000;
+
+/// Polling intervals of a watcher. After a poll which finds no change, the
+/// interval is doubled, up to `max_interval`. It drops back to `min_interval`
+/// as soon as a change is found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchConfig {
+    /// Shortest interval between two polls.
+    pub min_interval: Duration,
+    /// Longest interval between two polls.
+    pub max_interval: Duration,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        WatchConfig {
+            min_interval: Duration::from_millis(DEFAULT_MIN_INTERVAL_MS),
+            max_interval: Duration::from_millis(DEFAULT_MAX_INTERVAL_MS),
+        }
+    }
+}
+
+/// Change of a single entry, identified by its key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EntryChange {
+    /// The entry has been inserted.
+    Inserted(Vec<u8>),
+    /// The content of the entry has been updated.
+    Updated(Vec<u8>),
+    /// The entry has been deleted.
+    Deleted(Vec<u8>),
+}
+
+/// Changes of a watched `MutableData` since the previous notification.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MDataChange {
+    /// Name of the data.
+    pub name: XorName,
+    /// Type tag of the data.
+    pub type_tag: u64,
+    /// Version of the data the changes lead to.
+    pub version: u64,
+    /// The changed entries.
+    pub entries: Vec<EntryChange>,
+}
+
+/// Stream of changes returned by `Client::watch_mdata`.
+pub type WatchStream = Box<Stream<Item = MDataChange, Error = CoreError>>;
+
+type Snapshot = (u64, BTreeMap<Vec<u8>, Value>);
+
+struct State<T> {
+    client: Client<T>,
+    config: WatchConfig,
+    snapshots: HashMap<(XorName, u64), Snapshot>,
+    pending: VecDeque<MDataChange>,
+    interval: Duration,
+}
+
+/// Take a snapshot of each of the `targets` and return a stream of their
+/// subsequent changes. Polls which fail (e.g. on a timeout) are logged and
+/// retried after the next interval. The polling stops when the stream is
+/// dropped, or with `CoreError::OperationAborted` once the requests of
+/// `client` in progress when watching started are cancelled.
+pub fn watch<T: 'static>(
+    client: &Client<T>,
+    targets: Vec<(XorName, u64)>,
+    config: WatchConfig,
+) -> Box<CoreFuture<WatchStream>> {
+    let client = client.clone();
+    let cancel_signals = client.cancel_signals();
+
+    let snapshot_futs = targets
+        .into_iter()
+        .map(|(name, type_tag)| {
+            fetch_snapshot(&client, name, type_tag).map(move |snapshot| {
+                ((name, type_tag), snapshot)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    future::join_all(snapshot_futs)
+        .map(move |snapshots| {
+            let state = State {
+                client,
+                config,
+                snapshots: snapshots.into_iter().collect(),
+                pending: VecDeque::new(),
+                interval: config.min_interval,
+            };
+
+            let stream = stream::unfold(state, |mut state| match state.pending.pop_front() {
+                Some(change) => Some(future::ok((change, state)).into_box()),
+                None => Some(next_change(state)),
+            });
+            let stream = Box::new(stream) as WatchStream;
+
+            // The watcher is a request in progress until it's dropped, so
+            // cancelling stops it, even while no poll is in flight.
+            cancel_signals.into_iter().fold(stream, |stream, signal| {
+                let aborted = cancel::aborted(signal).into_stream();
+                Box::new(stream.select(aborted)) as WatchStream
+            })
+        })
+        .into_box()
+}
+
+// Poll the targets until some of them change. Returns the first change and
+// queues the others.
+fn next_change<T: 'static>(
+    state: State<T>,
+) -> Box<CoreFuture<(MDataChange, State<T>)>> {
+    future::loop_fn(state, |state| {
+        sleep(&state.client, state.interval)
+            .and_then(move |_| poll(state))
+            .map(|mut state| match state.pending.pop_front() {
+                Some(change) => Loop::Break((change, state)),
+                None => Loop::Continue(state),
+            })
+    }).into_box()
+}
+
+// Fetch the current state of all targets and diff it against their last
+// snapshots. Mutating entries doesn't bump the version of `MutableData` (only
+// changes to its permissions or owners do), so the entries are always listed.
+fn poll<T: 'static>(mut state: State<T>) -> Box<CoreFuture<State<T>>> {
+    let snapshot_futs = state
+        .snapshots
+        .keys()
+        .map(|&(name, type_tag)| {
+            fetch_snapshot(&state.client, name, type_tag).then(move |res| match res {
+                Ok(snapshot) => Ok::<_, CoreError>(Some(((name, type_tag), snapshot))),
+                Err(CoreError::OperationAborted) => Err(CoreError::OperationAborted),
+                Err(error) => {
+                    warn!("Failed to poll {:?} (tag {}): {:?}", name, type_tag, error);
+                    Ok(None)
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    future::join_all(snapshot_futs)
+        .map(move |snapshots| {
+            for ((name, type_tag), (version, entries)) in snapshots.into_iter().filter_map(|s| s) {
+                let changes = match state.snapshots.get(&(name, type_tag)) {
+                    Some(&(_, ref old_entries)) => diff(old_entries, &entries),
+                    None => Vec::new(),
+                };
+                if !changes.is_empty() {
+                    state.pending.push_back(MDataChange {
+                        name,
+                        type_tag,
+                        version,
+                        entries: changes,
+                    });
+                }
+                let _ = state.snapshots.insert((name, type_tag), (version, entries));
+            }
+
+            state.interval = if state.pending.is_empty() {
+                cmp::min(state.interval * 2, state.config.max_interval)
+            } else {
+                state.config.min_interval
+            };
+            state
+        })
+        .into_box()
+}
+
+fn fetch_snapshot<T: 'static>(
+    client: &Client<T>,
+    name: XorName,
+    type_tag: u64,
+) -> Box<CoreFuture<Snapshot>> {
+    // The version is fetched first, so if the data changes in between, the
+    // change is reported with the older version and the next poll catches up.
+    // The entries are always fetched from the network, bypassing the cache.
+    let client2 = client.clone();
+    client
+        .get_mdata_version(name, type_tag)
+        .and_then(move |version| {
+            client2.send_list_mdata_entries(name, type_tag).map(
+                move |entries| (version, entries),
+            )
+        })
+        .into_box()
+}
+
+fn sleep<T: 'static>(
+    client: &Client<T>,
+    duration: Duration,
+) -> Box<CoreFuture<()>> {
+    let timeout = match Timeout::new(duration, &client.inner().el_handle) {
+        Ok(timeout) => timeout,
+        Err(err) => {
+            return err!(CoreError::Unexpected(format!("Timeout create error: {:?}", err)));
+        }
+    };
+
+    timeout
+        .map_err(|err| CoreError::Unexpected(format!("Timeout fire error {:?}", err)))
+        .into_box()
+}
+
+// Entries with empty content are treated as deleted.
+fn diff(old: &BTreeMap<Vec<u8>, Value>, new: &BTreeMap<Vec<u8>, Value>) -> Vec<EntryChange> {
+    let mut changes = Vec::new();
+
+    for (key, value) in new {
+        let live = !value.content.is_empty();
+        match old.get(key) {
+            Some(old_value) if old_value.entry_version == value.entry_version => (),
+            Some(old_value) if !old_value.content.is_empty() => {
+                if live {
+                    changes.push(EntryChange::Updated(key.clone()));
+                } else {
+                    changes.push(EntryChange::Deleted(key.clone()));
+                }
+            }
+            _ if live => changes.push(EntryChange::Inserted(key.clone())),
+            _ => (),
+        }
+    }
+
+    for (key, value) in old {
+        if !value.content.is_empty() && !new.contains_key(key) {
+            changes.push(EntryChange::Deleted(key.clone()));
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DIR_TAG;
+    use rand;
+    use routing::{Action, EntryAction, MutableData, PermissionSet, User};
+    use utils::test_utils::random_client;
+
+    // Watch a `MutableData` and check that entry changes are reported.
+    #[test]
+    fn watch_entries() {
+        random_client(|client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+
+            let name: XorName = rand::random();
+            let owner = unwrap!(client.owner_key());
+            let perms = btree_map![
+                User::Anyone => PermissionSet::new().allow(Action::Insert)
+            ];
+            let entries = btree_map![
+                b"a".to_vec() => Value { content: b"a0".to_vec(), entry_version: 0 },
+                b"b".to_vec() => Value { content: b"b0".to_vec(), entry_version: 0 }
+            ];
+            let data = unwrap!(MutableData::new(
+                name,
+                DIR_TAG,
+                perms,
+                entries,
+                btree_set![owner],
+            ));
+
+            let config = WatchConfig {
+                min_interval: Duration::from_millis(10),
+                max_interval: Duration::from_millis(40),
+            };
+
+            client
+                .put_mdata(data)
+                .then(move |res| {
+                    unwrap!(res);
+                    client2.watch_mdata(vec![(name, DIR_TAG)], config)
+                })
+                .then(move |res| {
+                    let stream = unwrap!(res);
+                    let actions = btree_map![
+                        b"a".to_vec() => EntryAction::Update(Value {
+                            content: b"a1".to_vec(),
+                            entry_version: 1,
+                        }),
+                        b"b".to_vec() => EntryAction::Del(1),
+                        b"c".to_vec() => EntryAction::Ins(Value {
+                            content: b"c0".to_vec(),
+                            entry_version: 0,
+                        })
+                    ];
+                    client3
+                        .mutate_mdata_entries(name, DIR_TAG, actions)
+                        .map(move |_| stream)
+                })
+                .then(|res| {
+                    let stream = unwrap!(res);
+                    stream.into_future().map_err(|(err, _)| err)
+                })
+                .then(move |res| {
+                    let (change, _) = unwrap!(res);
+                    let change = unwrap!(change);
+                    assert_eq!(change.name, name);
+                    assert_eq!(change.type_tag, DIR_TAG);
+
+                    let mut entries = change.entries;
+                    entries.sort_by_key(|change| format!("{:?}", change));
+                    assert_eq!(
+                        entries,
+                        vec![
+                            EntryChange::Deleted(b"b".to_vec()),
+                            EntryChange::Inserted(b"c".to_vec()),
+                            EntryChange::Updated(b"a".to_vec()),
+                        ]
+                    );
+                    Ok::<_, CoreError>(())
+                })
+        })
+    }
+}
@@ -26,6 +26,17 @@ use std::path::PathBuf;
 pub struct Config {
     /// Developer options.
     pub dev: Option<DevConfig>,
+    /// Persistent cache of immutable data chunks. Disabled if not set.
+    pub chunk_cache: Option<ChunkCacheConfig>,
+}
+
+/// Configuration of the persistent cache of immutable data chunks.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChunkCacheConfig {
+    /// Directory to keep the cached chunks in.
+    pub path: String,
+    /// Maximum total size of the cached chunks, in bytes.
+    pub max_size: u64,
 }
 
 /// Extra configuration options intended for developers.
@@ -37,3 +37,6 @@ pub type SignSecretKey = [u8; sign::SECRETKEYBYTES];
 
 /// Array containing `XorName` bytes.
 pub type XorNameArray = [u8; XOR_NAME_LEN];
+
+/// Array containing SHA3-256 digest bytes.
+pub type Sha3Digest = [u8; 32];
@@ -76,6 +76,19 @@ pub struct MDataInfo {
     pub new_enc_nonce: SymNonce,
 }
 
+/// Represents an FFI-safe change of a `MutableData` entry.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MDataEntryChange {
+    /// Kind of the change: `ENTRY_CHANGE_INSERTED`, `ENTRY_CHANGE_UPDATED`
+    /// or `ENTRY_CHANGE_DELETED`.
+    pub kind: u32,
+    /// Key pointer.
+    pub key_ptr: *const u8,
+    /// Key length.
+    pub key_len: usize,
+}
+
 /// Returns true if this crate was compiled against mock-routing.
 #[no_mangle]
 pub extern "C" fn is_mock_build() -> bool {
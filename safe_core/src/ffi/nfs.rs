@@ -15,7 +15,10 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 
-use arrays::XorNameArray;
+use arrays::{Sha3Digest, XorNameArray};
+use ffi_utils::vec_into_raw_parts;
+use std::ffi::CString;
+use std::os::raw::c_char;
 
 /// FFI-wrapper for `File`.
 #[repr(C)]
@@ -38,17 +41,167 @@ pub struct File {
     pub user_metadata_cap: usize,
     /// Name of the `ImmutableData` containing the content of this file.
     pub data_map_name: XorNameArray,
+    /// Pointer to the UTF-8 encoded MIME type. Empty if not set.
+    pub mime_type_ptr: *mut u8,
+    /// Size of the MIME type.
+    pub mime_type_len: usize,
+    /// Capacity of the MIME type (internal field).
+    pub mime_type_cap: usize,
+    /// Flag indicating whether the mode is set.
+    pub has_mode: bool,
+    /// POSIX-like mode bits. Meaningful only if `has_mode` is `true`.
+    pub mode: u32,
+    /// Pointer to the extended attributes.
+    pub xattrs_ptr: *mut XAttr,
+    /// Number of the extended attributes.
+    pub xattrs_len: usize,
+    /// Capacity of the extended attributes (internal field).
+    pub xattrs_cap: usize,
+    /// Flag indicating whether the digest is known.
+    pub has_digest: bool,
+    /// SHA3-256 digest of the content. Meaningful only if `has_digest` is `true`.
+    pub digest: Sha3Digest,
+    /// Flag indicating whether the content is stored inline in the file.
+    pub has_inline_content: bool,
+    /// Pointer to the inline content, encrypted if the file was written with
+    /// an encryption key. Meaningful only if `has_inline_content` is `true`.
+    pub inline_content_ptr: *mut u8,
+    /// Size of the inline content.
+    pub inline_content_len: usize,
+    /// Capacity of the inline content (internal field).
+    pub inline_content_cap: usize,
+    /// Flag indicating whether only a part of the content is stored.
+    pub has_data_size: bool,
+    /// Number of bytes of the content actually stored. The rest of the file
+    /// reads as zeros.
+    pub data_size: u64,
 }
 
 impl Drop for File {
     #[allow(unsafe_code)]
     fn drop(&mut self) {
-        let _ = unsafe {
-            Vec::from_raw_parts(
+        unsafe {
+            let _ = Vec::from_raw_parts(
                 self.user_metadata_ptr,
                 self.user_metadata_len,
                 self.user_metadata_cap,
-            )
-        };
+            );
+            let _ = Vec::from_raw_parts(self.mime_type_ptr, self.mime_type_len, self.mime_type_cap);
+            let _ = Vec::from_raw_parts(self.xattrs_ptr, self.xattrs_len, self.xattrs_cap);
+            let _ = Vec::from_raw_parts(
+                self.inline_content_ptr,
+                self.inline_content_len,
+                self.inline_content_cap,
+            );
+        }
+    }
+}
+
+/// Extended attribute of a file.
+#[repr(C)]
+pub struct XAttr {
+    /// Pointer to the UTF-8 encoded name.
+    pub name_ptr: *mut u8,
+    /// Size of the name.
+    pub name_len: usize,
+    /// Capacity of the name (internal field).
+    pub name_cap: usize,
+    /// Pointer to the value.
+    pub value_ptr: *mut u8,
+    /// Size of the value.
+    pub value_len: usize,
+    /// Capacity of the value (internal field).
+    pub value_cap: usize,
+}
+
+impl XAttr {
+    /// Construct the extended attribute, consuming the name and the value.
+    pub fn new(name: Vec<u8>, value: Vec<u8>) -> Self {
+        let (name_ptr, name_len, name_cap) = vec_into_raw_parts(name);
+        let (value_ptr, value_len, value_cap) = vec_into_raw_parts(value);
+
+        XAttr {
+            name_ptr,
+            name_len,
+            name_cap,
+            value_ptr,
+            value_len,
+            value_cap,
+        }
+    }
+}
+
+impl Drop for XAttr {
+    #[allow(unsafe_code)]
+    fn drop(&mut self) {
+        unsafe {
+            let _ = Vec::from_raw_parts(self.name_ptr, self.name_len, self.name_cap);
+            let _ = Vec::from_raw_parts(self.value_ptr, self.value_len, self.value_cap);
+        }
+    }
+}
+
+/// FFI-wrapper for the usage of a directory.
+#[repr(C)]
+pub struct Usage {
+    /// Number of files in the directory.
+    pub files: u64,
+    /// Total size of the files in bytes.
+    pub size: u64,
+    /// Flag indicating whether the number of files is limited.
+    pub has_max_files: bool,
+    /// Maximum number of files. Meaningful only if `has_max_files` is `true`.
+    pub max_files: u64,
+    /// Flag indicating whether the total size of the files is limited.
+    pub has_max_size: bool,
+    /// Maximum total size of the files. Meaningful only if `has_max_size` is
+    /// `true`.
+    pub max_size: u64,
+}
+
+/// File entry of a directory listing.
+#[repr(C)]
+pub struct FileEntry {
+    /// File name as UTF-8 encoded null-terminated string.
+    pub name: *const c_char,
+    /// Version of the directory entry.
+    pub version: u64,
+    /// The file itself.
+    pub file: File,
+}
+
+impl Drop for FileEntry {
+    #[allow(unsafe_code)]
+    fn drop(&mut self) {
+        let _ = unsafe { CString::from_raw(self.name as *mut _) };
+    }
+}
+
+/// Previous version of a file.
+#[repr(C)]
+pub struct FileVersion {
+    /// Version of the directory entry while this was the current version.
+    pub version: u64,
+    /// The file itself.
+    pub file: File,
+}
+
+/// Soft-deleted file in the trash of a directory.
+#[repr(C)]
+pub struct TrashEntry {
+    /// File name as UTF-8 encoded null-terminated string.
+    pub name: *const c_char,
+    /// Deletion time (seconds part).
+    pub deleted_sec: i64,
+    /// Deletion time (nanoseconds part).
+    pub deleted_nsec: u32,
+    /// The file itself.
+    pub file: File,
+}
+
+impl Drop for TrashEntry {
+    #[allow(unsafe_code)]
+    fn drop(&mut self) {
+        let _ = unsafe { CString::from_raw(self.name as *mut _) };
     }
 }
@@ -107,10 +107,14 @@ extern crate config_file_handler;
 extern crate ffi_utils;
 #[cfg(feature = "use-mock-routing")]
 extern crate fs2;
+#[cfg(feature = "use-fuse")]
+extern crate fuse;
 extern crate futures;
-#[cfg(feature = "use-mock-routing")]
+extern crate futures_cpupool;
 #[macro_use]
 extern crate lazy_static;
+#[cfg(feature = "use-fuse")]
+extern crate libc;
 #[macro_use]
 extern crate log;
 extern crate lru_cache;
@@ -124,6 +128,8 @@ extern crate serde_derive;
 extern crate serde_json;
 extern crate rust_sodium;
 extern crate self_encryption;
+#[cfg(feature = "use-fuse")]
+extern crate time;
 extern crate tiny_keccak;
 extern crate tokio_core;
 #[macro_use]
@@ -160,7 +166,8 @@ mod client;
 mod errors;
 mod event;
 
-pub use self::client::{Client, ClientKeys, MDataInfo, mdata_info, recovery};
+pub use self::client::{CancelHandle, ChunkCache, Client, ClientKeys, MDataCacheConfig, MDataInfo,
+                       RequestStats, RetryPolicy, mdata_info, recovery, watcher};
 #[cfg(feature = "use-mock-routing")]
 pub use self::client::{MockRouting, mock_vault_path};
 pub use self::errors::CoreError;
@@ -0,0 +1,37 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+/// What `file_helper::update_checked` does when the file has been modified
+/// since it was read, e.g. by another device of the same user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Fail with `NfsError::Conflict`, leaving the resolution to the caller.
+    Fail,
+    /// Keep the concurrent modification and save the rejected file as a
+    /// conflict copy next to it, e.g. `notes (conflict 1).txt` for
+    /// `notes.txt`. The first free number is used.
+    KeepCopy,
+}
+
+/// Name of the `n`-th conflict copy of the file `name`. The suffix goes
+/// before the extension, so the copy still opens with the same application.
+pub fn copy_name(name: &str, n: u64) -> String {
+    match name.rfind('.') {
+        Some(pos) if pos > 0 => format!("{} (conflict {}){}", &name[..pos], n, &name[pos..]),
+        _ => format!("{} (conflict {})", name, n),
+    }
+}
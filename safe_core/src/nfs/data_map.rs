@@ -22,10 +22,10 @@ use crypto::shared_secretbox;
 use futures::{Future, future};
 use immutable_data;
 use maidsafe_utilities::serialisation::{deserialise, serialise};
-use nfs::NfsFuture;
+use nfs::{File, NfsFuture};
 use routing::XorName;
 use self_encryption::DataMap;
-use utils::FutureExt;
+use utils::{self, FutureExt};
 
 // Get `DataMap` from the network.
 // If the `DataMap` is encrypted, an `encryption_key` must be passed in to decrypt it.
@@ -40,6 +40,27 @@ pub fn get<T: 'static>(
         .into_box()
 }
 
+// Get the `DataMap` of the file. The content of small files is stored inline
+// in the file (encrypted if `encryption_key` is passed in) and files with no
+// stored data have no `DataMap` on the network, so in these cases the
+// `DataMap` is built locally instead of being fetched.
+pub fn get_for_file<T: 'static>(
+    client: &Client<T>,
+    file: &File,
+    encryption_key: Option<shared_secretbox::Key>,
+) -> Box<NfsFuture<DataMap>> {
+    if file.data_size() == 0 {
+        return ok!(DataMap::None);
+    }
+    match (file.inline_content(), encryption_key) {
+        (Some(content), Some(key)) => {
+            ok!(DataMap::Content(fry!(utils::symmetric_decrypt(content, &key))))
+        }
+        (Some(content), None) => ok!(DataMap::Content(content.to_vec())),
+        (None, encryption_key) => get(client, file.data_map_name(), encryption_key),
+    }
+}
+
 // Put `DataMap` on the network.
 // If `encryption_key` is passed in, the `DataMap` will be encrypted.
 pub fn put<T: 'static>(
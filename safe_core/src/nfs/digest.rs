@@ -0,0 +1,85 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use futures::Future;
+use futures::future::{self, Loop};
+use nfs::{NfsError, NfsFuture};
+use self_encryption::SelfEncryptor;
+use self_encryption_storage::SelfEncryptionStorage;
+use std::cmp;
+use std::rc::Rc;
+use tiny_keccak::Keccak;
+use utils::FutureExt;
+
+// Size of the chunks in which the content is read when computing its digest.
+const READ_CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// Create a new hasher computing the SHA3-256 digest of the content.
+pub fn hasher() -> Keccak {
+    Keccak::new_sha3_256()
+}
+
+/// Finish the computation of the digest.
+pub fn finalise(hasher: Keccak) -> [u8; 32] {
+    let mut digest = [0; 32];
+    hasher.finalize(&mut digest);
+    digest
+}
+
+/// Feed the whole content read by the self-encryptor into `hasher`, chunk by
+/// chunk.
+pub fn hash_content<T: 'static>(
+    self_encryptor: Rc<SelfEncryptor<SelfEncryptionStorage<T>>>,
+    hasher: Keccak,
+) -> Box<NfsFuture<Keccak>> {
+    let size = self_encryptor.len();
+
+    future::loop_fn((0, hasher), move |(position, mut hasher)| {
+        let length = cmp::min(READ_CHUNK_SIZE, size - position);
+        if length == 0 {
+            return ok!(Loop::Break(hasher));
+        }
+
+        self_encryptor
+            .read(position, length)
+            .map_err(NfsError::from)
+            .map(move |data| {
+                hasher.update(&data);
+
+                let position = position + length;
+                if position < size {
+                    Loop::Continue((position, hasher))
+                } else {
+                    Loop::Break(hasher)
+                }
+            })
+            .into_box()
+    }).into_box()
+}
+
+/// Feed `length` zero bytes into `hasher`, as read from a hole in a sparse
+/// file.
+pub fn hash_zeros(mut hasher: Keccak, length: u64) -> Keccak {
+    let zeros = [0u8; 4096];
+    let mut remaining = length;
+    while remaining > 0 {
+        let length = cmp::min(remaining, zeros.len() as u64);
+        hasher.update(&zeros[..length as usize]);
+        remaining -= length;
+    }
+    hasher
+}
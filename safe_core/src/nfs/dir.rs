@@ -18,11 +18,31 @@
 use client::{Client, MDataInfo};
 use errors::CoreError;
 use futures::Future;
+use ipc::resp::METADATA_KEY;
 use nfs::{NfsError, NfsFuture};
-use routing::{ClientError, MutableData, PermissionSet, User, Value};
+use routing::{ClientError, EntryAction, MutableData, PermissionSet, User, Value};
 use std::collections::BTreeMap;
 use utils::FutureExt;
 
+// Plaintext keys starting with a NUL byte are reserved for the entries
+// managed by nfs itself, so they can never clash with a file name.
+const RESERVED_KEY_MARKER: u8 = 0;
+// Prefix of the keys of entries pointing to subdirectories.
+const SUBDIR_KEY_PREFIX: &'static [u8] = b"\0dir:";
+// Prefix of the keys of entries holding soft links.
+const LINK_KEY_PREFIX: &'static [u8] = b"\0link:";
+// Prefix of the keys of entries holding the previous versions of a file.
+const HISTORY_KEY_PREFIX: &'static [u8] = b"\0history:";
+// Prefix of the keys of entries holding the soft-deleted versions of a file.
+const TRASH_KEY_PREFIX: &'static [u8] = b"\0trash:";
+
+/// Plaintext key of the entry holding the usage record of the directory.
+pub const USAGE_KEY: &'static [u8] = b"\0usage";
+/// Plaintext key of the entry holding the shard table of the directory.
+pub const SHARDS_KEY: &'static [u8] = b"\0shards";
+/// Plaintext key of the entry holding the index of a public file tree.
+pub const INDEX_KEY: &'static [u8] = b"\0index";
+
 /// Create a new directory based on the provided `MDataInfo`
 pub fn create_dir<T: 'static>(
     client: &Client<T>,
@@ -48,3 +68,169 @@ pub fn create_dir<T: 'static>(
         .map_err(NfsError::from)
         .into_box()
 }
+
+/// Returns the plaintext entry key under which the subdirectory `name` is stored.
+pub fn subdir_key(name: &str) -> Vec<u8> {
+    reserved_key(SUBDIR_KEY_PREFIX, name)
+}
+
+/// Returns the plaintext entry key under which the soft link `name` is stored.
+pub fn link_key(name: &str) -> Vec<u8> {
+    reserved_key(LINK_KEY_PREFIX, name)
+}
+
+/// If `key` is the plaintext key of a soft link entry, returns the name of the
+/// link.
+pub fn link_name(key: &[u8]) -> Option<&[u8]> {
+    if key.starts_with(LINK_KEY_PREFIX) {
+        Some(&key[LINK_KEY_PREFIX.len()..])
+    } else {
+        None
+    }
+}
+
+/// Returns the plaintext entry key under which the history of the file `name`
+/// is stored.
+pub fn history_key(name: &str) -> Vec<u8> {
+    reserved_key(HISTORY_KEY_PREFIX, name)
+}
+
+/// Returns the plaintext entry key under which the soft-deleted versions of
+/// the file `name` are stored.
+pub fn trash_key(name: &str) -> Vec<u8> {
+    reserved_key(TRASH_KEY_PREFIX, name)
+}
+
+/// If `key` is the plaintext key of a trash entry, returns the name of the
+/// file.
+pub fn trash_name(key: &[u8]) -> Option<&[u8]> {
+    if key.starts_with(TRASH_KEY_PREFIX) {
+        Some(&key[TRASH_KEY_PREFIX.len()..])
+    } else {
+        None
+    }
+}
+
+/// If `key` is the plaintext key of a subdirectory entry, returns the name of
+/// the subdirectory.
+pub fn subdir_name(key: &[u8]) -> Option<&[u8]> {
+    if key.starts_with(SUBDIR_KEY_PREFIX) {
+        Some(&key[SUBDIR_KEY_PREFIX.len()..])
+    } else {
+        None
+    }
+}
+
+/// Returns `true` if `key` is not the key of a file entry.
+pub fn is_reserved_key(key: &[u8]) -> bool {
+    key.first() == Some(&RESERVED_KEY_MARKER) || key == METADATA_KEY
+}
+
+/// Fetch a single entry of the directory and decrypt it. Returns `None` if
+/// there is no such entry. Deleted entries are returned with empty content.
+pub fn fetch_entry<T: 'static>(
+    client: &Client<T>,
+    dir: &MDataInfo,
+    key: &[u8],
+) -> Box<NfsFuture<Option<Value>>> {
+    let enc_key = fry!(dir.enc_entry_key(key));
+    let dir = dir.clone();
+
+    client
+        .get_mdata_value(dir.name, dir.type_tag, enc_key)
+        .then(move |res| match res {
+            Ok(ref value) if value.content.is_empty() => Ok(Some(value.clone())),
+            Ok(value) => {
+                let content = dir.decrypt(&value.content)?;
+                Ok(Some(Value {
+                    content,
+                    entry_version: value.entry_version,
+                }))
+            }
+            Err(CoreError::RoutingClientError(ClientError::NoSuchEntry)) => Ok(None),
+            Err(error) => Err(NfsError::from(error)),
+        })
+        .into_box()
+}
+
+/// Fetch all live entries of the directory and decrypt them. Deleted entries
+/// and entries which can't be decrypted using `dir` (e.g. the unencrypted
+/// metadata entry of a private directory) are skipped.
+pub fn list_entries<T: 'static>(
+    client: &Client<T>,
+    dir: &MDataInfo,
+) -> Box<NfsFuture<BTreeMap<Vec<u8>, Value>>> {
+    let dir = dir.clone();
+
+    client
+        .list_mdata_entries(dir.name, dir.type_tag)
+        .map(move |entries| {
+            entries
+                .into_iter()
+                .filter(|&(_, ref value)| !value.content.is_empty())
+                .filter_map(|(key, value)| {
+                    match (dir.decrypt(&key), dir.decrypt(&value.content)) {
+                        (Ok(key), Ok(content)) => Some((
+                            key,
+                            Value {
+                                content,
+                                entry_version: value.entry_version,
+                            },
+                        )),
+                        _ => None,
+                    }
+                })
+                .collect()
+        })
+        .map_err(NfsError::from)
+        .into_box()
+}
+
+/// Prepare an action inserting `content` under the plaintext `key`. If the
+/// entry has been deleted before, its slot is reused by updating it instead.
+/// Returns `None` if a live entry with this key already exists.
+pub fn insert_action<T: 'static>(
+    client: &Client<T>,
+    dir: &MDataInfo,
+    key: &[u8],
+    content: &[u8],
+) -> Box<NfsFuture<Option<(Vec<u8>, EntryAction)>>> {
+    let dir2 = dir.clone();
+    let key2 = key.to_vec();
+    let content = content.to_vec();
+
+    fetch_entry(client, dir, key)
+        .and_then(move |value| insert_action_over(&dir2, &key2, &content, value.as_ref()))
+        .into_box()
+}
+
+/// Same as `insert_action`, but uses the already fetched `current` value of
+/// the entry.
+pub fn insert_action_over(
+    dir: &MDataInfo,
+    key: &[u8],
+    content: &[u8],
+    current: Option<&Value>,
+) -> Result<Option<(Vec<u8>, EntryAction)>, NfsError> {
+    let entry_version = match current {
+        Some(value) if !value.content.is_empty() => return Ok(None),
+        Some(value) => value.entry_version + 1,
+        None => 0,
+    };
+    let value = Value {
+        content: dir.enc_entry_value(content)?,
+        entry_version,
+    };
+    let action = if entry_version == 0 {
+        EntryAction::Ins(value)
+    } else {
+        EntryAction::Update(value)
+    };
+    Ok(Some((dir.enc_entry_key(key)?, action)))
+}
+
+fn reserved_key(prefix: &[u8], name: &str) -> Vec<u8> {
+    let mut key = prefix.to_vec();
+    key.extend_from_slice(name.as_bytes());
+    key
+}
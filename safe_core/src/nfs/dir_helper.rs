@@ -0,0 +1,566 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+use client::{Client, MDataInfo};
+use futures::{Future, future};
+use futures::future::{Either, Loop};
+use ipc::resp::METADATA_KEY;
+use maidsafe_utilities::serialisation::{deserialise, serialise};
+use nfs::{File, Link, NfsError, NfsFuture, create_dir, dir};
+use nfs::{file_helper, shard};
+use routing::{EntryAction, EntryActions, Value};
+use std::collections::BTreeMap;
+use utils::FutureExt;
+
+/// Entry of a directory as seen by the path-based API.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Node {
+    /// Regular file.
+    File {
+        /// Version of the directory entry.
+        version: u64,
+        /// The file itself.
+        file: File,
+    },
+    /// Subdirectory.
+    Dir {
+        /// Version of the directory entry.
+        version: u64,
+        /// Location of the subdirectory.
+        info: MDataInfo,
+    },
+    /// Soft link.
+    Link {
+        /// Version of the directory entry.
+        version: u64,
+        /// Target of the link.
+        link: Link,
+    },
+}
+
+/// Get the directory at `path`, relative to `root`. Paths are `/`-separated;
+/// empty components are ignored, so both `""` and `"/"` refer to `root`.
+pub fn open_dir<S, T>(client: Client<T>, root: MDataInfo, path: S) -> Box<NfsFuture<MDataInfo>>
+where
+    S: AsRef<str>,
+    T: 'static,
+{
+    walk(client, root, components(path.as_ref()))
+}
+
+/// Create the directory at `path`. The new directory inherits the permissions
+/// of its parent and is encrypted if the parent is. If `parents` is `true`,
+/// missing intermediate directories are created too and an already existing
+/// directory at `path` is not considered an error (like `mkdir -p`).
+pub fn mkdir<S, T>(
+    client: Client<T>,
+    root: MDataInfo,
+    path: S,
+    parents: bool,
+) -> Box<NfsFuture<MDataInfo>>
+where
+    S: AsRef<str>,
+    T: 'static,
+{
+    let path = path.as_ref();
+    trace!("Creating directory '{}'", path);
+
+    let mut components = components(path);
+    let name = match components.pop() {
+        Some(name) => name,
+        None if parents => return ok!(root),
+        None => return err!(NfsError::DirectoryExists),
+    };
+
+    if !parents {
+        let client2 = client.clone();
+        return walk(client, root, components)
+            .and_then(move |parent| create_subdir(&client2, &parent, &name))
+            .into_box();
+    }
+
+    components.push(name);
+    future::loop_fn((root, components.into_iter()), move |(dir, mut components)| {
+        let next = components.next();
+        match next {
+            Some(name) => {
+                let client = client.clone();
+                let fut = fetch_subdir(&client, &dir, &name).and_then(move |subdir| {
+                    match subdir {
+                        Some((_, _, child)) => ok!(child),
+                        None => create_subdir(&client, &dir, &name),
+                    }
+                });
+                Either::A(fut.map(move |child| Loop::Continue((child, components))))
+            }
+            None => Either::B(future::ok(Loop::Break(dir))),
+        }
+    }).into_box()
+}
+
+/// Get the file or directory at `path`.
+pub fn stat<S, T>(client: Client<T>, root: MDataInfo, path: S) -> Box<NfsFuture<Node>>
+where
+    S: AsRef<str>,
+    T: 'static,
+{
+    let mut components = components(path.as_ref());
+    let name = match components.pop() {
+        Some(name) => name,
+        None => {
+            return ok!(Node::Dir {
+                version: 0,
+                info: root,
+            })
+        }
+    };
+
+    let client2 = client.clone();
+    walk(client, root, components)
+        .and_then(move |parent| lookup(&client2, &parent, &name))
+        .and_then(|node| node.map(|(_, node)| node).ok_or(NfsError::FileNotFound))
+        .into_box()
+}
+
+/// List the files and subdirectories of the directory at `path`.
+pub fn list<S, T>(
+    client: Client<T>,
+    root: MDataInfo,
+    path: S,
+) -> Box<NfsFuture<BTreeMap<String, Node>>>
+where
+    S: AsRef<str>,
+    T: 'static,
+{
+    let client2 = client.clone();
+    walk(client, root, components(path.as_ref()))
+        .and_then(move |dir| shard::list_entries(&client2, &dir))
+        .map(|entries| {
+            entries
+                .into_iter()
+                .filter_map(|(key, value)| decode_node(&key, &value))
+                .collect()
+        })
+        .into_box()
+}
+
+/// Move the file or directory at `src` to `dst`. Within a single directory
+/// this is done using one mutation; across directories the entry is first
+/// inserted at `dst` and then removed from `src`. Files are moved using
+/// `file_helper::move_to`. Fails if anything already exists at `dst`;
+/// renaming a path to itself does nothing.
+pub fn rename<S, D, T>(
+    client: Client<T>,
+    root: MDataInfo,
+    src: S,
+    dst: D,
+) -> Box<NfsFuture<()>>
+where
+    S: AsRef<str>,
+    D: AsRef<str>,
+    T: 'static,
+{
+    let mut src = components(src.as_ref());
+    let mut dst = components(dst.as_ref());
+    if src == dst {
+        return ok!(());
+    }
+    if !src.is_empty() && dst.starts_with(&src) {
+        // Moving a directory into itself would detach it into a cycle.
+        return err!(NfsError::InvalidPath);
+    }
+
+    let (src_name, dst_name) = match (src.pop(), dst.pop()) {
+        (Some(src_name), Some(dst_name)) => (src_name, dst_name),
+        _ => return err!(NfsError::InvalidPath),
+    };
+
+    let client2 = client.clone();
+    let client3 = client.clone();
+
+    walk(client.clone(), root.clone(), src)
+        .join(walk(client, root, dst))
+        .and_then(move |(src_dir, dst_dir)| {
+            // Files, subdirectories and soft links share the namespace of a
+            // directory, so `dst` must be free in all of them.
+            let dst_fut = lookup(&client2, &dst_dir, &dst_name).and_then(|node| match node {
+                Some((_, Node::Dir { .. })) => Err(NfsError::DirectoryExists),
+                Some((_, Node::File { .. })) |
+                Some((_, Node::Link { .. })) => Err(NfsError::FileExists),
+                None => Ok(()),
+            });
+
+            lookup(&client2, &src_dir, &src_name)
+                .and_then(|node| node.ok_or(NfsError::FileNotFound))
+                .join(dst_fut)
+                .map(move |(node, _)| (src_dir, dst_dir, src_name, dst_name, node))
+        })
+        .and_then(move |(src_dir, dst_dir, src_name, dst_name, (holder, node))| {
+            let (src_key, dst_key, version, content) = match node {
+                Node::File { .. } => {
+                    // `file_helper` keeps the usage records of the directories
+                    // updated.
+                    return file_helper::move_to(client3, src_dir, src_name, dst_dir, dst_name)
+                        .map(|_| ())
+                        .into_box();
+                }
+                Node::Dir { version, info } => (
+                    dir::subdir_key(&src_name),
+                    dir::subdir_key(&dst_name),
+                    version,
+                    fry!(serialise(&info)),
+                ),
+                Node::Link { version, link } => (
+                    dir::link_key(&src_name),
+                    dir::link_key(&dst_name),
+                    version,
+                    fry!(serialise(&link)),
+                ),
+            };
+            let src_action = (
+                fry!(holder.enc_entry_key(&src_key)),
+                EntryAction::Del(version + 1),
+            );
+            let client = client3.clone();
+
+            shard::insert(&client3, &dst_dir, &dst_key.clone(), move |target, current| {
+                let dst_action = match fry!(dir::insert_action_over(
+                    &target,
+                    &dst_key,
+                    &content,
+                    current.as_ref(),
+                )) {
+                    Some(dst_action) => dst_action,
+                    None => return err!(NfsError::FileExists),
+                };
+                let (src_key, src_action) = src_action.clone();
+
+                let fut = if holder.name == target.name && holder.type_tag == target.type_tag {
+                    let actions = btree_map![
+                        src_key => src_action,
+                        dst_action.0 => dst_action.1
+                    ];
+                    client.mutate_mdata_entries(target.name, target.type_tag, actions)
+                } else {
+                    let client2 = client.clone();
+                    let holder = holder.clone();
+                    client
+                        .mutate_mdata_entries(
+                            target.name,
+                            target.type_tag,
+                            btree_map![dst_action.0 => dst_action.1],
+                        )
+                        .and_then(move |_| {
+                            client2.mutate_mdata_entries(
+                                holder.name,
+                                holder.type_tag,
+                                btree_map![src_key => src_action],
+                            )
+                        })
+                        .into_box()
+                };
+                fut.map_err(NfsError::from).into_box()
+            })
+        })
+        .into_box()
+}
+
+/// Remove the directory at `path`. Unless `recursive` is `true`, the directory
+/// has to be empty. Note the underlying `MutableData` of removed directories
+/// can't be deleted from the network; their entries are deleted instead.
+pub fn rmdir<S, T>(
+    client: Client<T>,
+    root: MDataInfo,
+    path: S,
+    recursive: bool,
+) -> Box<NfsFuture<()>>
+where
+    S: AsRef<str>,
+    T: 'static,
+{
+    let path = path.as_ref();
+    trace!("Removing directory '{}'", path);
+
+    let mut components = components(path);
+    let name = match components.pop() {
+        Some(name) => name,
+        None => return err!(NfsError::from("Can't remove the root directory")),
+    };
+
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let client4 = client.clone();
+
+    walk(client, root, components)
+        .and_then(move |parent| {
+            fetch_subdir(&client2, &parent, &name).and_then(move |subdir| {
+                let (holder, version, child) = subdir.ok_or(NfsError::DirectoryNotFound)?;
+                Ok((holder, name, version, child))
+            })
+        })
+        .and_then(move |(holder, name, version, child)| {
+            let fut = if recursive {
+                clear_dir(client3, child)
+            } else {
+                shard::list_entries(&client3, &child)
+                    .and_then(|entries| {
+                        let non_empty = entries.keys().any(|key| {
+                            dir::subdir_name(key).is_some() || dir::link_name(key).is_some() ||
+                                !dir::is_reserved_key(key)
+                        });
+                        if non_empty {
+                            Err(NfsError::DirectoryNotEmpty)
+                        } else {
+                            Ok(())
+                        }
+                    })
+                    .into_box()
+            };
+            fut.map(move |_| (holder, name, version))
+        })
+        .and_then(move |(holder, name, version)| {
+            let key = fry!(holder.enc_entry_key(&dir::subdir_key(&name)));
+            client4
+                .mutate_mdata_entries(
+                    holder.name,
+                    holder.type_tag,
+                    EntryActions::new().del(key, version + 1).into(),
+                )
+                .map_err(NfsError::from)
+                .into_box()
+        })
+        .into_box()
+}
+
+fn components(path: &str) -> Vec<String> {
+    path.split('/')
+        .filter(|component| !component.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+// Follow the given path components starting at `root`.
+fn walk<T: 'static>(
+    client: Client<T>,
+    root: MDataInfo,
+    components: Vec<String>,
+) -> Box<NfsFuture<MDataInfo>> {
+    future::loop_fn((root, components.into_iter()), move |(dir, mut components)| {
+        let next = components.next();
+        match next {
+            Some(name) => {
+                let fut = fetch_subdir(&client, &dir, &name).and_then(move |subdir| {
+                    let (_, _, child) = subdir.ok_or(NfsError::DirectoryNotFound)?;
+                    Ok(Loop::Continue((child, components)))
+                });
+                Either::A(fut)
+            }
+            None => Either::B(future::ok(Loop::Break(dir))),
+        }
+    }).into_box()
+}
+
+// Fetch the subdirectory `name` of `parent` together with the directory or
+// shard holding its entry and the version of the entry.
+fn fetch_subdir<T: 'static>(
+    client: &Client<T>,
+    parent: &MDataInfo,
+    name: &str,
+) -> Box<NfsFuture<Option<(MDataInfo, u64, MDataInfo)>>> {
+    shard::locate(client, parent, &dir::subdir_key(name))
+        .and_then(|(holder, value)| match value {
+            Some(ref value) if !value.content.is_empty() => {
+                let info = deserialise::<MDataInfo>(&value.content)?;
+                Ok(Some((holder, value.entry_version, info)))
+            }
+            _ => Ok(None),
+        })
+        .into_box()
+}
+
+// Find a subdirectory, a file or a soft link with the given name, together
+// with the directory or shard holding its entry.
+fn lookup<T: 'static>(
+    client: &Client<T>,
+    parent: &MDataInfo,
+    name: &str,
+) -> Box<NfsFuture<Option<(MDataInfo, Node)>>> {
+    let file_fut = shard::locate(client, parent, name.as_bytes());
+    let link_fut = shard::locate(client, parent, &dir::link_key(name));
+
+    fetch_subdir(client, parent, name)
+        .join3(file_fut, link_fut)
+        .and_then(|(subdir, file, link)| {
+            if let Some((holder, version, info)) = subdir {
+                return Ok(Some((holder, Node::Dir { version, info })));
+            }
+
+            match file {
+                (ref holder, Some(ref value)) if !value.content.is_empty() => {
+                    let node = Node::File {
+                        version: value.entry_version,
+                        file: File::deserialise(&value.content)?,
+                    };
+                    return Ok(Some((holder.clone(), node)));
+                }
+                _ => (),
+            }
+
+            match link {
+                (ref holder, Some(ref value)) if !value.content.is_empty() => {
+                    let node = Node::Link {
+                        version: value.entry_version,
+                        link: deserialise(&value.content)?,
+                    };
+                    Ok(Some((holder.clone(), node)))
+                }
+                _ => Ok(None),
+            }
+        })
+        .into_box()
+}
+
+fn decode_node(key: &[u8], value: &Value) -> Option<(String, Node)> {
+    let version = value.entry_version;
+    let (name, node) = if let Some(name) = dir::subdir_name(key) {
+        match deserialise(&value.content) {
+            Ok(info) => (name, Node::Dir { version, info }),
+            Err(_) => return None,
+        }
+    } else if let Some(name) = dir::link_name(key) {
+        match deserialise(&value.content) {
+            Ok(link) => (name, Node::Link { version, link }),
+            Err(_) => return None,
+        }
+    } else if !dir::is_reserved_key(key) {
+        match File::deserialise(&value.content) {
+            Ok(file) => (key, Node::File { version, file }),
+            Err(_) => return None,
+        }
+    } else {
+        return None;
+    };
+
+    String::from_utf8(name.to_vec()).ok().map(|name| (name, node))
+}
+
+// Create a new subdirectory and link it into `parent`.
+fn create_subdir<T: 'static>(
+    client: &Client<T>,
+    parent: &MDataInfo,
+    name: &str,
+) -> Box<NfsFuture<MDataInfo>> {
+    let child = fry!(if parent.enc_info.is_some() {
+        MDataInfo::random_private(parent.type_tag)
+    } else {
+        MDataInfo::random_public(parent.type_tag)
+    });
+    let content = fry!(serialise(&child));
+
+    let entries = fry!(shard::reserved_entries(&child));
+
+    let client2 = client.clone();
+    let parent2 = parent.clone();
+    let key = dir::subdir_key(name);
+
+    lookup(client, parent, name)
+        .and_then(move |node| match node {
+            Some((_, Node::Dir { .. })) => Err(NfsError::DirectoryExists),
+            Some((_, Node::File { .. })) |
+            Some((_, Node::Link { .. })) => Err(NfsError::FileExists),
+            None => Ok(()),
+        })
+        .and_then(move |_| {
+            let client = client2.clone();
+            let parent = parent2.clone();
+
+            shard::insert(&client2, &parent2, &key.clone(), move |target, current| {
+                let (key, action) = match fry!(dir::insert_action_over(
+                    &target,
+                    &key,
+                    &content,
+                    current.as_ref(),
+                )) {
+                    Some(action) => action,
+                    None => return err!(NfsError::DirectoryExists),
+                };
+                let client2 = client.clone();
+                let client3 = client.clone();
+                let child = child.clone();
+                let entries = entries.clone();
+
+                client
+                    .list_mdata_permissions(parent.name, parent.type_tag)
+                    .map_err(NfsError::from)
+                    .and_then(move |perms| {
+                        create_dir(&client2, &child, entries, perms).map(move |_| child)
+                    })
+                    .and_then(move |child| {
+                        let actions = btree_map![key => action];
+                        client3
+                            .mutate_mdata_entries(target.name, target.type_tag, actions)
+                            .map_err(NfsError::from)
+                            .map(move |_| child)
+                    })
+                    .into_box()
+            })
+        })
+        .into_box()
+}
+
+// Recursively delete all entries of `dir`, including those of its shards.
+fn clear_dir<T: 'static>(client: Client<T>, dir: MDataInfo) -> Box<NfsFuture<()>> {
+    let client2 = client.clone();
+
+    dir::list_entries(&client, &dir)
+        .and_then(move |entries| {
+            let mut subdirs = Vec::new();
+            let mut actions = BTreeMap::new();
+
+            for (key, value) in entries {
+                if key == METADATA_KEY {
+                    continue;
+                }
+                if dir::subdir_name(&key).is_some() {
+                    subdirs.push(fry!(deserialise::<MDataInfo>(&value.content)));
+                } else if key == dir::SHARDS_KEY {
+                    let shards = fry!(deserialise::<Vec<MDataInfo>>(&value.content));
+                    subdirs.extend(shards);
+                }
+                let _ = actions.insert(
+                    fry!(dir.enc_entry_key(&key)),
+                    EntryAction::Del(value.entry_version + 1),
+                );
+            }
+
+            let subdirs_fut = subdirs
+                .into_iter()
+                .map(|subdir| clear_dir(client2.clone(), subdir))
+                .collect::<Vec<_>>();
+
+            future::join_all(subdirs_fut)
+                .and_then(move |_| if actions.is_empty() {
+                    ok!(())
+                } else {
+                    client2
+                        .mutate_mdata_entries(dir.name, dir.type_tag, actions)
+                        .map_err(NfsError::from)
+                        .into_box()
+                })
+                .into_box()
+        })
+        .into_box()
+}
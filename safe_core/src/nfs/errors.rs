@@ -17,6 +17,7 @@
 
 use errors::CoreError;
 use maidsafe_utilities::serialisation::SerialisationError;
+use nfs::File;
 use self_encryption::SelfEncryptionError;
 use self_encryption_storage::SelfEncryptionStorageError;
 use std::fmt;
@@ -32,6 +33,28 @@ pub enum NfsError {
     FileNotFound,
     /// Invalid byte range specified
     InvalidRange,
+    /// Directory already exists with the same name
+    DirectoryExists,
+    /// Directory not found
+    DirectoryNotFound,
+    /// Directory is not empty
+    DirectoryNotEmpty,
+    /// Requested version of a file not found in its history
+    VersionNotFound,
+    /// Content of a file doesn't match its digest
+    DigestMismatch,
+    /// File doesn't store the digest of its content, so it can't be verified
+    DigestUnknown,
+    /// Soft links form a cycle
+    LinkCycle,
+    /// Path can't be used for the operation, e.g. moving a directory into
+    /// itself
+    InvalidPath,
+    /// Directory soft quota exceeded
+    QuotaExceeded,
+    /// File has been modified concurrently. Carries the rejected file and the
+    /// current one
+    Conflict(File, File),
     /// Unexpected error
     Unexpected(String),
     /// Unsuccessful Serialisation or Deserialisation
@@ -74,6 +97,16 @@ impl fmt::Display for NfsError {
             NfsError::FileNotFound => write!(f, "File not found"),
 
             NfsError::InvalidRange => write!(f, "Invalid byte range specified"),
+            NfsError::DirectoryExists => write!(f, "Directory already exists with the same name"),
+            NfsError::DirectoryNotFound => write!(f, "Directory not found"),
+            NfsError::DirectoryNotEmpty => write!(f, "Directory is not empty"),
+            NfsError::VersionNotFound => write!(f, "File version not found in its history"),
+            NfsError::DigestMismatch => write!(f, "Content of a file doesn't match its digest"),
+            NfsError::DigestUnknown => write!(f, "Digest of the file content is unknown"),
+            NfsError::LinkCycle => write!(f, "Soft links form a cycle"),
+            NfsError::InvalidPath => write!(f, "Invalid path for the operation"),
+            NfsError::QuotaExceeded => write!(f, "Directory quota exceeded"),
+            NfsError::Conflict(..) => write!(f, "File has been modified concurrently"),
             NfsError::Unexpected(ref error) => write!(f, "Unexpected error - {:?}", error),
             NfsError::EncodeDecodeError(ref error) => {
                 write!(
@@ -100,6 +133,16 @@ impl fmt::Debug for NfsError {
             NfsError::FileExists => write!(f, "NfsError::FileExists"),
             NfsError::FileNotFound => write!(f, "NfsError::FileNotFound"),
             NfsError::InvalidRange => write!(f, "NfsError::InvalidRange"),
+            NfsError::DirectoryExists => write!(f, "NfsError::DirectoryExists"),
+            NfsError::DirectoryNotFound => write!(f, "NfsError::DirectoryNotFound"),
+            NfsError::DirectoryNotEmpty => write!(f, "NfsError::DirectoryNotEmpty"),
+            NfsError::VersionNotFound => write!(f, "NfsError::VersionNotFound"),
+            NfsError::DigestMismatch => write!(f, "NfsError::DigestMismatch"),
+            NfsError::DigestUnknown => write!(f, "NfsError::DigestUnknown"),
+            NfsError::LinkCycle => write!(f, "NfsError::LinkCycle"),
+            NfsError::InvalidPath => write!(f, "NfsError::InvalidPath"),
+            NfsError::QuotaExceeded => write!(f, "NfsError::QuotaExceeded"),
+            NfsError::Conflict(..) => write!(f, "NfsError::Conflict"),
             NfsError::Unexpected(ref error) => write!(f, "NfsError::Unexpected -> {:?}", error),
             NfsError::EncodeDecodeError(ref error) => {
                 write!(f, "NfsError::EncodeDecodeError -> {:?}", error)
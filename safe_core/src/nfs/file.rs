@@ -16,10 +16,14 @@
 // relating to use of the SAFE Network Software.
 
 use chrono::{DateTime, NaiveDateTime, Utc};
-use ffi::nfs::File as FfiFile;
+use ffi::nfs::{File as FfiFile, XAttr as FfiXAttr};
 use ffi_utils::{ReprC, vec_into_raw_parts};
+use maidsafe_utilities::serialisation::{SerialisationError, deserialise};
 use nfs::errors::NfsError;
 use routing::XorName;
+use serde::de::DeserializeOwned;
+use std::cmp;
+use std::collections::BTreeMap;
 use std::slice;
 
 /// Representation of a File to be put into the network. Could be any kind of
@@ -31,6 +35,71 @@ pub struct File {
     modified: DateTime<Utc>,
     user_metadata: Vec<u8>,
     data_map_name: XorName,
+    metadata: VersionedMetadata,
+}
+
+/// Structured metadata of a File, for the properties most clients need, as
+/// opposed to the free-form user metadata.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct Metadata {
+    /// MIME type of the content (e.g. `text/plain`).
+    pub mime_type: Option<String>,
+    /// POSIX-like mode bits (e.g. `0o644`).
+    pub mode: Option<u32>,
+    /// Extended attributes.
+    pub xattrs: BTreeMap<String, Vec<u8>>,
+}
+
+// Serialised form of `Metadata` and of the other properties added to `File`
+// together with it. Changes of the schema are done by adding new variants, so
+// the metadata of existing files can still be deserialised.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+enum VersionedMetadata {
+    V1 {
+        metadata: Metadata,
+        digest: Option<[u8; 32]>,
+        inline_content: Option<Vec<u8>>,
+        data_size: Option<u64>,
+    },
+}
+
+impl VersionedMetadata {
+    fn new(
+        metadata: Metadata,
+        digest: Option<[u8; 32]>,
+        inline_content: Option<Vec<u8>>,
+        data_size: Option<u64>,
+    ) -> Self {
+        VersionedMetadata::V1 {
+            metadata,
+            digest,
+            inline_content,
+            data_size,
+        }
+    }
+}
+
+/// Serialised form of `File` from before the structured metadata was added.
+#[derive(Deserialize)]
+pub struct LegacyFile {
+    size: u64,
+    created: DateTime<Utc>,
+    modified: DateTime<Utc>,
+    user_metadata: Vec<u8>,
+    data_map_name: XorName,
+}
+
+impl From<LegacyFile> for File {
+    fn from(file: LegacyFile) -> File {
+        File {
+            size: file.size,
+            created: file.created,
+            modified: file.modified,
+            user_metadata: file.user_metadata,
+            data_map_name: file.data_map_name,
+            metadata: VersionedMetadata::new(Metadata::default(), None, None, None),
+        }
+    }
 }
 
 impl File {
@@ -42,9 +111,16 @@ impl File {
             modified: Utc::now(),
             user_metadata: user_metadata,
             data_map_name: XorName::default(),
+            metadata: VersionedMetadata::new(Metadata::default(), None, None, None),
         }
     }
 
+    /// Deserialise a File. Files serialised before the structured metadata
+    /// was added are accepted too, with empty metadata.
+    pub fn deserialise(data: &[u8]) -> Result<File, SerialisationError> {
+        deserialise_compat(data, |file: LegacyFile| File::from(file))
+    }
+
     /// Construct FFI wrapper for the native rust `File`, consuming the file.
     pub fn into_repr_c(self) -> FfiFile {
         // TODO: move the metadata, not clone.
@@ -52,6 +128,19 @@ impl File {
         let (user_metadata_ptr, user_metadata_len, user_metadata_cap) =
             vec_into_raw_parts(user_metadata);
 
+        let metadata = self.metadata().clone();
+        let mime_type = metadata.mime_type.unwrap_or_default().into_bytes();
+        let (mime_type_ptr, mime_type_len, mime_type_cap) = vec_into_raw_parts(mime_type);
+        let xattrs = metadata
+            .xattrs
+            .into_iter()
+            .map(|(name, value)| FfiXAttr::new(name.into_bytes(), value))
+            .collect();
+        let (xattrs_ptr, xattrs_len, xattrs_cap) = vec_into_raw_parts(xattrs);
+        let inline_content = self.inline_content().unwrap_or(&[]).to_vec();
+        let (inline_content_ptr, inline_content_len, inline_content_cap) =
+            vec_into_raw_parts(inline_content);
+
         FfiFile {
             size: self.size(),
             created_sec: self.created_time().timestamp(),
@@ -62,6 +151,22 @@ impl File {
             user_metadata_len: user_metadata_len,
             user_metadata_cap: user_metadata_cap,
             data_map_name: self.data_map_name().0,
+            mime_type_ptr,
+            mime_type_len,
+            mime_type_cap,
+            has_mode: metadata.mode.is_some(),
+            mode: metadata.mode.unwrap_or(0),
+            xattrs_ptr,
+            xattrs_len,
+            xattrs_cap,
+            has_digest: self.digest().is_some(),
+            digest: self.digest().cloned().unwrap_or([0; 32]),
+            has_inline_content: self.inline_content().is_some(),
+            inline_content_ptr,
+            inline_content_len,
+            inline_content_cap,
+            has_data_size: self.stored_data_size().is_some(),
+            data_size: self.data_size(),
         }
     }
 
@@ -90,6 +195,41 @@ impl File {
         &self.user_metadata
     }
 
+    /// Get the structured metadata
+    pub fn metadata(&self) -> &Metadata {
+        match self.metadata {
+            VersionedMetadata::V1 { ref metadata, .. } => metadata,
+        }
+    }
+
+    /// Get the SHA3-256 digest of the content, if known
+    pub fn digest(&self) -> Option<&[u8; 32]> {
+        match self.metadata {
+            VersionedMetadata::V1 { ref digest, .. } => digest.as_ref(),
+        }
+    }
+
+    /// Get the content of the File if it is small enough to be stored inline
+    /// in the File itself, instead of in the data-map. The content is
+    /// encrypted with the key the File was written with, if any
+    pub fn inline_content(&self) -> Option<&[u8]> {
+        match self.metadata {
+            VersionedMetadata::V1 { ref inline_content, .. } => {
+                inline_content.as_ref().map(|content| &content[..])
+            }
+        }
+    }
+
+    /// Get the number of bytes of the content actually stored. The rest of
+    /// the File, up to its size, is a hole which reads as zeros. Zero means
+    /// no data is stored at all and the data-map name is ignored
+    pub fn data_size(&self) -> u64 {
+        match self.stored_data_size() {
+            Some(data_size) => cmp::min(data_size, self.size),
+            None => self.size,
+        }
+    }
+
     /// Set the data-map name of the File
     pub fn set_data_map_name(&mut self, datamap_name: XorName) {
         self.data_map_name = datamap_name;
@@ -114,6 +254,44 @@ impl File {
     pub fn set_user_metadata(&mut self, user_metadata: Vec<u8>) {
         self.user_metadata = user_metadata;
     }
+
+    /// Set the structured metadata
+    pub fn set_metadata(&mut self, metadata: Metadata) {
+        match self.metadata {
+            VersionedMetadata::V1 { metadata: ref mut current, .. } => *current = metadata,
+        }
+    }
+
+    /// Set the SHA3-256 digest of the content
+    pub fn set_digest(&mut self, digest: Option<[u8; 32]>) {
+        match self.metadata {
+            VersionedMetadata::V1 { digest: ref mut current, .. } => *current = digest,
+        }
+    }
+
+    /// Set the content stored inline in the File. If set, the data-map name
+    /// is ignored
+    pub fn set_inline_content(&mut self, inline_content: Option<Vec<u8>>) {
+        match self.metadata {
+            VersionedMetadata::V1 { inline_content: ref mut current, .. } => {
+                *current = inline_content
+            }
+        }
+    }
+
+    /// Set the number of bytes of the content actually stored. `None` means
+    /// the whole content is stored
+    pub fn set_data_size(&mut self, data_size: Option<u64>) {
+        match self.metadata {
+            VersionedMetadata::V1 { data_size: ref mut current, .. } => *current = data_size,
+        }
+    }
+
+    fn stored_data_size(&self) -> Option<u64> {
+        match self.metadata {
+            VersionedMetadata::V1 { data_size, .. } => data_size,
+        }
+    }
 }
 
 impl ReprC for File {
@@ -130,16 +308,70 @@ impl ReprC for File {
         let created = convert_date_time((*repr_c).created_sec, (*repr_c).created_nsec)?;
         let modified = convert_date_time((*repr_c).modified_sec, (*repr_c).modified_nsec)?;
 
+        let mime_type = convert_string(slice::from_raw_parts(
+            (*repr_c).mime_type_ptr,
+            (*repr_c).mime_type_len,
+        ))?;
+        let mut xattrs = BTreeMap::new();
+        for xattr in slice::from_raw_parts((*repr_c).xattrs_ptr, (*repr_c).xattrs_len) {
+            let name = convert_string(slice::from_raw_parts(xattr.name_ptr, xattr.name_len))?;
+            let value = slice::from_raw_parts(xattr.value_ptr, xattr.value_len).to_vec();
+            let _ = xattrs.insert(name, value);
+        }
+        let metadata = Metadata {
+            mime_type: if mime_type.is_empty() {
+                None
+            } else {
+                Some(mime_type)
+            },
+            mode: if (*repr_c).has_mode {
+                Some((*repr_c).mode)
+            } else {
+                None
+            },
+            xattrs,
+        };
+
         let mut file = File::new(user_metadata);
         file.set_size((*repr_c).size);
         file.set_created_time(created);
         file.set_modified_time(modified);
         file.set_data_map_name(XorName((*repr_c).data_map_name));
+        file.set_metadata(metadata);
+        if (*repr_c).has_digest {
+            file.set_digest(Some((*repr_c).digest));
+        }
+        if (*repr_c).has_inline_content {
+            let inline_content = slice::from_raw_parts(
+                (*repr_c).inline_content_ptr,
+                (*repr_c).inline_content_len,
+            );
+            file.set_inline_content(Some(inline_content.to_vec()));
+        }
+        if (*repr_c).has_data_size {
+            file.set_data_size(Some((*repr_c).data_size));
+        }
 
         Ok(file)
     }
 }
 
+/// Deserialise a value containing `File`s, such as the history of a file. If
+/// that fails, the value is deserialised as `L`, its form from before the
+/// structured metadata was added, which holds `LegacyFile`s instead, and
+/// converted using `convert`.
+pub fn deserialise_compat<T, L, F>(data: &[u8], convert: F) -> Result<T, SerialisationError>
+where
+    T: DeserializeOwned,
+    L: DeserializeOwned,
+    F: FnOnce(L) -> T,
+{
+    match deserialise(data) {
+        Ok(value) => Ok(value),
+        Err(error) => deserialise::<L>(data).map(convert).map_err(|_| error),
+    }
+}
+
 #[inline]
 fn convert_date_time(sec: i64, nsec: u32) -> Result<DateTime<Utc>, NfsError> {
     let naive = NaiveDateTime::from_timestamp_opt(sec, nsec).ok_or_else(
@@ -150,6 +382,13 @@ fn convert_date_time(sec: i64, nsec: u32) -> Result<DateTime<Utc>, NfsError> {
     Ok(DateTime::<Utc>::from_utc(naive, Utc))
 }
 
+#[inline]
+fn convert_string(bytes: &[u8]) -> Result<String, NfsError> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| {
+        NfsError::Unexpected("Invalid UTF-8 string".to_string())
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -163,4 +402,86 @@ mod tests {
         let obj_after = unwrap!(deserialise(&serialised_data));
         assert_eq!(obj_before, obj_after);
     }
+
+    // Test that the structured metadata survives serialisation and that files
+    // serialised without it can still be deserialised.
+    #[test]
+    fn serialise_deserialise_metadata() {
+        let mut obj_before = File::new(Vec::new());
+        obj_before.set_metadata(Metadata {
+            mime_type: Some("text/plain".to_string()),
+            mode: Some(0o644),
+            xattrs: btree_map!["user.tag".to_string() => b"important".to_vec()],
+        });
+        let serialised_data = unwrap!(serialise(&obj_before));
+        let obj_after = unwrap!(File::deserialise(&serialised_data));
+        assert_eq!(obj_before, obj_after);
+
+        #[derive(Serialize)]
+        struct LegacyFile {
+            size: u64,
+            created: DateTime<Utc>,
+            modified: DateTime<Utc>,
+            user_metadata: Vec<u8>,
+            data_map_name: XorName,
+        }
+
+        let legacy = LegacyFile {
+            size: 42,
+            created: Utc::now(),
+            modified: Utc::now(),
+            user_metadata: b"metadata".to_vec(),
+            data_map_name: XorName::default(),
+        };
+        let serialised_data = unwrap!(serialise(&legacy));
+        let obj_after = unwrap!(File::deserialise(&serialised_data));
+        assert_eq!(obj_after.size(), 42);
+        assert_eq!(obj_after.data_size(), 42);
+        assert_eq!(obj_after.user_metadata(), &b"metadata"[..]);
+        assert_eq!(*obj_after.metadata(), Metadata::default());
+
+        // Lists of files, such as the file history, fall back to the legacy
+        // form too.
+        let serialised_data = unwrap!(serialise(&vec![(3u64, legacy)]));
+        let history: Vec<(u64, File)> = unwrap!(deserialise_compat(
+            &serialised_data,
+            |history: Vec<(u64, super::LegacyFile)>| {
+                history
+                    .into_iter()
+                    .map(|(version, file)| (version, File::from(file)))
+                    .collect()
+            },
+        ));
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].0, 3);
+        assert_eq!(history[0].1.size(), 42);
+    }
+
+    // Test that converting a file to its FFI representation and back
+    // preserves the structured metadata.
+    #[test]
+    #[allow(unsafe_code)]
+    fn repr_c_metadata() {
+        let mut file = File::new(Vec::new());
+        file.set_metadata(Metadata {
+            mime_type: Some("text/plain".to_string()),
+            mode: Some(0o600),
+            xattrs: btree_map!["user.tag".to_string() => b"important".to_vec()],
+        });
+
+        file.set_digest(Some([1; 32]));
+        file.set_inline_content(Some(b"hello".to_vec()));
+
+        let ffi_file = file.clone().into_repr_c();
+        let file2 = unsafe { unwrap!(File::clone_from_repr_c(&ffi_file)) };
+        assert_eq!(file, file2);
+
+        file.set_metadata(Metadata::default());
+        file.set_digest(None);
+        file.set_inline_content(None);
+        let ffi_file = file.clone().into_repr_c();
+        assert!(!ffi_file.has_mode);
+        let file2 = unsafe { unwrap!(File::clone_from_repr_c(&ffi_file)) };
+        assert_eq!(file, file2);
+    }
 }
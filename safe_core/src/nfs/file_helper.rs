@@ -15,17 +15,25 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 
-use client::{Client, MDataInfo};
+use chrono::Utc;
+use client::{Client, MDataInfo, recovery};
 use crypto::shared_secretbox;
 use errors::CoreError;
-use futures::{Future, IntoFuture};
+use futures::Future;
+use futures::future::{self, Loop};
 use maidsafe_utilities::serialisation::{deserialise, serialise};
-use nfs::{File, Mode, NfsError, NfsFuture, Reader, Writer};
-use routing::{ClientError, EntryActions};
+use nfs::{ConflictPolicy, File, Link, Mode, NfsError, NfsFuture, Quota, Reader, TrashedFile,
+          Usage, Writer, dir};
+use nfs::{conflict, file, shard, trash, usage};
+use nfs::file::LegacyFile;
+use routing::{ClientError, EntryAction, EntryActions, Value};
+use std::collections::{BTreeMap, HashSet};
 use self_encryption_storage::SelfEncryptionStorage;
 use utils::FutureExt;
 
-/// Insert the file into the directory.
+/// Insert the file into the directory. If the directory is full, the file is
+/// inserted into one of its shards. Fails with `FileExists` if a file or a
+/// soft link with this name already exists.
 pub fn insert<S, T>(
     client: Client<T>,
     parent: MDataInfo,
@@ -36,26 +44,42 @@ where
     S: AsRef<str>,
     T: 'static,
 {
-    let name = name.as_ref();
+    let name = name.as_ref().to_string();
     trace!("Inserting file with name '{}'", name);
 
-    serialise(&file)
-        .map_err(From::from)
-        .and_then(|encoded| {
-            let key = parent.enc_entry_key(name.as_bytes())?;
-            let value = parent.enc_entry_value(&encoded)?;
+    let encoded = fry!(serialise(file));
+    let file = file.clone();
+    let client2 = client.clone();
+    let parent2 = parent.clone();
 
-            Ok((key, value))
-        })
-        .into_future()
-        .and_then(move |(key, value)| {
-            client.mutate_mdata_entries(
-                parent.name,
-                parent.type_tag,
-                EntryActions::new().ins(key, value, 0).into(),
-            )
+    shard::fetch_entry(&client, &parent, &dir::link_key(&name))
+        .and_then(move |link| {
+            if link.map(|value| !value.content.is_empty()).unwrap_or(false) {
+                return err!(NfsError::FileExists);
+            }
+            let client = client2.clone();
+            let key = name.as_bytes().to_vec();
+
+            shard::insert(&client2, &parent2, &key, move |target, current| {
+                let (key, action) = match fry!(dir::insert_action_over(
+                    &target,
+                    name.as_bytes(),
+                    &encoded,
+                    current.as_ref(),
+                )) {
+                    Some(action) => action,
+                    None => return err!(NfsError::FileExists),
+                };
+
+                mutate_with_usage(
+                    &client,
+                    &parent,
+                    &target,
+                    btree_map![key => action],
+                    vec![(name.clone(), Some(file.clone()))],
+                )
+            })
         })
-        .map_err(From::from)
         .into_box()
 }
 
@@ -65,20 +89,36 @@ where
     S: AsRef<str>,
     T: 'static,
 {
-    parent
-        .enc_entry_key(name.as_ref().as_bytes())
-        .into_future()
-        .and_then(move |key| {
-            client
-                .get_mdata_value(parent.name, parent.type_tag, key)
-                .map(move |value| (value, parent))
-        })
-        .and_then(move |(value, parent)| {
-            let plaintext = parent.decrypt(&value.content)?;
-            let file = deserialise(&plaintext)?;
+    fetch_file_entry(&client, &parent, name.as_ref())
+        .and_then(|(_, value)| {
+            let file = File::deserialise(&value.content)?;
             Ok((value.entry_version, file))
         })
-        .map_err(convert_error)
+        .into_box()
+}
+
+/// Lists all files in the directory, including those in its shards, returning
+/// their names, entry versions and the files themselves. Deleted entries,
+/// subdirectories and other entries not describing a file are skipped.
+pub fn list<T: 'static>(
+    client: &Client<T>,
+    parent: &MDataInfo,
+) -> Box<NfsFuture<Vec<(String, u64, File)>>> {
+    trace!("Listing files in directory {:?}", parent.name);
+
+    shard::list_entries(client, parent)
+        .map(|entries| {
+            entries
+                .into_iter()
+                .filter(|&(ref key, _)| !dir::is_reserved_key(key))
+                .filter_map(|(key, value)| {
+                    match (String::from_utf8(key), File::deserialise(&value.content)) {
+                        (Ok(name), Ok(file)) => Some((name, value.entry_version, file)),
+                        _ => None,
+                    }
+                })
+                .collect()
+        })
         .into_box()
 }
 
@@ -108,18 +148,189 @@ where
     S: AsRef<str>,
     T: 'static,
 {
-    let name = name.as_ref();
+    let name = name.as_ref().to_string();
     trace!("Deleting file with name {}.", name);
 
-    let key = fry!(parent.enc_entry_key(name.as_bytes()));
+    let client2 = client.clone();
+    let parent2 = parent.clone();
+
+    shard::locate(client, parent, name.as_bytes())
+        .and_then(move |(target, _)| {
+            let key = fry!(target.enc_entry_key(name.as_bytes()));
+            mutate_with_usage(
+                &client2,
+                &parent2,
+                &target,
+                EntryActions::new().del(key, version).into(),
+                vec![(name, None)],
+            )
+        })
+        .into_box()
+}
+
+/// Renames the file within the directory. The new entry is inserted and the
+/// old one deleted in a single mutation, so the file is never missing nor
+/// present under both names. The exception are large directories where the
+/// two names belong to different shards; the rename is then done like
+/// `move_to` between the shards.
+/// `version` is used to delete the old entry. If it is 0, the current version
+/// is first retrieved from the network, and that version incremented by one
+/// is then used as the actual version.
+/// Returns the version of the new entry.
+pub fn rename<S, D, T>(
+    client: Client<T>,
+    parent: MDataInfo,
+    name: S,
+    new_name: D,
+    version: u64,
+) -> Box<NfsFuture<u64>>
+where
+    S: AsRef<str>,
+    D: AsRef<str>,
+    T: 'static,
+{
+    let name = name.as_ref().to_string();
+    let new_name = new_name.as_ref().to_string();
+    trace!("Renaming file '{}' to '{}'", name, new_name);
+
+    let client2 = client.clone();
+    let parent2 = parent.clone();
+
+    fetch_file_entry(&client, &parent, &name)
+        .and_then(move |(src, value)| {
+            let client = client2.clone();
+            let key = new_name.as_bytes().to_vec();
+
+            shard::insert(&client2, &parent2, &key, move |dst, current| {
+                let (new_key, new_action) = match fry!(dir::insert_action_over(
+                    &dst,
+                    new_name.as_bytes(),
+                    &value.content,
+                    current.as_ref(),
+                )) {
+                    Some(new_action) => new_action,
+                    None => return err!(NfsError::FileExists),
+                };
+                let new_version = action_version(&new_action);
+                let version = if version == 0 {
+                    value.entry_version + 1
+                } else {
+                    version
+                };
+                let del_key = fry!(src.enc_entry_key(name.as_bytes()));
+                let del_action = EntryAction::Del(version);
+
+                let fut = if is_same_mdata(&src, &dst) {
+                    let actions = btree_map![del_key => del_action, new_key => new_action];
+                    client.mutate_mdata_entries(src.name, src.type_tag, actions)
+                } else {
+                    let client2 = client.clone();
+                    let src = src.clone();
+                    client
+                        .mutate_mdata_entries(
+                            dst.name,
+                            dst.type_tag,
+                            btree_map![new_key => new_action],
+                        )
+                        .and_then(move |_| {
+                            client2.mutate_mdata_entries(
+                                src.name,
+                                src.type_tag,
+                                btree_map![del_key => del_action],
+                            )
+                        })
+                        .into_box()
+                };
+                fut.map(move |_| new_version)
+                    .map_err(convert_error)
+                    .into_box()
+            })
+        })
+        .into_box()
+}
+
+/// Moves the file from `parent` into `new_parent` under `new_name`. If both
+/// are the same directory, this is equivalent to `rename`.
+///
+/// Two directories can't be mutated atomically, so the file is first inserted
+/// into `new_parent` and only then deleted from `parent`. If the move is
+/// interrupted in between (e.g. the app crashes or loses connection), the file
+/// is left present in both directories, pointing to the same data, so nothing
+/// is lost. Calling `move_to` again with the same arguments then completes the
+/// move: an entry in `new_parent` identical to the file being moved is treated
+/// as the result of the interrupted insertion and only the deletion is done.
+/// Returns the version of the new entry.
+pub fn move_to<S, D, T>(
+    client: Client<T>,
+    parent: MDataInfo,
+    name: S,
+    new_parent: MDataInfo,
+    new_name: D,
+) -> Box<NfsFuture<u64>>
+where
+    S: AsRef<str>,
+    D: AsRef<str>,
+    T: 'static,
+{
+    if is_same_mdata(&parent, &new_parent) {
+        return rename(client, parent, name, new_name, 0);
+    }
+
+    let name = name.as_ref().to_string();
+    let new_name = new_name.as_ref().to_string();
+    trace!("Moving file '{}' to another directory as '{}'", name, new_name);
+
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let new_parent2 = new_parent.clone();
 
-    client
-        .mutate_mdata_entries(
-            parent.name,
-            parent.type_tag,
-            EntryActions::new().del(key, version).into(),
-        )
-        .map_err(convert_error)
+    fetch_file_entry(&client, &parent, &name)
+        .and_then(move |(src, value)| {
+            let key = fry!(src.enc_entry_key(name.as_bytes()));
+            let del_action = EntryAction::Del(value.entry_version + 1);
+            let file = fry!(File::deserialise(&value.content));
+            let client = client2.clone();
+            let new_key = new_name.as_bytes().to_vec();
+
+            shard::insert(&client2, &new_parent2, &new_key, move |dst, current| {
+                if let Some(ref current) = current {
+                    if current.content == value.content {
+                        // Already inserted by an interrupted move.
+                        return ok!(current.entry_version);
+                    }
+                }
+
+                let (new_key, new_action) = match fry!(dir::insert_action_over(
+                    &dst,
+                    new_name.as_bytes(),
+                    &value.content,
+                    current.as_ref(),
+                )) {
+                    Some(new_action) => new_action,
+                    None => return err!(NfsError::FileExists),
+                };
+                let new_version = action_version(&new_action);
+
+                mutate_with_usage(
+                    &client,
+                    &new_parent,
+                    &dst,
+                    btree_map![new_key => new_action],
+                    vec![(new_name.clone(), Some(file.clone()))],
+                ).map(move |_| new_version)
+                    .into_box()
+            }).map(move |new_version| (new_version, src, key, del_action, name))
+                .into_box()
+        })
+        .and_then(move |(new_version, src, key, del_action, name)| {
+            mutate_with_usage(
+                &client3,
+                &parent,
+                &src,
+                btree_map![key => del_action],
+                vec![(name, None)],
+            ).map(move |_| new_version)
+        })
         .into_box()
 }
 
@@ -137,36 +348,448 @@ where
     S: AsRef<str>,
     T: 'static,
 {
-    let name = name.as_ref();
+    let name = name.as_ref().to_string();
     trace!("Updating file with name '{}'", name);
 
+    let encoded = fry!(serialise(file));
+    let file = file.clone();
+    let client2 = client.clone();
+
+    shard::locate(&client, &parent, name.as_bytes())
+        .and_then(move |(target, current)| {
+            let version = match (version, current) {
+                (0, Some(current)) => current.entry_version + 1,
+                (0, None) => return err!(NfsError::FileNotFound),
+                (version, _) => version,
+            };
+            let key = fry!(target.enc_entry_key(name.as_bytes()));
+            let content = fry!(target.enc_entry_value(&encoded));
+
+            mutate_with_usage(
+                &client2,
+                &parent,
+                &target,
+                EntryActions::new().update(key, content, version).into(),
+                vec![(name, Some(file))],
+            )
+        })
+        .into_box()
+}
+
+/// Updates the file, provided it hasn't been modified since it was fetched at
+/// entry `version`. Unlike `update` with `version` 0, which overwrites
+/// whatever the current file is, concurrent modifications are detected and
+/// resolved according to `policy`: either the update fails with
+/// `NfsError::Conflict` carrying `file` and the current file, or the current
+/// file is left intact and `file` is inserted as a conflict copy instead.
+/// Returns `None` if the file has been updated, or the name of the copy.
+/// Finding `file` itself stored right after `version` is not a conflict: it
+/// means an earlier attempt of this update succeeded, so it isn't repeated.
+/// A mutation rejected by a concurrent modification is retried at most
+/// `usage::MAX_ATTEMPTS` times, failing with the last error afterwards.
+pub fn update_checked<S, T>(
+    client: Client<T>,
+    parent: MDataInfo,
+    name: S,
+    file: &File,
+    version: u64,
+    policy: ConflictPolicy,
+) -> Box<NfsFuture<Option<String>>>
+where
+    S: AsRef<str>,
+    T: 'static,
+{
+    let name = name.as_ref().to_string();
+    trace!("Updating file with name '{}' from version {}", name, version);
+
+    let file = file.clone();
+
+    future::loop_fn(1, move |attempts| {
+        let client = client.clone();
+        let parent = parent.clone();
+        let name = name.clone();
+        let file = file.clone();
+
+        // The version is checked upfront to spare the mutation in the usual
+        // case of a conflict. Should the file be modified in between, the
+        // mutation fails as well.
+        fetch(client.clone(), parent.clone(), name.clone())
+            .and_then(move |(current_version, current)| {
+                if current_version == version + 1 && current == file {
+                    // Our own write, e.g. by an attempt which failed only
+                    // after the file was updated.
+                    return ok!(Loop::Break(None));
+                }
+                if current_version != version {
+                    return resolve_conflict(client, parent, name, file, current, policy)
+                        .map(Loop::Break)
+                        .into_box();
+                }
+
+                update(client, parent, name, &file, version + 1)
+                    .map(|_| Loop::Break(None))
+                    .or_else(move |err| {
+                        // Either the file or the usage record of the
+                        // directory has been modified. The check above
+                        // tells which one.
+                        if is_invalid_entry_actions(&err) && attempts < usage::MAX_ATTEMPTS {
+                            Ok(Loop::Continue(attempts + 1))
+                        } else {
+                            Err(err)
+                        }
+                    })
+                    .into_box()
+            })
+    }).into_box()
+}
+
+/// Updates the file, keeping the replaced `File` record in the history of the
+/// file. At most `max_history` previous versions are kept, the oldest ones
+/// are dropped first. The file entry and its history are updated in a single
+/// mutation.
+/// If `version` is 0, the current version is first retrieved from the network,
+/// and that version incremented by one is then used as the actual version.
+pub fn update_versioned<S, T>(
+    client: Client<T>,
+    parent: MDataInfo,
+    name: S,
+    file: &File,
+    version: u64,
+    max_history: usize,
+) -> Box<NfsFuture<()>>
+where
+    S: AsRef<str>,
+    T: 'static,
+{
+    let name = name.as_ref().to_string();
+    trace!("Updating file with name '{}', keeping its history", name);
+
+    let encoded = fry!(serialise(&file));
+    let file = file.clone();
     let client2 = client.clone();
+    let client3 = client.clone();
 
-    serialise(&file)
-        .map_err(From::from)
-        .and_then(|encoded| {
-            let key = parent.enc_entry_key(name.as_bytes())?;
-            let content = parent.enc_entry_value(&encoded)?;
-
-            Ok((key, content))
-        })
-        .into_future()
-        .and_then(move |(key, content)| if version != 0 {
-            ok!((key, content, version, parent))
-        } else {
-            client
-                .get_mdata_value(parent.name, parent.type_tag, key.clone())
-                .map(move |value| (key, content, value.entry_version + 1, parent))
+    fetch_file_entry(&client, &parent, &name)
+        .and_then(move |(target, value)| {
+            let cur_file = fry!(File::deserialise(&value.content));
+            fetch_history(&client2, &target, &name)
+                .map(move |history| (target, name, value.entry_version, cur_file, history))
                 .into_box()
         })
-        .and_then(move |(key, content, version, parent)| {
-            client2.mutate_mdata_entries(
-                parent.name,
-                parent.type_tag,
-                EntryActions::new().update(key, content, version).into(),
+        .and_then(move |(target, name, cur_version, cur_file, history)| {
+            let (history_version, mut history) = history;
+            history.push((cur_version, cur_file));
+            let excess = history.len().saturating_sub(max_history);
+            let _ = history.drain(..excess);
+
+            let key = target.enc_entry_key(name.as_bytes())?;
+            let content = target.enc_entry_value(&encoded)?;
+            let history_key = target.enc_entry_key(&dir::history_key(&name))?;
+            let history_content = target.enc_entry_value(&serialise(&history)?)?;
+
+            let version = if version == 0 {
+                cur_version + 1
+            } else {
+                version
+            };
+            let actions = EntryActions::new().update(key, content, version);
+            let actions = match history_version {
+                Some(history_version) => {
+                    actions.update(history_key, history_content, history_version + 1)
+                }
+                None => actions.ins(history_key, history_content, 0),
+            };
+
+            Ok((target, name, actions))
+        })
+        .and_then(move |(target, name, actions)| {
+            mutate_with_usage(
+                &client3,
+                &parent,
+                &target,
+                actions.into(),
+                vec![(name, Some(file))],
+            )
+        })
+        .into_box()
+}
+
+/// Returns the previous versions of the file kept by `update_versioned`,
+/// oldest first. Each `File` is paired with the entry version it had while it
+/// was the current version of the file.
+pub fn history<S, T>(
+    client: &Client<T>,
+    parent: &MDataInfo,
+    name: S,
+) -> Box<NfsFuture<Vec<(u64, File)>>>
+where
+    S: AsRef<str>,
+    T: 'static,
+{
+    let name = name.as_ref().to_string();
+    let client2 = client.clone();
+
+    shard::locate(client, parent, name.as_bytes())
+        .and_then(move |(target, _)| fetch_history(&client2, &target, &name))
+        .map(|(_, history)| history)
+        .into_box()
+}
+
+/// Makes the `File` recorded in the history under the given entry `version`
+/// the current version of the file. The replaced version is added to the
+/// history, as with `update_versioned`.
+pub fn restore<S, T>(
+    client: Client<T>,
+    parent: MDataInfo,
+    name: S,
+    version: u64,
+    max_history: usize,
+) -> Box<NfsFuture<()>>
+where
+    S: AsRef<str>,
+    T: 'static,
+{
+    let name = name.as_ref().to_string();
+    trace!("Restoring version {} of file with name '{}'", version, name);
+
+    history(&client, &parent, &name)
+        .and_then(move |history| {
+            let file = match history.into_iter().find(|&(v, _)| v == version) {
+                Some((_, file)) => file,
+                None => return err!(NfsError::VersionNotFound),
+            };
+            update_versioned(client, parent, name, &file, 0, max_history)
+        })
+        .into_box()
+}
+
+/// Moves the file into the trash of the directory instead of deleting it
+/// irrevocably, recording the time of the deletion. The file can be brought
+/// back using `restore_from_trash` until the trash is emptied.
+/// If `version` is 0, the current version is first retrieved from the network,
+/// and that version incremented by one is then used as the actual version.
+pub fn soft_delete<S, T>(
+    client: Client<T>,
+    parent: MDataInfo,
+    name: S,
+    version: u64,
+) -> Box<NfsFuture<()>>
+where
+    S: AsRef<str>,
+    T: 'static,
+{
+    let name = name.as_ref().to_string();
+    trace!("Moving file with name '{}' to trash", name);
+
+    let client2 = client.clone();
+    let parent2 = parent.clone();
+
+    fetch_file_entry(&client, &parent, &name)
+        .and_then(move |(target, value)| {
+            let file = fry!(File::deserialise(&value.content));
+            let key = fry!(target.enc_entry_key(name.as_bytes()));
+            let version = if version == 0 {
+                value.entry_version + 1
+            } else {
+                version
+            };
+            let client = client2.clone();
+            let trash_name = name.clone();
+
+            trash::update(
+                &client2,
+                &parent2,
+                &trash_name,
+                move |trash_target, trash_version, mut trash| {
+                    trash.push(TrashedFile {
+                        file: file.clone(),
+                        deleted: Utc::now(),
+                    });
+
+                    let trash_key = fry!(trash_target.enc_entry_key(&dir::trash_key(&name)));
+                    let encoded = fry!(serialise(&trash));
+                    let trash_content = fry!(trash_target.enc_entry_value(&encoded));
+                    let trash_actions = match trash_version {
+                        Some(trash_version) => {
+                            EntryActions::new().update(trash_key, trash_content, trash_version + 1)
+                        }
+                        None => EntryActions::new().ins(trash_key, trash_content, 0),
+                    };
+                    let changes = vec![(name.clone(), None)];
+
+                    if is_same_mdata(&trash_target, &target) {
+                        let actions = trash_actions.del(key.clone(), version).into();
+                        return mutate_with_usage(&client, &parent, &target, actions, changes);
+                    }
+
+                    // The file is recorded in the trash first, so it's never lost.
+                    let client2 = client.clone();
+                    let parent = parent.clone();
+                    let target = target.clone();
+                    let key = key.clone();
+                    client
+                        .mutate_mdata_entries(
+                            trash_target.name,
+                            trash_target.type_tag,
+                            trash_actions.into(),
+                        )
+                        .map_err(convert_error)
+                        .and_then(move |_| {
+                            let actions = EntryActions::new().del(key, version).into();
+                            mutate_with_usage(&client2, &parent, &target, actions, changes)
+                        })
+                        .into_box()
+                },
             )
         })
-        .map_err(convert_error)
+        .into_box()
+}
+
+/// Returns the trash of the directory: the soft-deleted files paired with
+/// their names, ordered by name and then by the time of deletion.
+pub fn list_trash<T: 'static>(
+    client: &Client<T>,
+    parent: &MDataInfo,
+) -> Box<NfsFuture<Vec<(String, TrashedFile)>>> {
+    trash::list(client, parent)
+        .map(|trash| {
+            trash
+                .into_iter()
+                .flat_map(|(name, _, files)| {
+                    files.into_iter().map(move |file| (name.clone(), file))
+                })
+                .collect()
+        })
+        .into_box()
+}
+
+/// Moves the most recently soft-deleted file with the given name out of the
+/// trash, back into the directory. Fails with `FileNotFound` if there's no
+/// such file in the trash, and with `FileExists` if a file with the same name
+/// has been created since.
+pub fn restore_from_trash<S, T>(
+    client: Client<T>,
+    parent: MDataInfo,
+    name: S,
+) -> Box<NfsFuture<()>>
+where
+    S: AsRef<str>,
+    T: 'static,
+{
+    let name = name.as_ref().to_string();
+    trace!("Restoring file with name '{}' from trash", name);
+
+    let client2 = client.clone();
+    let parent2 = parent.clone();
+
+    trash::fetch(&client, &parent, &name)
+        .and_then(move |(trash_target, trash_version, mut trash)| {
+            let (trash_version, file) = match (trash_version, trash.pop()) {
+                (Some(trash_version), Some(trashed)) => (trash_version, trashed.file),
+                _ => return err!(NfsError::FileNotFound),
+            };
+            let encoded = fry!(serialise(&file));
+            let trash_key = fry!(trash_target.enc_entry_key(&dir::trash_key(&name)));
+            let trash_action = if trash.is_empty() {
+                EntryAction::Del(trash_version + 1)
+            } else {
+                EntryAction::Update(Value {
+                    content: fry!(trash_target.enc_entry_value(&fry!(serialise(&trash)))),
+                    entry_version: trash_version + 1,
+                })
+            };
+            let client = client2.clone();
+            let key = name.as_bytes().to_vec();
+
+            shard::insert(&client2, &parent2, &key, move |target, current| {
+                let (key, action) = match fry!(dir::insert_action_over(
+                    &target,
+                    name.as_bytes(),
+                    &encoded,
+                    current.as_ref(),
+                )) {
+                    Some(action) => action,
+                    None => return err!(NfsError::FileExists),
+                };
+                let changes = vec![(name.clone(), Some(file.clone()))];
+
+                if is_same_mdata(&trash_target, &target) {
+                    let actions = btree_map![
+                        key => action,
+                        trash_key.clone() => trash_action.clone()
+                    ];
+                    return mutate_with_usage(&client, &parent, &target, actions, changes);
+                }
+
+                // The file is restored first, so it's never lost.
+                let client2 = client.clone();
+                let trash_target = trash_target.clone();
+                let trash_key = trash_key.clone();
+                let trash_action = trash_action.clone();
+                mutate_with_usage(&client, &parent, &target, btree_map![key => action], changes)
+                    .and_then(move |_| {
+                        client2
+                            .mutate_mdata_entries(
+                                trash_target.name,
+                                trash_target.type_tag,
+                                btree_map![trash_key => trash_action],
+                            )
+                            .map_err(convert_error)
+                    })
+                    .into_box()
+            })
+        })
+        .into_box()
+}
+
+/// Irrevocably deletes all files in the trash of the directory.
+pub fn empty_trash<T: 'static>(client: Client<T>, parent: MDataInfo) -> Box<NfsFuture<()>> {
+    trace!("Emptying trash of directory {:?}", parent.name);
+
+    let client2 = client.clone();
+    let client3 = client.clone();
+
+    trash::list(&client, &parent)
+        .and_then(move |trash| {
+            // The entries may be spread over the shards of the directory.
+            let fetch_futs = trash
+                .into_iter()
+                .map(|(name, _, _)| {
+                    trash::fetch(&client2, &parent, &name).map(move |trash| (name, trash))
+                })
+                .collect::<Vec<_>>();
+            future::join_all(fetch_futs)
+        })
+        .and_then(move |trash| {
+            let mut mutations: Vec<(MDataInfo, BTreeMap<_, _>)> = Vec::new();
+            for (name, (target, version, _)) in trash {
+                let version = match version {
+                    Some(version) => version,
+                    None => continue,
+                };
+                let key = target.enc_entry_key(&dir::trash_key(&name))?;
+                let action = EntryAction::Del(version + 1);
+
+                match mutations.iter().position(|&(ref t, _)| is_same_mdata(t, &target)) {
+                    Some(index) => {
+                        let _ = mutations[index].1.insert(key, action);
+                    }
+                    None => mutations.push((target, btree_map![key => action])),
+                }
+            }
+            Ok(mutations)
+        })
+        .and_then(move |mutations| {
+            let mutate_futs = mutations
+                .into_iter()
+                .map(|(target, actions)| {
+                    client3
+                        .mutate_mdata_entries(target.name, target.type_tag, actions)
+                        .map_err(convert_error)
+                })
+                .collect::<Vec<_>>();
+            future::join_all(mutate_futs).map(|_| ())
+        })
         .into_box()
 }
 
@@ -194,6 +817,608 @@ where
     )
 }
 
+/// Like `write`, but for a file stored in `parent`, whose key is used to
+/// encrypt the content. If the directory has a size quota, the writer is
+/// limited to the size still available to the file, so that writes exceeding
+/// the quota fail with `NfsError::QuotaExceeded` before their data is
+/// uploaded, rather than only when the file is inserted or updated. Unless
+/// the client enables usage tracking, this is the same as `write`.
+pub fn write_in<T>(
+    client: Client<T>,
+    parent: &MDataInfo,
+    file: File,
+    mode: Mode,
+) -> Box<NfsFuture<Writer<T>>>
+where
+    T: 'static,
+{
+    let encryption_key = parent.enc_key().cloned();
+
+    if !client.usage_tracking() {
+        return write(client, file, mode, encryption_key);
+    }
+
+    usage::fetch(&client, parent)
+        .and_then(move |usage| {
+            // The current size of the file is freed when it is replaced.
+            let size_limit = usage.and_then(|(_, usage)| {
+                usage.quota.max_size.map(|max_size| {
+                    max_size.saturating_add(file.size()).saturating_sub(usage.size)
+                })
+            });
+
+            write(client, file, mode, encryption_key).map(move |writer| match size_limit {
+                Some(size_limit) => writer.with_size_limit(size_limit),
+                None => writer,
+            })
+        })
+        .into_box()
+}
+
+/// Returns the usage of the directory, or `None` if it doesn't track its usage.
+pub fn dir_usage<T: 'static>(
+    client: &Client<T>,
+    parent: &MDataInfo,
+) -> Box<NfsFuture<Option<Usage>>> {
+    usage::fetch(client, parent)
+        .map(|usage| usage.map(|(_, usage)| usage))
+        .into_box()
+}
+
+/// Sets the soft quota of the directory. If the directory doesn't track its
+/// usage yet, it is enabled, computing the initial usage from the current
+/// files. The quota may be set below the current usage, in which case only
+/// mutations decreasing the usage are allowed.
+pub fn set_quota<T: 'static>(
+    client: Client<T>,
+    parent: MDataInfo,
+    quota: Quota,
+) -> Box<NfsFuture<()>> {
+    trace!("Setting quota of directory {:?} to {:?}", parent.name, quota);
+
+    let client2 = client.clone();
+    let client3 = client.clone();
+
+    usage::fetch(&client, &parent)
+        .and_then(move |usage| match usage {
+            Some((version, usage)) => {
+                let usage = Usage { quota, ..usage };
+                let action = EntryAction::Update(Value {
+                    content: fry!(parent.enc_entry_value(&fry!(serialise(&usage)))),
+                    entry_version: version + 1,
+                });
+                let key = fry!(parent.enc_entry_key(dir::USAGE_KEY));
+                ok!((parent, Some((key, action))))
+            }
+            None => {
+                list(&client2, &parent)
+                    .and_then(move |files| {
+                        let usage = usage::compute(&files, quota);
+                        let encoded = fry!(serialise(&usage));
+                        dir::insert_action(&client2, &parent, dir::USAGE_KEY, &encoded)
+                            .map(move |action| (parent, action))
+                            .into_box()
+                    })
+                    .into_box()
+            }
+        })
+        .and_then(move |(parent, action)| {
+            // `None` means the record has been created concurrently since it
+            // was fetched.
+            let (key, action) = match action {
+                Some(action) => action,
+                None => return err!(NfsError::from("Usage record created concurrently")),
+            };
+            client3
+                .mutate_mdata_entries(parent.name, parent.type_tag, btree_map![key => action])
+                .map_err(convert_error)
+                .into_box()
+        })
+        .into_box()
+}
+
+/// Creates a soft link called `name` in `parent`, pointing to `link`. Fails
+/// with `FileExists` if a file or another link with this name already exists.
+pub fn symlink<S, T>(
+    client: Client<T>,
+    parent: MDataInfo,
+    name: S,
+    link: &Link,
+) -> Box<NfsFuture<()>>
+where
+    S: AsRef<str>,
+    T: 'static,
+{
+    let name = name.as_ref().to_string();
+    trace!("Creating a soft link with name '{}'", name);
+
+    let encoded = fry!(serialise(link));
+    let client2 = client.clone();
+    let parent2 = parent.clone();
+
+    shard::fetch_entry(&client, &parent, name.as_bytes())
+        .and_then(move |file| {
+            if file.map(|value| !value.content.is_empty()).unwrap_or(false) {
+                return err!(NfsError::FileExists);
+            }
+            let client = client2.clone();
+            let key = dir::link_key(&name);
+
+            shard::insert(&client2, &parent2, &key, move |target, current| {
+                let (key, action) = match fry!(dir::insert_action_over(
+                    &target,
+                    &dir::link_key(&name),
+                    &encoded,
+                    current.as_ref(),
+                )) {
+                    Some(action) => action,
+                    None => return err!(NfsError::FileExists),
+                };
+
+                client
+                    .mutate_mdata_entries(target.name, target.type_tag, btree_map![key => action])
+                    .map_err(convert_error)
+                    .into_box()
+            })
+        })
+        .into_box()
+}
+
+/// Gets the soft link `name` from the directory together with the version of
+/// its entry.
+pub fn read_link<S, T>(
+    client: &Client<T>,
+    parent: &MDataInfo,
+    name: S,
+) -> Box<NfsFuture<(u64, Link)>>
+where
+    S: AsRef<str>,
+    T: 'static,
+{
+    shard::fetch_entry(client, parent, &dir::link_key(name.as_ref()))
+        .and_then(|value| match value {
+            Some(ref value) if value.content.is_empty() => Err(NfsError::FileNotFound),
+            Some(value) => Ok((value.entry_version, deserialise(&value.content)?)),
+            None => Err(NfsError::FileNotFound),
+        })
+        .into_box()
+}
+
+/// Deletes the soft link `name` from the directory. The target is unaffected.
+pub fn delete_link<S, T>(
+    client: &Client<T>,
+    parent: &MDataInfo,
+    name: S,
+    version: u64,
+) -> Box<NfsFuture<()>>
+where
+    S: AsRef<str>,
+    T: 'static,
+{
+    let name = name.as_ref();
+    trace!("Deleting soft link with name '{}'", name);
+
+    let key = dir::link_key(name);
+    let client2 = client.clone();
+
+    shard::locate(client, parent, &key)
+        .and_then(move |(target, _)| {
+            let key = fry!(target.enc_entry_key(&key));
+            client2
+                .mutate_mdata_entries(
+                    target.name,
+                    target.type_tag,
+                    EntryActions::new().del(key, version).into(),
+                )
+                .map_err(convert_error)
+                .into_box()
+        })
+        .into_box()
+}
+
+/// Gets a file from the directory like `fetch`, but if `name` is a soft link,
+/// follows it (and any further links) to the file it eventually points to.
+/// Fails with `LinkCycle` if a link is reached twice and with `FileNotFound`
+/// if the chain ends at a name which is neither a file nor a link.
+pub fn fetch_resolved<S, T>(
+    client: Client<T>,
+    parent: MDataInfo,
+    name: S,
+) -> Box<NfsFuture<(u64, File)>>
+where
+    S: AsRef<str>,
+    T: 'static,
+{
+    let name = name.as_ref().to_string();
+    trace!("Fetching file '{}', resolving soft links", name);
+
+    future::loop_fn(
+        (parent, name, HashSet::new()),
+        move |(parent, name, mut visited)| {
+            shard::fetch_entry(&client, &parent, name.as_bytes())
+                .join(shard::fetch_entry(&client, &parent, &dir::link_key(&name)))
+                .and_then(move |(file, link)| {
+                    if let Some(value) = file {
+                        if !value.content.is_empty() {
+                            let file = File::deserialise(&value.content)?;
+                            return Ok(Loop::Break((value.entry_version, file)));
+                        }
+                    }
+
+                    let link: Link = match link {
+                        Some(ref value) if !value.content.is_empty() => {
+                            deserialise(&value.content)?
+                        }
+                        _ => return Err(NfsError::FileNotFound),
+                    };
+
+                    if !visited.insert((parent.name, parent.type_tag, name)) {
+                        return Err(NfsError::LinkCycle);
+                    }
+
+                    Ok(Loop::Continue((link.dir, link.name, visited)))
+                })
+        },
+    ).into_box()
+}
+
+/// Copies the file `target_name` in `target_parent` into `parent` under
+/// `name`, resolving soft links. The copy shares the data of the target, so no
+/// content is uploaded. Unlike a hard link, the copy is a separate `File`
+/// record: updating or deleting one of them leaves the other unchanged. Use
+/// `symlink` to have another name always refer to the current file.
+/// Returns the version of the target which has been copied.
+pub fn copy<S, D, T>(
+    client: Client<T>,
+    target_parent: MDataInfo,
+    target_name: S,
+    parent: MDataInfo,
+    name: D,
+) -> Box<NfsFuture<u64>>
+where
+    S: AsRef<str>,
+    D: AsRef<str>,
+    T: 'static,
+{
+    let name = name.as_ref().to_string();
+    trace!("Copying a file as '{}'", name);
+
+    let client2 = client.clone();
+
+    fetch_resolved(client, target_parent, target_name)
+        .and_then(move |(version, file)| {
+            insert(client2, parent, name, &file).map(move |_| version)
+        })
+        .into_box()
+}
+
+/// Accumulates file operations against one directory and commits them as a
+/// single mutation, so either all of them are applied or none is.
+///
+/// Conflicting entry versions are resolved the same way as in
+/// `recovery::mutate_mdata_entries`: inserting an existing file replaces it,
+/// updating a missing file inserts it, deleting a missing file is skipped and
+/// wrong versions are replaced with the correct ones. In particular, passing
+/// 0 as the version of an update or delete uses the current version plus one.
+///
+/// Files of a sharded directory may be spread over several `MutableData`s,
+/// which can't be mutated atomically, so committing a batch on such a
+/// directory fails.
+pub struct Batch {
+    parent: MDataInfo,
+    ops: BTreeMap<String, BatchOp>,
+}
+
+enum BatchOp {
+    Insert(File),
+    Update(File, u64),
+    Delete(u64),
+}
+
+impl Batch {
+    /// Create a new empty batch of operations on files in `parent`.
+    pub fn new(parent: MDataInfo) -> Self {
+        Batch {
+            parent,
+            ops: BTreeMap::new(),
+        }
+    }
+
+    /// Insert the file into the directory. Replaces any previous operation on
+    /// a file of the same name in this batch.
+    pub fn insert<S: AsRef<str>>(mut self, name: S, file: File) -> Self {
+        let _ = self.ops.insert(name.as_ref().to_string(), BatchOp::Insert(file));
+        self
+    }
+
+    /// Update the file. Replaces any previous operation on a file of the same
+    /// name in this batch.
+    pub fn update<S: AsRef<str>>(mut self, name: S, file: File, version: u64) -> Self {
+        let _ = self.ops.insert(name.as_ref().to_string(), BatchOp::Update(file, version));
+        self
+    }
+
+    /// Delete the file. Replaces any previous operation on a file of the same
+    /// name in this batch.
+    pub fn delete<S: AsRef<str>>(mut self, name: S, version: u64) -> Self {
+        let _ = self.ops.insert(name.as_ref().to_string(), BatchOp::Delete(version));
+        self
+    }
+
+    /// Returns `true` if the batch contains no operations.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Apply all operations of the batch in a single mutation.
+    pub fn commit<T: 'static>(self, client: &Client<T>) -> Box<NfsFuture<()>> {
+        trace!(
+            "Committing a batch of {} file operations on directory {:?}",
+            self.ops.len(),
+            self.parent.name
+        );
+
+        if self.ops.is_empty() {
+            return ok!(());
+        }
+
+        let parent = self.parent;
+        let mut actions = BTreeMap::new();
+        let mut changes = Vec::with_capacity(self.ops.len());
+
+        for (name, op) in self.ops {
+            let key = fry!(parent.enc_entry_key(name.as_bytes()));
+            let action = match op {
+                BatchOp::Insert(file) => {
+                    let content = fry!(encrypt_file(&parent, &file));
+                    changes.push((name, Some(file)));
+                    EntryAction::Ins(Value {
+                        content,
+                        entry_version: 0,
+                    })
+                }
+                BatchOp::Update(file, version) => {
+                    let content = fry!(encrypt_file(&parent, &file));
+                    changes.push((name, Some(file)));
+                    EntryAction::Update(Value {
+                        content,
+                        entry_version: version,
+                    })
+                }
+                BatchOp::Delete(version) => {
+                    changes.push((name, None));
+                    EntryAction::Del(version)
+                }
+            };
+            let _ = actions.insert(key, action);
+        }
+
+        let client = client.clone();
+
+        shard::fetch_shards(&client, &parent)
+            .and_then(move |shards| if shards.is_some() {
+                err!(NfsError::from("Batches can't be committed on sharded directories"))
+            } else {
+                commit_actions(client, parent, actions, changes)
+            })
+            .into_box()
+    }
+}
+
+// Mutate the entries of the directory together with its usage record,
+// recovering from errors like `recovery::mutate_mdata_entries` does. The usage
+// is recomputed from the fresh record on every attempt, so a concurrent update
+// of the record is never overwritten.
+fn commit_actions<T: 'static>(
+    client: Client<T>,
+    parent: MDataInfo,
+    actions: BTreeMap<Vec<u8>, EntryAction>,
+    changes: Vec<(String, Option<File>)>,
+) -> Box<NfsFuture<()>> {
+    future::loop_fn((1, actions), move |(attempts, actions)| {
+        let client2 = client.clone();
+        let parent2 = parent.clone();
+
+        usage::update_action(&client, &parent, changes.clone())
+            .and_then(move |usage_action| {
+                let mut all_actions = actions.clone();
+                let usage_key = usage_action.map(|(_, key, action)| {
+                    let _ = all_actions.insert(key.clone(), action);
+                    key
+                });
+
+                client2
+                    .mutate_mdata_entries(parent2.name, parent2.type_tag, all_actions)
+                    .map(|_| Loop::Break(()))
+                    .or_else(move |error| {
+                        if attempts >= usage::MAX_ATTEMPTS {
+                            return Err(convert_error(error));
+                        }
+                        match error {
+                            CoreError::RoutingClientError(
+                                ClientError::InvalidEntryActions(mut errors)
+                            ) => {
+                                if let Some(key) = usage_key {
+                                    let _ = errors.remove(&key);
+                                }
+                                let actions = recovery::fix_entry_actions(actions, &errors);
+                                Ok(Loop::Continue((attempts + 1, actions)))
+                            }
+                            CoreError::RequestTimeout => {
+                                Ok(Loop::Continue((attempts + 1, actions)))
+                            }
+                            error => Err(convert_error(error)),
+                        }
+                    })
+            })
+            .into_box()
+    }).into_box()
+}
+
+fn encrypt_file(parent: &MDataInfo, file: &File) -> Result<Vec<u8>, NfsError> {
+    let encoded = serialise(file)?;
+    Ok(parent.enc_entry_value(&encoded)?)
+}
+
+// Mutate the entries of `target`, which is either `parent` or one of its
+// shards, updating the usage record of `parent` (if it tracks its usage) to
+// account for the `changes` of the files, as described in
+// `usage::update_action`. The record is updated in the same mutation, unless
+// the files are in a shard. Either way, a concurrent update of the record
+// makes the usage be recomputed from the fresh record, never overwritten.
+fn mutate_with_usage<T: 'static>(
+    client: &Client<T>,
+    parent: &MDataInfo,
+    target: &MDataInfo,
+    actions: BTreeMap<Vec<u8>, EntryAction>,
+    changes: Vec<(String, Option<File>)>,
+) -> Box<NfsFuture<()>> {
+    let client = client.clone();
+    let parent = parent.clone();
+    let target = target.clone();
+
+    if !is_same_mdata(&parent, &target) {
+        let client2 = client.clone();
+
+        return usage::update_action(&client, &parent, changes)
+            .and_then(move |usage_action| {
+                client
+                    .mutate_mdata_entries(target.name, target.type_tag, actions)
+                    .map_err(convert_error)
+                    .map(move |_| usage_action)
+            })
+            .and_then(move |usage_action| match usage_action {
+                Some((delta, _, _)) => usage::apply(&client2, &parent, delta),
+                None => ok!(()),
+            })
+            .into_box();
+    }
+
+    future::loop_fn(1, move |attempts| {
+        let client2 = client.clone();
+        let target = target.clone();
+        let mut actions = actions.clone();
+
+        usage::update_action(&client, &parent, changes.clone())
+            .and_then(move |usage_action| {
+                let usage_key = usage_action.map(|(_, key, action)| {
+                    let _ = actions.insert(key.clone(), action);
+                    key
+                });
+
+                client2
+                    .mutate_mdata_entries(target.name, target.type_tag, actions)
+                    .map(|_| Loop::Break(()))
+                    .or_else(move |error| {
+                        let conflict = usage_key.map_or(false, |key| {
+                            usage::is_conflict(&error, &key)
+                        });
+                        if conflict && attempts < usage::MAX_ATTEMPTS {
+                            Ok(Loop::Continue(attempts + 1))
+                        } else {
+                            Err(convert_error(error))
+                        }
+                    })
+            })
+            .into_box()
+    }).into_box()
+}
+
+// Resolve a conflict between `ours`, the rejected version of the file, and
+// `theirs`, its current version, according to the policy.
+fn resolve_conflict<T: 'static>(
+    client: Client<T>,
+    parent: MDataInfo,
+    name: String,
+    ours: File,
+    theirs: File,
+    policy: ConflictPolicy,
+) -> Box<NfsFuture<Option<String>>> {
+    match policy {
+        ConflictPolicy::Fail => err!(NfsError::Conflict(ours, theirs)),
+        ConflictPolicy::KeepCopy => {
+            future::loop_fn(1, move |n| {
+                let copy = conflict::copy_name(&name, n);
+                insert(client.clone(), parent.clone(), &copy, &ours).then(
+                    move |res| match res {
+                        Ok(()) => Ok(Loop::Break(Some(copy))),
+                        Err(NfsError::FileExists) => Ok(Loop::Continue(n + 1)),
+                        Err(err) => Err(err),
+                    },
+                )
+            }).into_box()
+        }
+    }
+}
+
+// Returns `true` if the mutation failed because of outdated entry versions.
+fn is_invalid_entry_actions(err: &NfsError) -> bool {
+    match *err {
+        NfsError::CoreError(CoreError::RoutingClientError(ClientError::InvalidEntryActions(_))) => {
+            true
+        }
+        _ => false,
+    }
+}
+
+// Returns `true` if both refer to the same `MutableData`.
+fn is_same_mdata(a: &MDataInfo, b: &MDataInfo) -> bool {
+    a.name == b.name && a.type_tag == b.type_tag
+}
+
+// Fetch the decrypted entry of the file together with the directory or shard
+// holding it, failing with `FileNotFound` if there is no such entry or it has
+// been deleted.
+fn fetch_file_entry<T: 'static>(
+    client: &Client<T>,
+    parent: &MDataInfo,
+    name: &str,
+) -> Box<NfsFuture<(MDataInfo, Value)>> {
+    shard::locate(client, parent, name.as_bytes())
+        .and_then(|(target, value)| match value {
+            Some(ref value) if value.content.is_empty() => Err(NfsError::FileNotFound),
+            Some(value) => Ok((target, value)),
+            None => Err(NfsError::FileNotFound),
+        })
+        .into_box()
+}
+
+// Returns the entry version an action sets.
+fn action_version(action: &EntryAction) -> u64 {
+    match *action {
+        EntryAction::Ins(ref value) |
+        EntryAction::Update(ref value) => value.entry_version,
+        EntryAction::Del(version) => version,
+    }
+}
+
+// Fetch the history of the file together with the version of the entry
+// holding it, which is `None` if the entry doesn't exist yet.
+fn fetch_history<T: 'static>(
+    client: &Client<T>,
+    parent: &MDataInfo,
+    name: &str,
+) -> Box<NfsFuture<(Option<u64>, Vec<(u64, File)>)>> {
+    dir::fetch_entry(client, parent, &dir::history_key(name))
+        .and_then(|value| match value {
+            Some(ref value) if value.content.is_empty() => Ok((Some(value.entry_version), vec![])),
+            Some(value) => {
+                let history = file::deserialise_compat(
+                    &value.content,
+                    |history: Vec<(u64, LegacyFile)>| {
+                        history
+                            .into_iter()
+                            .map(|(version, file)| (version, File::from(file)))
+                            .collect()
+                    },
+                )?;
+                Ok((Some(value.entry_version), history))
+            }
+            None => Ok((None, vec![])),
+        })
+        .into_box()
+}
+
 // This is different from `impl From<CoreError> for NfsError`, because it maps
 // `NoSuchEntry` to `FileNotFound`.
 // TODO:  consider performing such conversion directly in the mentioned `impl From`.
@@ -15,23 +15,39 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 
+/// `DirHelper` provides path-based operations on nested directories
+pub mod dir_helper;
 /// `FileHelper` provides functions for CRUD on file
 pub mod file_helper;
+/// Helpers for publishing unencrypted file trees
+pub mod public;
+/// Synchronous, path-based filesystem interface
+pub mod vfs;
 
 mod errors;
+mod conflict;
 mod data_map;
+mod digest;
 mod dir;
 mod file;
+mod link;
 mod reader;
+mod shard;
 #[cfg(test)]
 mod tests;
+mod trash;
+mod usage;
 mod writer;
 
+pub use self::conflict::ConflictPolicy;
 pub use self::dir::create_dir;
 pub use self::errors::NfsError;
-pub use self::file::File;
+pub use self::file::{File, Metadata};
+pub use self::link::Link;
 pub use self::reader::Reader;
-pub use self::writer::{Mode, Writer};
+pub use self::trash::TrashedFile;
+pub use self::usage::{Quota, Usage};
+pub use self::writer::{MAX_INLINE_SIZE, Mode, Writer};
 use futures::Future;
 
 /// Helper type for futures that can result in `NfsError`
@@ -0,0 +1,268 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Helpers for publishing unencrypted file trees, such as static websites.
+//! Published trees can be read by anyone, including unregistered clients.
+
+use chrono::{DateTime, Utc};
+use client::{Client, MDataInfo};
+use futures::{Future, future};
+use futures::future::{Either, Loop};
+use maidsafe_utilities::serialisation::{deserialise, serialise};
+use nfs::{File, Mode, NfsError, NfsFuture, create_dir, dir, dir_helper, file_helper, shard};
+use nfs::dir_helper::Node;
+use routing::{EntryAction, Value, XorName};
+use std::collections::BTreeMap;
+use utils::FutureExt;
+
+/// Name of the file served when a directory is resolved.
+pub const DEFAULT_DOCUMENT: &'static str = "index.html";
+
+/// Browsable description of a published tree.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Index {
+    /// Files of the tree, keyed by their `/`-separated path relative to the
+    /// root directory.
+    pub files: BTreeMap<String, IndexEntry>,
+}
+
+/// Description of a single file in an `Index`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// Size of the file in bytes.
+    pub size: u64,
+    /// Modification time of the file.
+    pub modified: DateTime<Utc>,
+}
+
+/// Create a new, empty public directory with the given type tag.
+pub fn create_public_dir<T: 'static>(
+    client: &Client<T>,
+    type_tag: u64,
+) -> Box<NfsFuture<MDataInfo>> {
+    let dir = fry!(MDataInfo::random_public(type_tag));
+    let entries = fry!(shard::reserved_entries(&dir));
+    create_dir(client, &dir, entries, btree_map![])
+        .map(move |_| dir)
+        .into_box()
+}
+
+/// Publish `content` as the unencrypted file at `path`, relative to the public
+/// directory `root`. Missing intermediate directories are created and an
+/// existing file is overwritten.
+pub fn write_file<S, T>(
+    client: Client<T>,
+    root: MDataInfo,
+    path: S,
+    content: &[u8],
+) -> Box<NfsFuture<File>>
+where
+    S: AsRef<str>,
+    T: 'static,
+{
+    if root.enc_info.is_some() {
+        return err!(NfsError::from("Directory is not public"));
+    }
+
+    let path = path.as_ref().trim_matches('/');
+    let (parent, name) = match path.rfind('/') {
+        Some(pos) => (&path[..pos], path[pos + 1..].to_string()),
+        None => ("", path.to_string()),
+    };
+    if name.is_empty() {
+        return err!(NfsError::from("Invalid path"));
+    }
+    trace!("Publishing file '{}'", path);
+
+    let content = content.to_vec();
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let client4 = client.clone();
+
+    dir_helper::mkdir(client, root, parent, true)
+        .and_then(move |parent| {
+            file_helper::fetch(client2, parent.clone(), name.clone())
+                .then(move |res| match res {
+                    Ok(existing) => Ok((parent, name, Some(existing))),
+                    Err(NfsError::FileNotFound) => Ok((parent, name, None)),
+                    Err(err) => Err(err),
+                })
+        })
+        .and_then(move |(parent, name, existing)| {
+            let file = existing.as_ref().map_or_else(
+                || File::new(Vec::new()),
+                |&(_, ref file)| file.clone(),
+            );
+            file_helper::write(client3, file, Mode::Overwrite, None)
+                .and_then(move |writer| {
+                    writer.write(&content).and_then(move |_| writer.close())
+                })
+                .map(move |file| (parent, name, existing.map(|(version, _)| version), file))
+        })
+        .and_then(move |(parent, name, version, file)| {
+            let fut = match version {
+                Some(version) => file_helper::update(client4, parent, name, &file, version + 1),
+                None => file_helper::insert(client4, parent, name, &file),
+            };
+            fut.map(move |_| file)
+        })
+        .into_box()
+}
+
+/// Build the index of the whole tree under the public directory `root` and
+/// store it in `root`, replacing any previous one. The index is not updated
+/// automatically, so this should be called after the tree is published.
+pub fn update_index<T: 'static>(client: Client<T>, root: MDataInfo) -> Box<NfsFuture<Index>> {
+    if root.enc_info.is_some() {
+        return err!(NfsError::from("Directory is not public"));
+    }
+
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let client4 = client.clone();
+    let root2 = root.clone();
+
+    build_index(client, root.clone())
+        .and_then(move |index| {
+            let content = fry!(serialise(&index));
+            file_helper::write(client2, File::new(Vec::new()), Mode::Overwrite, None)
+                .and_then(move |writer| {
+                    writer.write(&content).and_then(move |_| writer.close())
+                })
+                .map(move |file| (index, file))
+                .into_box()
+        })
+        .and_then(move |(index, file)| {
+            dir::fetch_entry(&client3, &root, dir::INDEX_KEY)
+                .map(move |current| (index, file, current))
+        })
+        .and_then(move |(index, file, current)| {
+            let root = root2;
+            let value = Value {
+                content: fry!(root.enc_entry_value(&fry!(serialise(&file)))),
+                entry_version: current.as_ref().map_or(0, |value| value.entry_version + 1),
+            };
+            let action = if current.is_some() {
+                EntryAction::Update(value)
+            } else {
+                EntryAction::Ins(value)
+            };
+            let key = fry!(root.enc_entry_key(dir::INDEX_KEY));
+
+            client4
+                .mutate_mdata_entries(root.name, root.type_tag, btree_map![key => action])
+                .map_err(NfsError::from)
+                .map(move |_| index)
+                .into_box()
+        })
+        .into_box()
+}
+
+/// Fetch the index stored in the public directory `root`, if any.
+pub fn fetch_index<T: 'static>(
+    client: Client<T>,
+    root: &MDataInfo,
+) -> Box<NfsFuture<Option<Index>>> {
+    dir::fetch_entry(&client, root, dir::INDEX_KEY)
+        .and_then(move |value| match value {
+            Some(ref value) if !value.content.is_empty() => {
+                let file = fry!(File::deserialise(&value.content));
+                read_all(client, &file)
+                    .and_then(|content| Ok(Some(deserialise::<Index>(&content)?)))
+                    .into_box()
+            }
+            _ => ok!(None),
+        })
+        .into_box()
+}
+
+/// Get the content of the file at `path` in the public directory identified
+/// by `name` and `type_tag`. Soft links are followed and directories resolve
+/// to their `DEFAULT_DOCUMENT`. Doesn't require an account, so it can be used
+/// from an unregistered client.
+pub fn resolve<S, T>(
+    client: Client<T>,
+    name: XorName,
+    type_tag: u64,
+    path: S,
+) -> Box<NfsFuture<Vec<u8>>>
+where
+    S: AsRef<str>,
+    T: 'static,
+{
+    let root = MDataInfo::new_public(name, type_tag);
+    let client2 = client.clone();
+    let client3 = client.clone();
+
+    dir_helper::stat(client, root, path)
+        .and_then(move |node| match node {
+            Node::File { file, .. } => ok!(file),
+            Node::Dir { info, .. } => {
+                file_helper::fetch(client2, info, DEFAULT_DOCUMENT)
+                    .map(|(_, file)| file)
+                    .into_box()
+            }
+            Node::Link { link, .. } => {
+                file_helper::fetch_resolved(client2, link.dir, link.name)
+                    .map(|(_, file)| file)
+                    .into_box()
+            }
+        })
+        .and_then(move |file| read_all(client3, &file))
+        .into_box()
+}
+
+// Collect the files of the tree under `root`, skipping soft links.
+fn build_index<T: 'static>(client: Client<T>, root: MDataInfo) -> Box<NfsFuture<Index>> {
+    let state = (Index::default(), vec![(String::new(), root)]);
+
+    future::loop_fn(state, move |(mut index, mut queue)| {
+        let (prefix, dir) = match queue.pop() {
+            Some(next) => next,
+            None => return Either::A(future::ok(Loop::Break(index))),
+        };
+
+        let fut = dir_helper::list(client.clone(), dir, "").map(move |nodes| {
+            for (name, node) in nodes {
+                let path = if prefix.is_empty() {
+                    name
+                } else {
+                    format!("{}/{}", prefix, name)
+                };
+                match node {
+                    Node::File { file, .. } => {
+                        let entry = IndexEntry {
+                            size: file.size(),
+                            modified: *file.modified_time(),
+                        };
+                        let _ = index.files.insert(path, entry);
+                    }
+                    Node::Dir { info, .. } => queue.push((path, info)),
+                    Node::Link { .. } => (),
+                }
+            }
+            Loop::Continue((index, queue))
+        });
+        Either::B(fut)
+    }).into_box()
+}
+
+fn read_all<T: 'static>(client: Client<T>, file: &File) -> Box<NfsFuture<Vec<u8>>> {
+    file_helper::read(client, file, None)
+        .and_then(|reader| reader.read_clamped(0, reader.size()))
+        .into_box()
+}
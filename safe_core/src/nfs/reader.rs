@@ -17,10 +17,13 @@
 
 use client::Client;
 use crypto::shared_secretbox;
-use futures::Future;
+use futures::{Future, Stream, stream};
 use nfs::{File, NfsError, NfsFuture, data_map};
+use nfs::digest;
 use self_encryption::SelfEncryptor;
 use self_encryption_storage::SelfEncryptionStorage;
+use std::cmp;
+use std::rc::Rc;
 use utils::FutureExt;
 
 /// Reader is used to read contents of a File. It can read in chunks if the
@@ -28,7 +31,11 @@ use utils::FutureExt;
 #[allow(dead_code)]
 pub struct Reader<T> {
     client: Client<T>,
-    self_encryptor: SelfEncryptor<SelfEncryptionStorage<T>>,
+    self_encryptor: Rc<SelfEncryptor<SelfEncryptionStorage<T>>>,
+    digest: Option<[u8; 32]>,
+    // Size of the file. Anything past the data read by the self-encryptor is
+    // a hole in a sparse file.
+    size: u64,
 }
 
 impl<T: 'static> Reader<T> {
@@ -39,13 +46,19 @@ impl<T: 'static> Reader<T> {
         file: &File,
         encryption_key: Option<shared_secretbox::Key>,
     ) -> Box<NfsFuture<Reader<T>>> {
-        data_map::get(&client, file.data_map_name(), encryption_key)
+        let digest = file.digest().cloned();
+        let size = file.size();
+
+        data_map::get_for_file(&client, file, encryption_key)
             .and_then(move |data_map| {
                 let self_encryptor = SelfEncryptor::new(storage, data_map)?;
+                let size = cmp::max(size, self_encryptor.len());
 
                 Ok(Reader {
                     client: client,
-                    self_encryptor: self_encryptor,
+                    self_encryptor: Rc::new(self_encryptor),
+                    digest: digest,
+                    size: size,
                 })
             })
             .into_box()
@@ -53,10 +66,10 @@ impl<T: 'static> Reader<T> {
 
     /// Returns the total size of the file/blob
     pub fn size(&self) -> u64 {
-        self.self_encryptor.len()
+        self.size
     }
 
-    /// Read data from file/blob
+    /// Read data from file/blob. Holes in sparse files read as zeros.
     pub fn read(&self, position: u64, length: u64) -> Box<NfsFuture<Vec<u8>>> {
         trace!(
             "Reader reading from pos: {} and size: {}.",
@@ -72,10 +85,113 @@ impl<T: 'static> Reader<T> {
                 len = length,
                 pos = position
             );
-            self.self_encryptor
-                .read(position, length)
-                .map_err(From::from)
-                .into_box()
+            // Only the part of the range covered by stored data is read, the
+            // rest is a hole.
+            let data_size = self.self_encryptor.len();
+            let data_length = if position >= data_size {
+                0
+            } else {
+                cmp::min(length, data_size - position)
+            };
+            let fut = if data_length == 0 {
+                ok!(Vec::new())
+            } else {
+                self.self_encryptor
+                    .read(position, data_length)
+                    .map_err(From::from)
+                    .into_box()
+            };
+
+            fut.map(move |mut data| {
+                data.resize(length as usize, 0);
+                data
+            }).into_box()
+        }
+    }
+
+    /// Read data from file/blob, returning fewer bytes than requested if the
+    /// range crosses the end of the file. Reading at or past the end returns
+    /// an empty vector.
+    pub fn read_clamped(&self, position: u64, length: u64) -> Box<NfsFuture<Vec<u8>>> {
+        let size = self.size();
+        let length = if position >= size {
+            0
+        } else {
+            cmp::min(length, size - position)
+        };
+
+        if length == 0 {
+            ok!(Vec::new())
+        } else {
+            self.read(position, length)
         }
     }
+
+    /// Turn the reader into a stream of consecutive chunks of `chunk_size`
+    /// bytes covering the whole file/blob. The last chunk may be shorter.
+    /// Up to `read_ahead` chunks following the one being consumed are fetched
+    /// in the background, so at most `(read_ahead + 1) * chunk_size` bytes
+    /// are held in memory.
+    pub fn stream(
+        self,
+        chunk_size: u64,
+        read_ahead: usize,
+    ) -> Box<Stream<Item = Vec<u8>, Error = NfsError>> {
+        trace!(
+            "Reader streaming in chunks of size: {} with read-ahead: {}.",
+            chunk_size,
+            read_ahead
+        );
+
+        if chunk_size == 0 {
+            return Box::new(stream::once(Err(NfsError::InvalidRange)));
+        }
+
+        let size = self.size();
+        let num_chunks = size / chunk_size + if size % chunk_size == 0 { 0 } else { 1 };
+
+        let chunks = stream::iter_ok((0..num_chunks).map(move |index| index * chunk_size))
+            .map(move |position| self.read_clamped(position, chunk_size))
+            .buffered(read_ahead + 1);
+
+        Box::new(chunks)
+    }
+
+    /// Returns the SHA3-256 digest of the content. If the `File` doesn't store
+    /// it, e.g. because it was appended to or modified, the digest is
+    /// computed by reading the whole file/blob.
+    pub fn digest(&self) -> Box<NfsFuture<[u8; 32]>> {
+        if let Some(digest) = self.digest {
+            return ok!(digest);
+        }
+        self.compute_digest()
+    }
+
+    /// Re-read the whole file/blob and check it against the digest stored in
+    /// the `File`, failing with `NfsError::DigestMismatch` if they differ.
+    /// Files without a digest (e.g. appended to or modified) can't be verified
+    /// and fail with `NfsError::DigestUnknown`.
+    pub fn verify(&self) -> Box<NfsFuture<()>> {
+        let expected = match self.digest {
+            Some(expected) => expected,
+            None => return err!(NfsError::DigestUnknown),
+        };
+        trace!("Reader verifying the file digest.");
+
+        self.compute_digest()
+            .and_then(move |digest| if digest == expected {
+                Ok(())
+            } else {
+                Err(NfsError::DigestMismatch)
+            })
+            .into_box()
+    }
+
+    fn compute_digest(&self) -> Box<NfsFuture<[u8; 32]>> {
+        let hole_size = self.size - self.self_encryptor.len();
+
+        digest::hash_content(self.self_encryptor.clone(), digest::hasher())
+            .map(move |hasher| digest::finalise(digest::hash_zeros(hasher, hole_size)))
+            .into_box()
+    }
 }
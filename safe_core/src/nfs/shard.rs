@@ -0,0 +1,308 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Sharding of large directories. Once a directory is full, new entries spill
+//! into a fixed number of shards: child `MutableData`s listed in the shard
+//! table entry of the directory. The shard of an entry is selected by the hash
+//! of its encrypted key; shards use the same encryption as the directory, so
+//! the key is the same in all of them. Shards which fill up are sharded
+//! further in the same way, using the next byte of the hash at each level.
+//! The history of a file is kept next to the file, all other entries (files,
+//! subdirectories, links and trash entries) are sharded by their own key.
+//!
+//! Directories are only split when an insertion fails with `TooManyEntries`,
+//! so a directory which isn't full is used without looking at its shard table
+//! at all. The shard table is stored in a slot reserved when the directory is
+//! created (see `reserved_entries`), as the full directory has no room left
+//! for a new entry. Deleted entries keep their slot, so a split directory
+//! stays full and every insertion of a new key into it fails, leading to its
+//! shards. As there's no room left either for the history of a file kept in a
+//! full directory, only files which already have a history can be updated
+//! keeping it there.
+//!
+//! An entry is stored in the first directory on its path already holding an
+//! entry with that key (possibly deleted), so existing entries never move.
+
+use client::{Client, MDataInfo};
+use errors::CoreError;
+use futures::{Future, future};
+use futures::future::Loop;
+use maidsafe_utilities::serialisation::{deserialise, serialise};
+use nfs::{NfsError, NfsFuture, create_dir, dir};
+use routing::{ClientError, EntryAction, Value};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use tiny_keccak::sha3_256;
+use utils::FutureExt;
+
+/// Number of shards a directory is split into.
+pub const SHARD_COUNT: usize = 16;
+
+/// Entries a new directory has to be created with, so it can be split once
+/// it's full: the empty slot of its shard table. Directories created without
+/// it fail with `TooManyEntries` once full.
+pub fn reserved_entries(dir: &MDataInfo) -> Result<BTreeMap<Vec<u8>, Value>, NfsError> {
+    Ok(btree_map![
+        dir.enc_entry_key(dir::SHARDS_KEY)? => Value {
+            content: Vec::new(),
+            entry_version: 0,
+        }
+    ])
+}
+
+/// Fetch the shards of the directory, or `None` if it isn't sharded.
+pub fn fetch_shards<T: 'static>(
+    client: &Client<T>,
+    dir: &MDataInfo,
+) -> Box<NfsFuture<Option<Vec<MDataInfo>>>> {
+    dir::fetch_entry(client, dir, dir::SHARDS_KEY)
+        .and_then(|value| match value {
+            Some(ref value) if !value.content.is_empty() => Ok(Some(deserialise(&value.content)?)),
+            _ => Ok(None),
+        })
+        .into_box()
+}
+
+/// Find the directory or shard holding the entry with the plaintext `key`,
+/// starting at `dir`. Returns it together with the entry, which is deleted if
+/// its content is empty. If there is no such entry, `None` is returned
+/// together with the last directory or shard searched. The shard table of a
+/// directory is only fetched if the entry isn't found in the directory itself.
+pub fn locate<T: 'static>(
+    client: &Client<T>,
+    dir: &MDataInfo,
+    key: &[u8],
+) -> Box<NfsFuture<(MDataInfo, Option<Value>)>> {
+    let client = client.clone();
+    let key = key.to_vec();
+
+    future::loop_fn((dir.clone(), 0), move |(dir, depth)| {
+        let client2 = client.clone();
+        let key = key.clone();
+
+        dir::fetch_entry(&client, &dir, &key).and_then(move |value| {
+            if value.is_some() {
+                return ok!(Loop::Break((dir, value)));
+            }
+
+            fetch_shards(&client2, &dir)
+                .and_then(move |shards| match shards {
+                    Some(ref shards) if !shards.is_empty() => {
+                        let index = shard_index(&dir, &key, depth, shards.len())?;
+                        Ok(Loop::Continue((shards[index].clone(), depth + 1)))
+                    }
+                    _ => Ok(Loop::Break((dir, None))),
+                })
+                .into_box()
+        })
+    }).into_box()
+}
+
+/// Fetch a single entry of the directory following its shards. Returns `None`
+/// if there is no such entry.
+pub fn fetch_entry<T: 'static>(
+    client: &Client<T>,
+    dir: &MDataInfo,
+    key: &[u8],
+) -> Box<NfsFuture<Option<Value>>> {
+    locate(client, dir, key).map(|(_, value)| value).into_box()
+}
+
+/// Insert an entry with the plaintext `key`, following the shards of the
+/// directory. `mutate` is called with the directory or shard holding the entry
+/// (or the one it should be inserted into) and the current value of the entry,
+/// and is expected to perform the mutation. Should it fail with
+/// `TooManyEntries`, the directory is split unless it already is, and `mutate`
+/// is called again with the shard of the entry. So inserting into a directory
+/// which isn't full costs the fetch of the entry only.
+pub fn insert<T, F, R>(
+    client: &Client<T>,
+    dir: &MDataInfo,
+    key: &[u8],
+    mutate: F,
+) -> Box<NfsFuture<R>>
+where
+    T: 'static,
+    F: Fn(MDataInfo, Option<Value>) -> Box<NfsFuture<R>> + 'static,
+    R: 'static,
+{
+    let client = client.clone();
+    let key = key.to_vec();
+    let mutate = Rc::new(mutate);
+
+    future::loop_fn((dir.clone(), 0), move |(dir, depth)| {
+        let client2 = client.clone();
+        let key = key.clone();
+        let mutate = Rc::clone(&mutate);
+
+        dir::fetch_entry(&client, &dir, &key).and_then(move |value| {
+            // Existing entries are updated in place, so only an insertion of a
+            // new one can fail for lack of room.
+            let new = value.is_none();
+
+            (*mutate)(dir.clone(), value).then(move |res| match res {
+                Ok(res) => ok!(Loop::Break(res)),
+                Err(ref err) if new && is_too_many_entries(err) => {
+                    trace!("Directory {:?} is full, inserting into its shard", dir.name);
+                    shards(&client2, &dir)
+                        .and_then(move |shards| {
+                            let index = shard_index(&dir, &key, depth, shards.len())?;
+                            Ok(Loop::Continue((shards[index].clone(), depth + 1)))
+                        })
+                        .into_box()
+                }
+                Err(err) => err!(err),
+            })
+        })
+    }).into_box()
+}
+
+/// Fetch all live entries of the directory and of all its shards, decrypted.
+pub fn list_entries<T: 'static>(
+    client: &Client<T>,
+    dir: &MDataInfo,
+) -> Box<NfsFuture<BTreeMap<Vec<u8>, Value>>> {
+    let client = client.clone();
+
+    dir::list_entries(&client, dir)
+        .and_then(move |mut entries| {
+            let shards: Vec<MDataInfo> = match entries.get(dir::SHARDS_KEY) {
+                Some(value) => fry!(deserialise(&value.content)),
+                None => return ok!(entries),
+            };
+            let shards_fut = shards
+                .iter()
+                .map(|shard| list_entries(&client, shard))
+                .collect::<Vec<_>>();
+
+            future::join_all(shards_fut)
+                .map(move |shard_entries| {
+                    for (key, value) in shard_entries.into_iter().flat_map(|e| e) {
+                        let _ = entries.entry(key).or_insert(value);
+                    }
+                    entries
+                })
+                .into_box()
+        })
+        .into_box()
+}
+
+// Returns the shards of the full directory, splitting it first unless that has
+// been done already. Fails with `TooManyEntries` if the directory has no slot
+// reserved for its shard table.
+fn shards<T: 'static>(client: &Client<T>, dir: &MDataInfo) -> Box<NfsFuture<Vec<MDataInfo>>> {
+    let client = client.clone();
+    let dir = dir.clone();
+
+    dir::fetch_entry(&client, &dir, dir::SHARDS_KEY)
+        .and_then(move |value| match value {
+            Some(ref value) if !value.content.is_empty() => {
+                ok!(fry!(deserialise::<Vec<MDataInfo>>(&value.content)))
+            }
+            Some(value) => split(&client, &dir, value.entry_version),
+            None => err!(too_many_entries()),
+        })
+        .into_box()
+}
+
+// Split the directory into shards: create them and store the shard table in
+// its reserved slot, currently at `version`. The shards get the permissions of
+// the directory. If the directory has been split concurrently, its existing
+// shards are returned instead.
+fn split<T: 'static>(
+    client: &Client<T>,
+    dir: &MDataInfo,
+    version: u64,
+) -> Box<NfsFuture<Vec<MDataInfo>>> {
+    trace!("Splitting directory {:?} into {} shards", dir.name, SHARD_COUNT);
+
+    let mut shards = Vec::with_capacity(SHARD_COUNT);
+    for _ in 0..SHARD_COUNT {
+        let mut shard = fry!(MDataInfo::random_public(dir.type_tag));
+        shard.enc_info = dir.enc_info.clone();
+        shard.new_enc_info = dir.new_enc_info.clone();
+        shards.push(shard);
+    }
+    let key = fry!(dir.enc_entry_key(dir::SHARDS_KEY));
+    let content = fry!(dir.enc_entry_value(&fry!(serialise(&shards))));
+
+    let client = client.clone();
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let dir = dir.clone();
+
+    client
+        .list_mdata_permissions(dir.name, dir.type_tag)
+        .map_err(NfsError::from)
+        .and_then(move |perms| {
+            let create_futs = shards
+                .iter()
+                .map(|shard| {
+                    let entries = fry!(reserved_entries(shard));
+                    create_dir(&client2, shard, entries, perms.clone())
+                })
+                .collect::<Vec<_>>();
+
+            future::join_all(create_futs).map(move |_| shards)
+        })
+        .and_then(move |shards| {
+            let value = Value {
+                content,
+                entry_version: version + 1,
+            };
+            client
+                .mutate_mdata_entries(
+                    dir.name,
+                    dir.type_tag,
+                    btree_map![key => EntryAction::Update(value)],
+                )
+                .then(move |res| match res {
+                    Ok(()) => ok!(shards),
+                    Err(CoreError::RoutingClientError(ClientError::InvalidEntryActions(_))) => {
+                        fetch_shards(&client3, &dir)
+                            .and_then(|shards| {
+                                shards.ok_or_else(|| NfsError::from("Shard table not found"))
+                            })
+                            .into_box()
+                    }
+                    Err(err) => err!(NfsError::from(err)),
+                })
+        })
+        .into_box()
+}
+
+// Returns the index of the shard for the plaintext `key` at the given depth.
+fn shard_index(
+    dir: &MDataInfo,
+    key: &[u8],
+    depth: usize,
+    count: usize,
+) -> Result<usize, NfsError> {
+    let hash = sha3_256(&dir.enc_entry_key(key)?);
+    Ok(hash[depth % hash.len()] as usize % count)
+}
+
+fn is_too_many_entries(err: &NfsError) -> bool {
+    match *err {
+        NfsError::CoreError(CoreError::RoutingClientError(ClientError::TooManyEntries)) => true,
+        _ => false,
+    }
+}
+
+fn too_many_entries() -> NfsError {
+    NfsError::from(CoreError::RoutingClientError(ClientError::TooManyEntries))
+}
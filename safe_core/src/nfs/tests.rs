@@ -19,14 +19,19 @@ use DIR_TAG;
 use client::{Client, MDataInfo};
 use crypto::shared_secretbox;
 use errors::CoreError;
-use futures::Future;
+use futures::{Future, Stream};
 use futures::future::{self, Loop};
-use nfs::{File, Mode, NfsError, NfsFuture, create_dir, file_helper};
+use nfs::{ConflictPolicy, File, Link, MAX_INLINE_SIZE, Mode, NfsError, NfsFuture, Quota,
+          conflict, create_dir, dir_helper, file_helper, public};
+use nfs::dir_helper::Node;
 use nfs::reader::Reader;
+use nfs::shard::{self, SHARD_COUNT};
 use nfs::writer::Writer;
 use rand::{self, Rng};
+use routing::MAX_MUTABLE_DATA_ENTRIES;
+use tiny_keccak::sha3_256;
 use utils::FutureExt;
-use utils::test_utils::random_client;
+use utils::test_utils::{random_client, setup_client};
 
 const APPEND_SIZE: usize = 10;
 const ORIG_SIZE: usize = 5555;
@@ -172,6 +177,54 @@ fn file_read_chunks() {
 }
 
 // Test writing to files in chunks.
+#[test]
+fn file_read_stream() {
+    const CHUNK_SIZE: u64 = 1000;
+    const READ_AHEAD: usize = 2;
+
+    random_client(|client| {
+        let c2 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+
+                file_helper::read(c2, &file, dir.enc_key().cloned())
+            })
+            .then(|res| {
+                let reader = unwrap!(res);
+                let size = reader.size();
+
+                // Clamped reads return what's left before the end of the file
+                reader
+                    .read_clamped(size - 10, CHUNK_SIZE)
+                    .map(move |data| (reader, size, data))
+            })
+            .then(|res| {
+                let (reader, size, data) = unwrap!(res);
+                assert_eq!(data, vec![0u8; 10]);
+
+                reader
+                    .read_clamped(size, CHUNK_SIZE)
+                    .map(move |data| (reader, data))
+            })
+            .then(|res| {
+                let (reader, data) = unwrap!(res);
+                assert_eq!(data, Vec::<u8>::new());
+
+                reader.stream(CHUNK_SIZE, READ_AHEAD).collect()
+            })
+            .map(|chunks| {
+                let num_chunks = (ORIG_SIZE as u64 + CHUNK_SIZE - 1) / CHUNK_SIZE;
+                assert_eq!(chunks.len() as u64, num_chunks);
+                for chunk in &chunks[..chunks.len() - 1] {
+                    assert_eq!(chunk.len() as u64, CHUNK_SIZE);
+                }
+                assert_eq!(chunks.concat(), vec![0u8; ORIG_SIZE]);
+            })
+    });
+}
+
 #[test]
 fn file_write_chunks() {
     const CHUNK_SIZE: usize = 1000;
@@ -387,117 +440,173 @@ fn file_update_append() {
 }
 
 #[test]
-fn file_update_metadata() {
+fn file_update_modify() {
+    const TRUNCATED_SIZE: usize = 1000;
+    const OFFSET: usize = 100;
+
     random_client(|client| {
         let c2 = client.clone();
         let c3 = client.clone();
 
         create_test_file(client)
             .then(move |res| {
-                let (dir, mut file) = unwrap!(res);
+                let (dir, file) = unwrap!(res);
 
-                file.set_user_metadata(vec![12u8; 10]);
-                file_helper::update(c2, dir.clone(), "hello.txt", &file, 1)
-                    .map(move |()| dir)
+                // Updating file - modify in place
+                file_helper::write(c2, file, Mode::Modify, dir.enc_key().cloned())
+                    .map(move |writer| (dir, writer))
             })
             .then(move |res| {
-                let dir = unwrap!(res);
-
-                file_helper::fetch(c3.clone(), dir, "hello.txt")
+                let (dir, writer) = unwrap!(res);
+                writer
+                    .truncate(TRUNCATED_SIZE as u64)
+                    .and_then(move |_| {
+                        writer
+                            .write_at(OFFSET as u64, &[1u8; APPEND_SIZE])
+                            .map(move |_| writer)
+                    })
+                    .and_then(move |writer| {
+                        // Writing past the end extends the file.
+                        writer
+                            .write_at(TRUNCATED_SIZE as u64, &[2u8; APPEND_SIZE])
+                            .map(move |_| writer)
+                    })
+                    .and_then(move |writer| writer.close())
+                    .map(move |file| (dir, file))
             })
-            .map(move |(_version, file)| {
-                assert_eq!(*file.user_metadata(), [12u8; 10][..]);
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                assert_eq!(file.size(), (TRUNCATED_SIZE + APPEND_SIZE) as u64);
+                file_helper::read(c3, &file, dir.enc_key().cloned())
+            })
+            .then(move |res| {
+                let reader = unwrap!(res);
+                let size = reader.size();
+                reader.read(0, size)
+            })
+            .map(move |data| {
+                assert_eq!(data.len(), TRUNCATED_SIZE + APPEND_SIZE);
+                assert_eq!(data[0..OFFSET].to_owned(), vec![0u8; OFFSET]);
+                assert_eq!(&data[OFFSET..OFFSET + APPEND_SIZE], [1u8; APPEND_SIZE]);
+                assert_eq!(
+                    data[OFFSET + APPEND_SIZE..TRUNCATED_SIZE].to_owned(),
+                    vec![0u8; TRUNCATED_SIZE - OFFSET - APPEND_SIZE]
+                );
+                assert_eq!(&data[TRUNCATED_SIZE..], [2u8; APPEND_SIZE]);
             })
     });
 }
+
+// Random-access operations are not available in the sequential modes.
 #[test]
-fn file_delete() {
+fn file_write_at_requires_modify_mode() {
     random_client(|client| {
         let c2 = client.clone();
-        let c3 = client.clone();
 
         create_test_file(client)
             .then(move |res| {
-                let (dir, _file) = unwrap!(res);
-                file_helper::delete(&c2, &dir, "hello.txt", 1)
-                    .map(move |()| dir)
+                let (dir, file) = unwrap!(res);
+                file_helper::write(c2, file, Mode::Append, dir.enc_key().cloned())
             })
             .then(move |res| {
-                let dir = unwrap!(res);
-                file_helper::fetch(c3.clone(), dir, "hello.txt")
+                let writer = unwrap!(res);
+                writer.write_at(0, &[1u8; APPEND_SIZE]).then(move |res| {
+                    match res {
+                        Err(NfsError::Unexpected(_)) => (),
+                        Ok(()) => panic!("Unexpected success"),
+                        Err(err) => panic!("Unexpected error {:?}", err),
+                    }
+                    writer.truncate(0)
+                })
             })
-            .then(move |res| -> Result<_, CoreError> {
+            .then(|res| -> Result<_, NfsError> {
                 match res {
-                    Ok(_) => {
-                        // We expect an error in this case
-                        panic!("Fetched non-existing file succesfully")
-                    }
-                    Err(_) => Ok(()),
+                    Err(NfsError::Unexpected(_)) => Ok(()),
+                    Ok(()) => panic!("Unexpected success"),
+                    Err(err) => panic!("Unexpected error {:?}", err),
                 }
             })
     });
 }
 
-// Test deleting an entry and then re-adding it.
-// We should be able to successfully open and read the re-added file.
 #[test]
-fn file_delete_then_add() {
+fn file_history_and_restore() {
+    const MAX_HISTORY: usize = 1;
+
     random_client(|client| {
         let c2 = client.clone();
         let c3 = client.clone();
         let c4 = client.clone();
         let c5 = client.clone();
         let c6 = client.clone();
+        let c7 = client.clone();
+        let c8 = client.clone();
 
         create_test_file(client)
-            .then(move |res| {
-                let (dir, file) = unwrap!(res);
-                file_helper::delete(&c2, &dir, "hello.txt", 1).map(move |_| (dir, file))
-            })
             .then(move |res| {
                 let (dir, file) = unwrap!(res);
 
-                file_helper::write(c3, file, Mode::Overwrite, dir.enc_key().cloned())
-                    .map(move |writer| (writer, dir))
+                file_helper::write(c2, file, Mode::Overwrite, dir.enc_key().cloned())
+                    .map(move |writer| (dir, writer))
             })
             .then(move |res| {
-                let (writer, dir) = unwrap!(res);
-
+                let (dir, writer) = unwrap!(res);
                 writer
                     .write(&[1u8; NEW_SIZE])
                     .and_then(move |_| writer.close())
-                    .map(move |file| (file, dir))
+                    .map(move |file| (dir, file))
             })
             .then(move |res| {
-                let (file, dir) = unwrap!(res);
-                file_helper::update(c4, dir.clone(), "hello.txt", &file, 2).map(move |_| dir)
+                let (dir, file) = unwrap!(res);
+                file_helper::update_versioned(c3, dir.clone(), "hello.txt", &file, 0, MAX_HISTORY)
+                    .map(move |_| dir)
             })
             .then(move |res| {
                 let dir = unwrap!(res);
-                file_helper::fetch(c5, dir.clone(), "hello.txt").map(
-                    move |(version, file)| (version, file, dir),
-                )
+                file_helper::history(&c4, &dir, "hello.txt").map(move |history| (dir, history))
             })
             .then(move |res| {
-                let (version, file, dir) = unwrap!(res);
+                let (dir, history) = unwrap!(res);
+                assert_eq!(history.len(), 1);
+                assert_eq!(history[0].0, 0);
+                assert_eq!(history[0].1.size(), ORIG_SIZE as u64);
+
+                file_helper::restore(c5, dir.clone(), "hello.txt", 0, MAX_HISTORY)
+                    .map(move |_| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::fetch(c6, dir.clone(), "hello.txt").map(move |file| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, (version, file)) = unwrap!(res);
                 assert_eq!(version, 2);
-                file_helper::read(c6, &file, dir.enc_key().cloned())
+                assert_eq!(file.size(), ORIG_SIZE as u64);
+
+                file_helper::history(&c7, &dir, "hello.txt").map(move |history| (dir, history))
             })
             .then(move |res| {
-                let reader = unwrap!(res);
-                let size = reader.size();
-                println!("reading {} bytes", size);
-                reader.read(0, size)
+                let (dir, history) = unwrap!(res);
+
+                // The history is bounded, so the original version has been dropped
+                assert_eq!(history.len(), 1);
+                assert_eq!(history[0].0, 1);
+                assert_eq!(history[0].1.size(), NEW_SIZE as u64);
+
+                file_helper::restore(c8, dir, "hello.txt", 0, MAX_HISTORY)
             })
-            .map(move |data| {
-                assert_eq!(data, vec![1u8; NEW_SIZE]);
+            .then(|res| -> Result<_, NfsError> {
+                match res {
+                    Err(NfsError::VersionNotFound) => Ok(()),
+                    Ok(()) => panic!("Unexpected success"),
+                    Err(err) => panic!("Unexpected error {:?}", err),
+                }
             })
     });
 }
 
-// Test closing files immediately after opening them in the different modes.
 #[test]
-fn file_open_close() {
+fn file_digest() {
     random_client(|client| {
         let c2 = client.clone();
         let c3 = client.clone();
@@ -507,104 +616,1459 @@ fn file_open_close() {
         create_test_file(client)
             .then(move |res| {
                 let (dir, file) = unwrap!(res);
-                // Open the file for reading
-                file_helper::read(c2, &file, dir.enc_key().cloned()).map(
-                    move |reader| (reader, file, dir),
-                )
+                assert_eq!(file.digest(), Some(&sha3_256(&[0u8; ORIG_SIZE])));
+
+                file_helper::read(c5, &file, dir.enc_key().cloned())
+                    .and_then(|reader| reader.verify())
+                    .map(move |_| (dir, file))
             })
             .then(move |res| {
-                // The reader should get dropped implicitly
-                let (_reader, file, dir) = unwrap!(res);
-                // Open the file for writing
-                file_helper::write(c3, file.clone(), Mode::Overwrite, dir.enc_key().cloned())
-                    .map(move |writer| (writer, file, dir))
+                let (dir, file) = unwrap!(res);
+
+                // Appending doesn't read the existing data, so the digest is
+                // dropped and has to be computed by the reader.
+                file_helper::write(c2, file, Mode::Append, dir.enc_key().cloned())
+                    .map(move |writer| (dir, writer))
             })
             .then(move |res| {
-                let (writer, file, dir) = unwrap!(res);
-                // Close the file
-                let _ = writer.close();
-                // Open the file for appending
-                file_helper::write(c4, file.clone(), Mode::Append, dir.enc_key().cloned())
-                    .map(move |writer| (writer, file, dir))
+                let (dir, writer) = unwrap!(res);
+                writer
+                    .write(&[2u8; APPEND_SIZE])
+                    .and_then(move |_| writer.close())
+                    .map(move |file| (dir, file))
             })
             .then(move |res| {
-                let (writer, file, dir) = unwrap!(res);
-                // Close the file
-                let _ = writer.close();
-                // Open the file for reading, ensure it has original contents
-                file_helper::read(c5, &file, dir.enc_key().cloned())
+                let (dir, file) = unwrap!(res);
+                let mut content = vec![0u8; ORIG_SIZE];
+                content.extend_from_slice(&[2u8; APPEND_SIZE]);
+                assert_eq!(file.digest(), None);
+
+                // Without a digest, the file can't be verified.
+                file_helper::read(c3, &file, dir.enc_key().cloned())
+                    .and_then(|reader| {
+                        reader.verify().then(move |res| {
+                            match res {
+                                Err(NfsError::DigestUnknown) => (),
+                                Ok(()) => panic!("Unexpected success"),
+                                Err(err) => panic!("Unexpected error {:?}", err),
+                            }
+                            reader.digest()
+                        })
+                    })
+                    .map(move |digest| {
+                        assert_eq!(digest, sha3_256(&content));
+                        (dir, file)
+                    })
             })
             .then(move |res| {
-                let reader = unwrap!(res);
-                let size = reader.size();
-                reader.read(0, size)
+                let (dir, mut file) = unwrap!(res);
+                file.set_digest(Some([0; 32]));
+
+                file_helper::read(c4, &file, dir.enc_key().cloned())
+                    .and_then(|reader| reader.verify())
             })
-            .map(move |data| {
-                assert_eq!(data, vec![0u8; ORIG_SIZE]);
+            .then(|res| -> Result<_, NfsError> {
+                match res {
+                    Err(NfsError::DigestMismatch) => Ok(()),
+                    Ok(()) => panic!("Unexpected success"),
+                    Err(err) => panic!("Unexpected error {:?}", err),
+                }
             })
     });
 }
 
-// Create and store encrypted file and make sure it can only be read back with
-// the original encryption key.
 #[test]
-fn encryption() {
+fn file_inline() {
+    const SMALL_SIZE: usize = 20;
+
     random_client(|client| {
         let c2 = client.clone();
         let c3 = client.clone();
         let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let dir = unwrap!(MDataInfo::random_private(DIR_TAG));
 
-        let mut rng = rand::thread_rng();
+        create_dir(client, &dir, btree_map![], btree_map![])
+            .then(move |res| {
+                unwrap!(res);
+                file_helper::write(
+                    c2,
+                    File::new(Vec::new()),
+                    Mode::Overwrite,
+                    dir.enc_key().cloned(),
+                ).map(move |writer| (dir, writer))
+            })
+            .then(move |res| {
+                let (dir, writer) = unwrap!(res);
+                writer
+                    .write(&[1u8; SMALL_SIZE])
+                    .and_then(move |_| writer.close())
+                    .map(move |file| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                // The inline content is encrypted with the directory's key.
+                let inline_content = unwrap!(file.inline_content());
+                assert!(inline_content != &[1u8; SMALL_SIZE][..]);
 
-        let content: Vec<u8> = rng.gen_iter().take(ORIG_SIZE).collect();
-        let content2 = content.clone();
+                // The inline content survives storing the file in the directory
+                file_helper::insert(c3.clone(), dir.clone(), "small.txt", &file)
+                    .and_then(move |_| file_helper::fetch(c3, dir.clone(), "small.txt"))
+                    .map(move |(_, fetched)| {
+                        assert_eq!(fetched, file);
+                        (dir, fetched)
+                    })
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::read(c4, &file, dir.enc_key().cloned())
+                    .and_then(|reader| {
+                        let size = reader.size();
+                        reader.read(0, size)
+                    })
+                    .map(move |data| (dir, file, data))
+            })
+            .then(move |res| {
+                let (dir, file, data) = unwrap!(res);
+                assert_eq!(data, vec![1u8; SMALL_SIZE]);
 
-        let key = shared_secretbox::gen_key();
-        let wrong_key = shared_secretbox::gen_key();
+                // Growing the file past the threshold moves the content out
+                file_helper::write(c5, file, Mode::Append, dir.enc_key().cloned())
+                    .map(move |writer| (dir, writer))
+            })
+            .then(move |res| {
+                let (dir, writer) = unwrap!(res);
+                writer
+                    .write(&[2u8; MAX_INLINE_SIZE])
+                    .and_then(move |_| writer.close())
+                    .map(move |file| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                assert!(file.inline_content().is_none());
 
-        file_helper::write(
-            client.clone(),
-            File::new(Vec::new()),
-            Mode::Overwrite,
-            Some(key.clone()),
-        ).then(move |res| {
-            let writer = unwrap!(res);
-            writer.write(&content).and_then(move |_| writer.close())
-        })
+                file_helper::read(c6, &file, dir.enc_key().cloned()).and_then(|reader| {
+                    let size = reader.size();
+                    reader.read(0, size)
+                })
+            })
+            .map(|data| {
+                assert_eq!(&data[..SMALL_SIZE], [1u8; SMALL_SIZE]);
+                assert_eq!(data[SMALL_SIZE..].to_owned(), vec![2u8; MAX_INLINE_SIZE]);
+            })
+    });
+}
+
+// Test that empty files and holes in sparse files aren't stored and read as
+// zeros.
+#[test]
+fn file_sparse() {
+    const DATA_SIZE: usize = 10;
+    // Large enough for the hole to be filled in several chunks.
+    const SPARSE_SIZE: usize = 2_500_000;
+
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+
+        file_helper::write(c2, File::new(Vec::new()), Mode::Overwrite, None)
+            .then(move |res| {
+                let writer = unwrap!(res);
+                writer.close()
+            })
             .then(move |res| {
-                // Attempt to read without an encryption key fails.
                 let file = unwrap!(res);
-                file_helper::read(c2, &file, None)
-                    .and_then(|_| Err(NfsError::from("Unexpected success")))
-                    .or_else(move |_error| -> Result<_, NfsError> {
-                        // TODO: assert the error is of the expected variant.
-                        Ok(file)
+                assert_eq!(file.size(), 0);
+                assert_eq!(file.data_size(), 0);
+                assert_eq!(file.digest(), Some(&sha3_256(&[])));
+
+                file_helper::write(c3, file, Mode::Modify, None)
+            })
+            .then(move |res| {
+                let writer = unwrap!(res);
+                writer
+                    .write(&[1u8; DATA_SIZE])
+                    .and_then(move |_| writer.truncate(SPARSE_SIZE as u64).map(move |_| writer))
+                    .and_then(move |writer| {
+                        assert_eq!(writer.size(), SPARSE_SIZE as u64);
+                        writer.close()
                     })
             })
             .then(move |res| {
-                // Attempt to read using incorrect encryption key fails.
                 let file = unwrap!(res);
-                file_helper::read(c3, &file, Some(wrong_key))
-                    .and_then(|_| Err(NfsError::from("Unexpected success")))
-                    .or_else(move |error| match error {
-                        NfsError::CoreError(CoreError::SymmetricDecipherFailure) => Ok(file),
-                        error => Err(error),
+                let mut expected = vec![0u8; SPARSE_SIZE];
+                expected[..DATA_SIZE].copy_from_slice(&[1u8; DATA_SIZE]);
+
+                assert_eq!(file.size(), SPARSE_SIZE as u64);
+                assert_eq!(file.data_size(), DATA_SIZE as u64);
+                assert_eq!(file.digest(), None);
+
+                file_helper::read(c4, &file, None)
+                    .and_then(move |reader| {
+                        reader.digest().and_then(move |digest| {
+                            assert_eq!(digest, sha3_256(&expected));
+                            reader.read(DATA_SIZE as u64 - 2, 4)
+                        })
                     })
+                    .map(move |data| (file, data))
             })
             .then(move |res| {
-                // Attempt to read using original encryption key succeeds.
-                let file = unwrap!(res);
-                file_helper::read(c4, &file, Some(key))
+                let (file, data) = unwrap!(res);
+                assert_eq!(data, vec![1, 1, 0, 0]);
+
+                // Appending fills the hole first.
+                file_helper::write(c5, file, Mode::Append, None)
             })
             .then(move |res| {
-                let reader = unwrap!(res);
-                let size = reader.size();
+                let writer = unwrap!(res);
+                writer.write(&[2u8; DATA_SIZE]).and_then(
+                    move |_| writer.close(),
+                )
+            })
+            .then(move |res| {
+                let file = unwrap!(res);
+                assert_eq!(file.size(), (SPARSE_SIZE + DATA_SIZE) as u64);
+                assert_eq!(file.data_size(), file.size());
+
+                file_helper::read(c6, &file, None).and_then(|reader| {
+                    let size = reader.size();
+                    reader.read(0, size)
+                })
+            })
+            .map(|data| {
+                assert_eq!(&data[..DATA_SIZE], [1u8; DATA_SIZE]);
+                assert!(data[DATA_SIZE..SPARSE_SIZE].iter().all(|byte| *byte == 0));
+                assert_eq!(&data[SPARSE_SIZE..], [2u8; DATA_SIZE]);
+            })
+    });
+}
+
+#[test]
+fn file_update_metadata() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, mut file) = unwrap!(res);
+
+                file.set_user_metadata(vec![12u8; 10]);
+                file_helper::update(c2, dir.clone(), "hello.txt", &file, 1)
+                    .map(move |()| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+
+                file_helper::fetch(c3.clone(), dir, "hello.txt")
+            })
+            .map(move |(_version, file)| {
+                assert_eq!(*file.user_metadata(), [12u8; 10][..]);
+            })
+    });
+}
+#[test]
+fn file_batch() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+
+                file_helper::Batch::new(dir.clone())
+                    .insert("a.txt", file.clone())
+                    .insert("b.txt", file.clone())
+                    .update("hello.txt", file, 0)
+                    .commit(&c2)
+                    .map(move |_| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::list(&c3, &dir).map(move |files| (dir, files))
+            })
+            .then(move |res| {
+                let (dir, files) = unwrap!(res);
+                let names: Vec<_> = files
+                    .iter()
+                    .map(|&(ref name, version, _)| (name.as_str(), version))
+                    .collect();
+                assert_eq!(names, vec![("a.txt", 0), ("b.txt", 0), ("hello.txt", 1)]);
+
+                // Deleting a missing file doesn't fail the whole batch
+                file_helper::Batch::new(dir.clone())
+                    .delete("a.txt", 0)
+                    .delete("missing.txt", 0)
+                    .commit(&c4)
+                    .map(move |_| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::list(&c5, &dir)
+            })
+            .map(|files| {
+                let names: Vec<_> = files.into_iter().map(|(name, _, _)| name).collect();
+                assert_eq!(names, vec!["b.txt", "hello.txt"]);
+            })
+    });
+}
+
+#[test]
+fn file_rename_and_move() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+        let other = unwrap!(MDataInfo::random_private(DIR_TAG));
+        let other2 = other.clone();
+
+        create_test_file(client)
+            .join(create_dir(client, &other, btree_map![], btree_map![]))
+            .then(move |res| {
+                let ((dir, _file), _) = unwrap!(res);
+                file_helper::rename(c2, dir.clone(), "hello.txt", "world.txt", 0)
+                    .map(move |version| (dir, version))
+            })
+            .then(move |res| {
+                let (dir, version) = unwrap!(res);
+                assert_eq!(version, 0);
+                file_helper::list(&c3, &dir).map(move |files| (dir, files))
+            })
+            .then(move |res| {
+                let (dir, files) = unwrap!(res);
+                assert_eq!(files.len(), 1);
+                assert_eq!(files[0].0, "world.txt");
+
+                // Simulate an interrupted move: the file is already present in
+                // the destination directory.
+                let file = files[0].2.clone();
+                file_helper::insert(c4, other2.clone(), "moved.txt", &file)
+                    .map(move |_| (dir, other2))
+            })
+            .then(move |res| {
+                let (dir, other) = unwrap!(res);
+                file_helper::move_to(c5, dir.clone(), "world.txt", other.clone(), "moved.txt")
+                    .map(move |version| (dir, other, version))
+            })
+            .then(move |res| {
+                let (dir, other, version) = unwrap!(res);
+                assert_eq!(version, 0);
+                file_helper::list(&c6, &dir)
+                    .join(file_helper::list(&c6, &other))
+                    .map(move |lists| (dir, lists))
+            })
+            .then(move |res| {
+                let (dir, (files, other_files)) = unwrap!(res);
+                assert!(files.is_empty());
+                assert_eq!(other_files.len(), 1);
+                assert_eq!(other_files[0].0, "moved.txt");
+
+                file_helper::rename(c7, dir, "world.txt", "again.txt", 0)
+            })
+            .then(|res| -> Result<_, NfsError> {
+                match res {
+                    Err(NfsError::FileNotFound) => Ok(()),
+                    Ok(_) => panic!("Unexpected success"),
+                    Err(err) => panic!("Unexpected error {:?}", err),
+                }
+            })
+    });
+}
+
+// Create soft links (including a cycle) and check they are resolved
+// correctly, that they can't share a name with a file, and that a file can be
+// copied through them.
+#[test]
+fn file_links() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+        let c8 = client.clone();
+        let other = unwrap!(MDataInfo::random_private(DIR_TAG));
+        let other2 = other.clone();
+
+        create_test_file(client)
+            .join(create_dir(client, &other, btree_map![], btree_map![]))
+            .then(move |res| {
+                let ((dir, file), _) = unwrap!(res);
+                let link = Link {
+                    dir: dir.clone(),
+                    name: "hello.txt".to_string(),
+                };
+                let loop_a = Link {
+                    dir: other2.clone(),
+                    name: "loop_b".to_string(),
+                };
+                let loop_b = Link {
+                    dir: other2.clone(),
+                    name: "loop_a".to_string(),
+                };
+
+                file_helper::symlink(c2.clone(), other2.clone(), "link", &link)
+                    .join3(
+                        file_helper::symlink(c2.clone(), other2.clone(), "loop_a", &loop_a),
+                        file_helper::symlink(c2, other2.clone(), "loop_b", &loop_b),
+                    )
+                    .map(move |_| (dir, other2, file))
+            })
+            .then(move |res| {
+                let (dir, other, file) = unwrap!(res);
+                file_helper::fetch_resolved(c3, other.clone(), "link")
+                    .map(move |(_, resolved)| (dir, other, file, resolved))
+            })
+            .then(move |res| {
+                let (dir, other, file, resolved) = unwrap!(res);
+                assert_eq!(resolved, file);
+
+                file_helper::symlink(c4, other.clone(), "link", &Link {
+                    dir: dir.clone(),
+                    name: "hello.txt".to_string(),
+                }).then(move |res| -> Result<_, NfsError> {
+                    match res {
+                        Err(NfsError::FileExists) => Ok((dir, other, file)),
+                        Ok(_) => panic!("Unexpected success"),
+                        Err(err) => panic!("Unexpected error {:?}", err),
+                    }
+                })
+            })
+            .then(move |res| {
+                let (dir, other, file) = unwrap!(res);
+                file_helper::fetch_resolved(c5, other.clone(), "loop_a")
+                    .then(move |res| -> Result<_, NfsError> {
+                        match res {
+                            Err(NfsError::LinkCycle) => Ok((dir, other, file)),
+                            Ok(_) => panic!("Unexpected success"),
+                            Err(err) => panic!("Unexpected error {:?}", err),
+                        }
+                    })
+            })
+            .then(move |res| {
+                let (dir, other, file) = unwrap!(res);
+                // Files and soft links can't share a name.
+                let link = Link {
+                    dir: other.clone(),
+                    name: "link".to_string(),
+                };
+                let insert = file_helper::insert(c6.clone(), other.clone(), "link", &file);
+                let symlink = file_helper::symlink(c6, dir.clone(), "hello.txt", &link);
+
+                insert
+                    .then(|res| -> Result<_, NfsError> {
+                        match res {
+                            Err(NfsError::FileExists) => Ok(()),
+                            x => panic!("Unexpected {:?}", x),
+                        }
+                    })
+                    .join(symlink.then(|res| -> Result<_, NfsError> {
+                        match res {
+                            Err(NfsError::FileExists) => Ok(()),
+                            x => panic!("Unexpected {:?}", x),
+                        }
+                    }))
+                    .map(move |_| (dir, other, file))
+            })
+            .then(move |res| {
+                let (dir, other, file) = unwrap!(res);
+                file_helper::copy(c7, other, "link", dir.clone(), "copy.txt")
+                    .map(move |_| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::fetch(c8, dir, "copy.txt").map(move |(_, copy)| (file, copy))
+            })
+            .map(|(file, copy)| {
+                assert_eq!(copy.data_map_name(), file.data_map_name());
+                assert_eq!(copy.size(), file.size());
+            })
+    });
+}
+
+#[test]
+fn file_delete() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, _file) = unwrap!(res);
+                file_helper::delete(&c2, &dir, "hello.txt", 1)
+                    .map(move |()| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::fetch(c3.clone(), dir, "hello.txt")
+            })
+            .then(move |res| -> Result<_, CoreError> {
+                match res {
+                    Ok(_) => {
+                        // We expect an error in this case
+                        panic!("Fetched non-existing file succesfully")
+                    }
+                    Err(_) => Ok(()),
+                }
+            })
+    });
+}
+
+// Test deleting an entry and then re-adding it.
+// We should be able to successfully open and read the re-added file.
+#[test]
+fn file_delete_then_add() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::delete(&c2, &dir, "hello.txt", 1).map(move |_| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+
+                file_helper::write(c3, file, Mode::Overwrite, dir.enc_key().cloned())
+                    .map(move |writer| (writer, dir))
+            })
+            .then(move |res| {
+                let (writer, dir) = unwrap!(res);
+
+                writer
+                    .write(&[1u8; NEW_SIZE])
+                    .and_then(move |_| writer.close())
+                    .map(move |file| (file, dir))
+            })
+            .then(move |res| {
+                let (file, dir) = unwrap!(res);
+                file_helper::update(c4, dir.clone(), "hello.txt", &file, 2).map(move |_| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::fetch(c5, dir.clone(), "hello.txt").map(
+                    move |(version, file)| (version, file, dir),
+                )
+            })
+            .then(move |res| {
+                let (version, file, dir) = unwrap!(res);
+                assert_eq!(version, 2);
+                file_helper::read(c6, &file, dir.enc_key().cloned())
+            })
+            .then(move |res| {
+                let reader = unwrap!(res);
+                let size = reader.size();
+                println!("reading {} bytes", size);
+                reader.read(0, size)
+            })
+            .map(move |data| {
+                assert_eq!(data, vec![1u8; NEW_SIZE]);
+            })
+    });
+}
+
+// Test closing files immediately after opening them in the different modes.
+#[test]
+fn file_open_close() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                // Open the file for reading
+                file_helper::read(c2, &file, dir.enc_key().cloned()).map(
+                    move |reader| (reader, file, dir),
+                )
+            })
+            .then(move |res| {
+                // The reader should get dropped implicitly
+                let (_reader, file, dir) = unwrap!(res);
+                // Open the file for writing
+                file_helper::write(c3, file.clone(), Mode::Overwrite, dir.enc_key().cloned())
+                    .map(move |writer| (writer, file, dir))
+            })
+            .then(move |res| {
+                let (writer, file, dir) = unwrap!(res);
+                // Close the file
+                let _ = writer.close();
+                // Open the file for appending
+                file_helper::write(c4, file.clone(), Mode::Append, dir.enc_key().cloned())
+                    .map(move |writer| (writer, file, dir))
+            })
+            .then(move |res| {
+                let (writer, file, dir) = unwrap!(res);
+                // Close the file
+                let _ = writer.close();
+                // Open the file for reading, ensure it has original contents
+                file_helper::read(c5, &file, dir.enc_key().cloned())
+            })
+            .then(move |res| {
+                let reader = unwrap!(res);
+                let size = reader.size();
                 reader.read(0, size)
             })
-            .then(move |res| -> Result<_, NfsError> {
-                let retrieved_content = unwrap!(res);
-                assert_eq!(retrieved_content, content2);
+            .map(move |data| {
+                assert_eq!(data, vec![0u8; ORIG_SIZE]);
+            })
+    });
+}
+
+// Create and store encrypted file and make sure it can only be read back with
+// the original encryption key.
+#[test]
+fn encryption() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+
+        let mut rng = rand::thread_rng();
+
+        let content: Vec<u8> = rng.gen_iter().take(ORIG_SIZE).collect();
+        let content2 = content.clone();
+
+        let key = shared_secretbox::gen_key();
+        let wrong_key = shared_secretbox::gen_key();
+
+        file_helper::write(
+            client.clone(),
+            File::new(Vec::new()),
+            Mode::Overwrite,
+            Some(key.clone()),
+        ).then(move |res| {
+            let writer = unwrap!(res);
+            writer.write(&content).and_then(move |_| writer.close())
+        })
+            .then(move |res| {
+                // Attempt to read without an encryption key fails.
+                let file = unwrap!(res);
+                file_helper::read(c2, &file, None)
+                    .and_then(|_| Err(NfsError::from("Unexpected success")))
+                    .or_else(move |_error| -> Result<_, NfsError> {
+                        // TODO: assert the error is of the expected variant.
+                        Ok(file)
+                    })
+            })
+            .then(move |res| {
+                // Attempt to read using incorrect encryption key fails.
+                let file = unwrap!(res);
+                file_helper::read(c3, &file, Some(wrong_key))
+                    .and_then(|_| Err(NfsError::from("Unexpected success")))
+                    .or_else(move |error| match error {
+                        NfsError::CoreError(CoreError::SymmetricDecipherFailure) => Ok(file),
+                        error => Err(error),
+                    })
+            })
+            .then(move |res| {
+                // Attempt to read using original encryption key succeeds.
+                let file = unwrap!(res);
+                file_helper::read(c4, &file, Some(key))
+            })
+            .then(move |res| {
+                let reader = unwrap!(res);
+                let size = reader.size();
+                reader.read(0, size)
+            })
+            .then(move |res| -> Result<_, NfsError> {
+                let retrieved_content = unwrap!(res);
+                assert_eq!(retrieved_content, content2);
+                Ok(())
+            })
+    })
+}
+
+// Test that the usage of a directory is tracked once enabled and that its
+// quota is enforced.
+#[test]
+fn dir_usage_and_quota() {
+    random_client(|client| {
+        client.set_usage_tracking(true);
+
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::dir_usage(&c2, &dir).map(move |usage| (dir, file, usage))
+            })
+            .then(move |res| {
+                let (dir, file, usage) = unwrap!(res);
+                assert!(usage.is_none());
+
+                let quota = Quota {
+                    max_files: Some(2),
+                    max_size: None,
+                };
+                file_helper::set_quota(c3.clone(), dir.clone(), quota)
+                    .and_then(move |_| file_helper::dir_usage(&c3, &dir).map(move |u| (dir, u)))
+                    .map(move |(dir, usage)| (dir, file, usage))
+            })
+            .then(move |res| {
+                let (dir, file, usage) = unwrap!(res);
+                let usage = unwrap!(usage);
+                assert_eq!(usage.files, 1);
+                assert_eq!(usage.size, ORIG_SIZE as u64);
+
+                file_helper::insert(c4, dir.clone(), "copy.txt", &file).map(move |_| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::insert(c5, dir.clone(), "another.txt", &file)
+                    .then(move |res| -> Result<_, NfsError> {
+                        match res {
+                            Err(NfsError::QuotaExceeded) => Ok(dir),
+                            Ok(_) => panic!("Unexpected success"),
+                            Err(err) => panic!("Unexpected error {:?}", err),
+                        }
+                    })
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::delete(&c6, &dir, "copy.txt", 1).map(move |_| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::dir_usage(&c7, &dir)
+            })
+            .map(|usage| {
+                let usage = unwrap!(usage);
+                assert_eq!(usage.files, 1);
+                assert_eq!(usage.size, ORIG_SIZE as u64);
+            })
+    });
+}
+
+// Test that concurrent insertions don't overwrite each other's usage updates
+// and that writers refuse data exceeding the size quota before uploading it.
+#[test]
+fn dir_usage_concurrent() {
+    random_client(|client| {
+        client.set_usage_tracking(true);
+
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                let quota = Quota {
+                    max_files: None,
+                    max_size: Some(3 * ORIG_SIZE as u64),
+                };
+                file_helper::set_quota(c2, dir.clone(), quota).map(move |_| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                let fut1 = file_helper::insert(c3.clone(), dir.clone(), "a.txt", &file);
+                let fut2 = file_helper::insert(c3.clone(), dir.clone(), "b.txt", &file);
+
+                fut1.join(fut2).and_then(move |_| {
+                    file_helper::dir_usage(&c3, &dir).map(move |usage| (dir, usage))
+                })
+            })
+            .then(move |res| {
+                let (dir, usage) = unwrap!(res);
+                let usage = unwrap!(usage);
+                assert_eq!(usage.files, 3);
+                assert_eq!(usage.size, 3 * ORIG_SIZE as u64);
+
+                file_helper::write_in(c4, &dir, File::new(Vec::new()), Mode::Overwrite)
+            })
+            .then(|res| {
+                let writer = unwrap!(res);
+                writer.write(&[0u8; 1])
+            })
+            .then(|res| -> Result<_, NfsError> {
+                match res {
+                    Err(NfsError::QuotaExceeded) => Ok(()),
+                    Ok(_) => panic!("Unexpected success"),
+                    Err(err) => panic!("Unexpected error {:?}", err),
+                }
+            })
+    });
+}
+
+// Test that a client which doesn't enable usage tracking neither enforces the
+// quota nor updates the usage record.
+#[test]
+fn dir_usage_not_tracked() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                let quota = Quota {
+                    max_files: Some(1),
+                    max_size: None,
+                };
+                file_helper::set_quota(c2, dir.clone(), quota).map(move |_| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::insert(c3, dir.clone(), "a.txt", &file).map(move |_| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::dir_usage(&c4, &dir)
+            })
+            .map(|usage| assert_eq!(unwrap!(usage).files, 1))
+    });
+}
+
+// Soft-delete a file, restore it from the trash and empty the trash.
+#[test]
+fn file_trash() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+        let c8 = client.clone();
+        let c9 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, _) = unwrap!(res);
+                let dir2 = dir.clone();
+                file_helper::soft_delete(c2.clone(), dir.clone(), "hello.txt", 0)
+                    .and_then(move |_| file_helper::fetch(c2, dir2, "hello.txt"))
+                    .then(move |res| -> Result<_, NfsError> {
+                        match res {
+                            Err(NfsError::FileNotFound) => Ok(dir),
+                            Ok(_) => panic!("Unexpected success"),
+                            Err(err) => panic!("Unexpected error {:?}", err),
+                        }
+                    })
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::list_trash(&c3, &dir).map(move |trash| (dir, trash))
+            })
+            .then(move |res| {
+                let (dir, trash) = unwrap!(res);
+                assert_eq!(trash.len(), 1);
+                assert_eq!(trash[0].0, "hello.txt");
+                assert_eq!(trash[0].1.file.size(), ORIG_SIZE as u64);
+
+                // A new file with the same name blocks the restore.
+                file_helper::insert(c4.clone(), dir.clone(), "hello.txt", &File::new(vec![]))
+                    .and_then(move |_| {
+                        file_helper::restore_from_trash(c4, dir.clone(), "hello.txt")
+                            .then(move |res| -> Result<_, NfsError> {
+                                match res {
+                                    Err(NfsError::FileExists) => Ok(dir),
+                                    Ok(_) => panic!("Unexpected success"),
+                                    Err(err) => panic!("Unexpected error {:?}", err),
+                                }
+                            })
+                    })
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::delete(&c5, &dir, "hello.txt", 3)
+                    .and_then(move |_| {
+                        file_helper::restore_from_trash(c5.clone(), dir.clone(), "hello.txt")
+                            .map(move |_| dir)
+                    })
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::fetch(c6.clone(), dir.clone(), "hello.txt")
+                    .join(file_helper::list_trash(&c6, &dir))
+                    .map(move |(file, trash)| (dir, file, trash))
+            })
+            .then(move |res| {
+                let (dir, (version, file), trash) = unwrap!(res);
+                assert_eq!(version, 4);
+                assert_eq!(file.size(), ORIG_SIZE as u64);
+                assert!(trash.is_empty());
+
+                file_helper::soft_delete(c7.clone(), dir.clone(), "hello.txt", 0)
+                    .and_then(move |_| file_helper::empty_trash(c7, dir.clone()).map(move |_| dir))
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::list_trash(&c8, &dir).map(move |trash| (dir, trash))
+            })
+            .then(move |res| {
+                let (dir, trash) = unwrap!(res);
+                assert!(trash.is_empty());
+                file_helper::restore_from_trash(c9, dir, "hello.txt")
+            })
+            .then(|res| -> Result<_, NfsError> {
+                match res {
+                    Err(NfsError::FileNotFound) => Ok(()),
+                    Ok(_) => panic!("Unexpected success"),
+                    Err(err) => panic!("Unexpected error {:?}", err),
+                }
+            })
+    });
+}
+
+// Update a file which has been concurrently modified, failing on the conflict
+// and then saving conflict copies.
+#[test]
+fn file_update_conflict() {
+    assert_eq!(conflict::copy_name("hello.txt", 1), "hello (conflict 1).txt");
+    assert_eq!(conflict::copy_name(".hidden", 2), ".hidden (conflict 2)");
+    assert_eq!(conflict::copy_name("hello", 3), "hello (conflict 3)");
+
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+        let c8 = client.clone();
+        let c9 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (dir, mut file) = unwrap!(res);
+
+                // Another device updates the file.
+                let mut theirs = file.clone();
+                theirs.set_user_metadata(vec![1u8; 10]);
+                file.set_user_metadata(vec![2u8; 10]);
+
+                file_helper::update(c2, dir.clone(), "hello.txt", &theirs, 1)
+                    .map(move |_| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::update_checked(
+                    c3,
+                    dir.clone(),
+                    "hello.txt",
+                    &file,
+                    0,
+                    ConflictPolicy::Fail,
+                ).then(move |res| -> Result<_, NfsError> {
+                    match res {
+                        Err(NfsError::Conflict(ours, theirs)) => {
+                            assert_eq!(*ours.user_metadata(), [2u8; 10][..]);
+                            assert_eq!(*theirs.user_metadata(), [1u8; 10][..]);
+                            Ok((dir, file))
+                        }
+                        Ok(_) => panic!("Unexpected success"),
+                        Err(err) => panic!("Unexpected error {:?}", err),
+                    }
+                })
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                let dir2 = dir.clone();
+                let file2 = file.clone();
+
+                file_helper::update_checked(
+                    c4.clone(),
+                    dir,
+                    "hello.txt",
+                    &file,
+                    0,
+                    ConflictPolicy::KeepCopy,
+                ).and_then(move |copy1| {
+                    file_helper::update_checked(
+                        c4,
+                        dir2.clone(),
+                        "hello.txt",
+                        &file2,
+                        0,
+                        ConflictPolicy::KeepCopy,
+                    ).map(move |copy2| (dir2, (copy1, copy2)))
+                })
+            })
+            .then(move |res| {
+                let (dir, (copy1, copy2)) = unwrap!(res);
+                assert_eq!(copy1, Some("hello (conflict 1).txt".to_string()));
+                assert_eq!(copy2, Some("hello (conflict 2).txt".to_string()));
+
+                file_helper::fetch(c5.clone(), dir.clone(), "hello (conflict 1).txt")
+                    .join(file_helper::fetch(c5, dir.clone(), "hello.txt"))
+                    .map(move |res| (dir, res))
+            })
+            .then(move |res| {
+                let (dir, ((_, copy), (version, current))) = unwrap!(res);
+                assert_eq!(*copy.user_metadata(), [2u8; 10][..]);
+                assert_eq!(*current.user_metadata(), [1u8; 10][..]);
+                assert_eq!(version, 1);
+
+                // Updating from the current version succeeds.
+                file_helper::update_checked(
+                    c6,
+                    dir.clone(),
+                    "hello.txt",
+                    &copy,
+                    version,
+                    ConflictPolicy::Fail,
+                ).map(move |res| (dir, copy, res))
+            })
+            .then(move |res| {
+                let (dir, copy, res) = unwrap!(res);
+                assert_eq!(res, None);
+
+                // Retrying the same update finds its own write rather than a
+                // conflict.
+                file_helper::update_checked(
+                    c7,
+                    dir.clone(),
+                    "hello.txt",
+                    &copy,
+                    1,
+                    ConflictPolicy::Fail,
+                ).map(move |res| (dir, res))
+            })
+            .then(move |res| {
+                let (dir, res) = unwrap!(res);
+                assert_eq!(res, None);
+                file_helper::fetch(c8, dir.clone(), "hello.txt")
+                    .join(file_helper::list(&c9, &dir))
+            })
+            .map(|((version, file), files)| {
+                assert_eq!(version, 2);
+                assert_eq!(*file.user_metadata(), [2u8; 10][..]);
+                assert_eq!(files.len(), 3);
+            })
+    });
+}
+
+// Test creating nested directories and looking them up.
+// 1. Create a nested path in one go, with missing parents.
+// 2. Creating an existing directory without `parents` fails.
+// 3. Stat and list the directories, check that both files and subdirectories
+//    are reported.
+// 4. Rename a subdirectory, check it's reachable only under the new path.
+// 5. Moving a directory into itself or onto an existing file fails, renaming
+//    it to itself does nothing.
+#[test]
+fn dir_create_and_list() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+        let c9 = client.clone();
+        let c10 = client.clone();
+        let c11 = client.clone();
+        let c12 = client.clone();
+
+        create_test_file(client)
+            .then(move |res| {
+                let (root, _file) = unwrap!(res);
+                dir_helper::mkdir(c2, root.clone(), "a/b/c", true).map(move |_| root)
+            })
+            .then(move |res| {
+                let root = unwrap!(res);
+                dir_helper::mkdir(c3, root.clone(), "/a/b", false).then(move |res| match res {
+                    Err(NfsError::DirectoryExists) => Ok(root),
+                    Ok(_) => panic!("Created an existing directory"),
+                    Err(err) => Err(err),
+                })
+            })
+            .then(move |res| {
+                let root = unwrap!(res);
+                dir_helper::stat(c4, root.clone(), "a/b/c").map(move |node| (root, node))
+            })
+            .then(move |res| {
+                let (root, node) = unwrap!(res);
+                match node {
+                    Node::Dir { .. } => (),
+                    node => panic!("Unexpected {:?}", node),
+                }
+                dir_helper::list(c5, root.clone(), "").map(move |nodes| (root, nodes))
+            })
+            .then(move |res| {
+                let (root, nodes) = unwrap!(res);
+                assert_eq!(nodes.len(), 2);
+                match nodes.get("hello.txt") {
+                    Some(&Node::File { ref file, .. }) => assert_eq!(file.size(), ORIG_SIZE as u64),
+                    x => panic!("Unexpected {:?}", x),
+                }
+                match nodes.get("a") {
+                    Some(&Node::Dir { .. }) => (),
+                    x => panic!("Unexpected {:?}", x),
+                }
+                dir_helper::rename(c6, root.clone(), "a/b", "a/d").map(move |_| root)
+            })
+            .then(move |res| {
+                let root = unwrap!(res);
+                let c8 = c7.clone();
+                dir_helper::open_dir(c7, root.clone(), "a/b").then(move |res| {
+                    match res {
+                        Err(NfsError::DirectoryNotFound) => (),
+                        x => panic!("Unexpected {:?}", x),
+                    }
+                    dir_helper::list(c8, root.clone(), "a/d").map(move |nodes| (root, nodes))
+                })
+            })
+            .then(move |res| {
+                let (root, nodes) = unwrap!(res);
+                assert_eq!(nodes.len(), 1);
+                assert!(nodes.contains_key("c"));
+
+                // Moving a directory into itself is rejected.
+                dir_helper::rename(c9, root.clone(), "a", "/a/d/e").then(move |res| {
+                    match res {
+                        Err(NfsError::InvalidPath) => (),
+                        x => panic!("Unexpected {:?}", x),
+                    }
+                    // Renaming a directory to itself does nothing.
+                    dir_helper::rename(c10, root.clone(), "a/d", "a/d").map(move |_| root)
+                })
+            })
+            .then(move |res| {
+                let root = unwrap!(res);
+                // A directory can't take the name of an existing file.
+                dir_helper::rename(c11, root.clone(), "a/d", "hello.txt").then(move |res| {
+                    match res {
+                        Err(NfsError::FileExists) => (),
+                        x => panic!("Unexpected {:?}", x),
+                    }
+                    dir_helper::list(c12, root, "a/d")
+                })
+            })
+            .then(|res| -> Result<_, NfsError> {
+                let nodes = unwrap!(res);
+                assert!(nodes.contains_key("c"));
                 Ok(())
             })
-    })
+    });
+}
+
+// Test removing directories.
+// 1. Removing a non-empty directory fails unless `recursive` is set.
+// 2. Recursively removed directory and its subdirectories can't be opened
+//    anymore, and the name can be reused by a new directory.
+#[test]
+fn dir_remove() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let root = unwrap!(MDataInfo::random_private(DIR_TAG));
+        let root2 = root.clone();
+
+        create_dir(client, &root, btree_map![], btree_map![])
+            .then(move |res| {
+                unwrap!(res);
+                dir_helper::mkdir(c2, root2.clone(), "x/y", true).map(move |_| root2)
+            })
+            .then(move |res| {
+                let root = unwrap!(res);
+                dir_helper::rmdir(c3, root.clone(), "x", false).then(move |res| match res {
+                    Err(NfsError::DirectoryNotEmpty) => Ok(root),
+                    Ok(()) => panic!("Removed a non-empty directory"),
+                    Err(err) => Err(err),
+                })
+            })
+            .then(move |res| {
+                let root = unwrap!(res);
+                dir_helper::rmdir(c4, root.clone(), "x", true).map(move |_| root)
+            })
+            .then(move |res| {
+                let root = unwrap!(res);
+                dir_helper::open_dir(c5, root.clone(), "x/y").then(move |res| match res {
+                    Err(NfsError::DirectoryNotFound) => Ok(root),
+                    Ok(_) => panic!("Opened a removed directory"),
+                    Err(err) => Err(err),
+                })
+            })
+            .then(move |res| {
+                let root = unwrap!(res);
+                dir_helper::mkdir(c6, root, "x", false)
+            })
+            .map(|_| ())
+    });
+}
+
+// Test that a directory which gets full is sharded transparently: files
+// inserted past the limit can be fetched, updated, restored from their
+// history, moved to the trash and back, listed and deleted as any other, and
+// subdirectories can still be created.
+#[test]
+fn dir_sharding() {
+    const NUM_FILES: usize = MAX_MUTABLE_DATA_ENTRIES as usize + 10;
+
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+        let c8 = client.clone();
+        let c9 = client.clone();
+        let c10 = client.clone();
+        let c11 = client.clone();
+        let c12 = client.clone();
+        let dir = unwrap!(MDataInfo::random_private(DIR_TAG));
+        let dir2 = dir.clone();
+        let name = format!("file{}.txt", NUM_FILES - 1);
+        let name2 = name.clone();
+        let name3 = name.clone();
+        let name4 = name.clone();
+        let name5 = name.clone();
+        let name6 = name.clone();
+
+        create_dir(client, &dir, unwrap!(shard::reserved_entries(&dir)), btree_map![])
+            .then(move |res| {
+                unwrap!(res);
+                let dir3 = dir2.clone();
+                future::loop_fn(0, move |index| {
+                    let name = format!("file{}.txt", index);
+                    file_helper::insert(c2.clone(), dir3.clone(), name, &File::new(Vec::new()))
+                        .map(move |_| if index + 1 < NUM_FILES {
+                            Loop::Continue(index + 1)
+                        } else {
+                            Loop::Break(())
+                        })
+                }).map(move |_| dir2)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                shard::fetch_shards(&c3, &dir).map(move |shards| (dir, shards))
+            })
+            .then(move |res| {
+                let (dir, shards) = unwrap!(res);
+                assert_eq!(unwrap!(shards).len(), SHARD_COUNT);
+
+                file_helper::update_versioned(c4, dir.clone(), name, &File::new(vec![1]), 0, 5)
+                    .map(move |_| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::fetch(c5, dir.clone(), name2).map(move |(version, file)| {
+                    assert_eq!(version, 1);
+                    assert_eq!(file.user_metadata(), &[1u8][..]);
+                    dir
+                })
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                let dir2 = dir.clone();
+                file_helper::restore(c6.clone(), dir.clone(), name3.clone(), 0, 5)
+                    .and_then(move |_| file_helper::fetch(c6, dir2, name3))
+                    .map(move |(version, file)| {
+                        assert_eq!(version, 2);
+                        assert!(file.user_metadata().is_empty());
+                        dir
+                    })
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                let dir2 = dir.clone();
+                file_helper::soft_delete(c7.clone(), dir.clone(), name4.clone(), 0)
+                    .and_then(move |_| file_helper::list_trash(&c7, &dir2))
+                    .map(move |trash| {
+                        assert_eq!(trash.len(), 1);
+                        assert_eq!(trash[0].0, name4);
+                        dir
+                    })
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                let dir2 = dir.clone();
+                file_helper::restore_from_trash(c8.clone(), dir.clone(), name5.clone())
+                    .and_then(move |_| file_helper::fetch(c8, dir2, name5))
+                    .map(move |(version, _)| {
+                        assert_eq!(version, 4);
+                        dir
+                    })
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                let dir2 = dir.clone();
+                file_helper::soft_delete(c9.clone(), dir.clone(), name6, 0)
+                    .and_then(move |_| file_helper::empty_trash(c9, dir2))
+                    .map(move |_| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::list_trash(&c10, &dir).map(move |trash| (dir, trash))
+            })
+            .then(move |res| {
+                let (dir, trash) = unwrap!(res);
+                assert!(trash.is_empty());
+                file_helper::list(&c11, &dir).map(move |files| (dir, files))
+            })
+            .then(move |res| {
+                let (dir, files) = unwrap!(res);
+                assert_eq!(files.len(), NUM_FILES - 1);
+
+                dir_helper::mkdir(c12.clone(), dir.clone(), "subdir", false)
+                    .and_then(move |_| dir_helper::open_dir(c12, dir, "subdir"))
+            })
+            .map(|_| ())
+    });
+}
+
+// Test that inserting into a directory which isn't full costs no more
+// requests than updating an existing file plus looking up a soft link with the
+// same name, which inserting a file has to check for.
+#[test]
+fn dir_sharding_no_extra_requests() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let dir = unwrap!(MDataInfo::random_private(DIR_TAG));
+        let dir2 = dir.clone();
+        let dir3 = dir.clone();
+
+        create_dir(client, &dir, unwrap!(shard::reserved_entries(&dir)), btree_map![])
+            .then(move |res| {
+                unwrap!(res);
+                let sent = c2.request_stats().sent;
+                let c5 = c2.clone();
+
+                file_helper::insert(c2, dir2, "test.txt", &File::new(Vec::new()))
+                    .map(move |_| c5.request_stats().sent - sent)
+            })
+            .then(move |res| {
+                let insert_requests = unwrap!(res);
+                let sent = c3.request_stats().sent;
+                let c5 = c3.clone();
+
+                file_helper::update(c3, dir3, "test.txt", &File::new(vec![1]), 0)
+                    .map(move |_| (insert_requests, c5.request_stats().sent - sent))
+            })
+            .then(move |res| {
+                let (insert_requests, update_requests) = unwrap!(res);
+                let sent = c4.request_stats().sent;
+                let c5 = c4.clone();
+
+                file_helper::read_link(&c4, &dir, "test.txt")
+                    .then(move |res| -> Result<_, NfsError> {
+                        match res {
+                            Err(NfsError::FileNotFound) => (),
+                            x => panic!("Unexpected {:?}", x),
+                        }
+                        let lookup_requests = c5.request_stats().sent - sent;
+                        assert_eq!(insert_requests, update_requests + lookup_requests);
+                        Ok(())
+                    })
+            })
+    });
+}
+
+// Publish a tree of unencrypted files with an index and read it back using an
+// unregistered client.
+#[test]
+fn public_tree() {
+    let root = random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+
+        public::create_public_dir(client, DIR_TAG)
+            .then(move |res| {
+                let root = unwrap!(res);
+                public::write_file(c2, root.clone(), "index.html", b"<h1>Home</h1>")
+                    .map(move |_| root)
+            })
+            .then(move |res| {
+                let root = unwrap!(res);
+                public::write_file(c3, root.clone(), "/css/site.css", b"body {}")
+                    .map(move |_| root)
+            })
+            .then(move |res| {
+                let root = unwrap!(res);
+                public::write_file(c4, root.clone(), "docs/v1/notes.txt", b"notes")
+                    .map(move |_| root)
+            })
+            .then(move |res| {
+                let root = unwrap!(res);
+                // Overwrite an already published file.
+                public::write_file(c5, root.clone(), "docs/v1/notes.txt", b"new notes")
+                    .map(move |_| root)
+            })
+            .then(move |res| {
+                let root = unwrap!(res);
+                public::update_index(c6, root.clone()).map(move |index| (root, index))
+            })
+            .map(|(root, index)| {
+                let paths: Vec<_> = index.files.keys().map(|path| path.as_str()).collect();
+                assert_eq!(paths, vec!["css/site.css", "docs/v1/notes.txt", "index.html"]);
+                assert_eq!(index.files["docs/v1/notes.txt"].size, 9);
+                assert!(root.enc_info.is_none());
+                root
+            })
+    });
+
+    setup_client(
+        |el_h, core_tx, net_tx| Client::unregistered(el_h, core_tx, net_tx, None),
+        move |client| {
+            let c2 = client.clone();
+            let c3 = client.clone();
+            let c4 = client.clone();
+            let c5 = client.clone();
+            let name = root.name;
+            let root2 = root.clone();
+
+            public::resolve(client.clone(), name, DIR_TAG, "/")
+                .then(move |res| {
+                    assert_eq!(unwrap!(res), b"<h1>Home</h1>".to_vec());
+                    public::resolve(c2, name, DIR_TAG, "docs/v1/notes.txt")
+                })
+                .then(move |res| {
+                    assert_eq!(unwrap!(res), b"new notes".to_vec());
+                    public::resolve(c3, name, DIR_TAG, "docs/missing.txt")
+                })
+                .then(move |res| {
+                    match res {
+                        Err(NfsError::FileNotFound) => (),
+                        Err(x) => panic!("Unexpected: {:?}", x),
+                        Ok(_) => panic!("Unexpected success"),
+                    }
+                    // `docs` has no default document.
+                    public::resolve(c4, name, DIR_TAG, "docs")
+                })
+                .then(move |res| {
+                    assert!(res.is_err());
+                    public::fetch_index(c5, &root2)
+                })
+                .map(|index| {
+                    let index = unwrap!(index);
+                    assert_eq!(index.files.len(), 3);
+                    assert_eq!(index.files["css/site.css"].size, 7);
+                })
+        },
+    );
 }
@@ -0,0 +1,128 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use chrono::{DateTime, Utc};
+use client::{Client, MDataInfo};
+use futures::Future;
+use maidsafe_utilities::serialisation::SerialisationError;
+use nfs::{File, NfsError, NfsFuture, dir, file, shard};
+use nfs::file::LegacyFile;
+use routing::Value;
+use std::str;
+use utils::FutureExt;
+
+/// File moved to the trash of its directory by `file_helper::soft_delete`.
+/// The trash is kept in entries of the directory which don't count towards
+/// its usage. In a sharded directory, they are spread over the shards like
+/// the files.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TrashedFile {
+    /// The deleted file.
+    pub file: File,
+    /// Time of the deletion.
+    pub deleted: DateTime<Utc>,
+}
+
+// Serialised form of `TrashedFile` holding a file from before the structured
+// metadata was added.
+#[derive(Deserialize)]
+struct LegacyTrashedFile {
+    file: LegacyFile,
+    deleted: DateTime<Utc>,
+}
+
+/// Fetch the soft-deleted versions of the file `name`, oldest first, together
+/// with the directory or shard holding them and the version of their entry,
+/// which is `None` if the entry doesn't exist yet.
+pub fn fetch<T: 'static>(
+    client: &Client<T>,
+    dir: &MDataInfo,
+    name: &str,
+) -> Box<NfsFuture<(MDataInfo, Option<u64>, Vec<TrashedFile>)>> {
+    shard::locate(client, dir, &dir::trash_key(name))
+        .and_then(|(target, value)| {
+            let (version, trash) = decode(value)?;
+            Ok((target, version, trash))
+        })
+        .into_box()
+}
+
+/// Like `fetch`, but passes the soft-deleted versions of the file to `mutate`,
+/// which is expected to update their entry. New entries are inserted into the
+/// shards of a full directory, as with `shard::insert`.
+pub fn update<T, F, R>(
+    client: &Client<T>,
+    dir: &MDataInfo,
+    name: &str,
+    mutate: F,
+) -> Box<NfsFuture<R>>
+where
+    T: 'static,
+    F: Fn(MDataInfo, Option<u64>, Vec<TrashedFile>) -> Box<NfsFuture<R>> + 'static,
+    R: 'static,
+{
+    shard::insert(client, dir, &dir::trash_key(name), move |target, value| {
+        let (version, trash) = fry!(decode(value));
+        mutate(target, version, trash)
+    })
+}
+
+/// Fetch the whole trash of the directory, following its shards. Each file
+/// name is paired with the version of its trash entry and its soft-deleted
+/// versions, oldest first.
+pub fn list<T: 'static>(
+    client: &Client<T>,
+    dir: &MDataInfo,
+) -> Box<NfsFuture<Vec<(String, u64, Vec<TrashedFile>)>>> {
+    shard::list_entries(client, dir)
+        .and_then(|entries| {
+            let mut trash = Vec::new();
+            for (key, value) in entries {
+                let name = match dir::trash_name(&key) {
+                    Some(name) => name,
+                    None => continue,
+                };
+                let name = str::from_utf8(name)
+                    .map_err(|_| NfsError::from("Invalid file name"))?
+                    .to_string();
+                trash.push((name, value.entry_version, deserialise(&value.content)?));
+            }
+            Ok(trash)
+        })
+        .into_box()
+}
+
+// Decode the trash entry into its version and the soft-deleted files.
+fn decode(value: Option<Value>) -> Result<(Option<u64>, Vec<TrashedFile>), NfsError> {
+    match value {
+        Some(ref value) if value.content.is_empty() => Ok((Some(value.entry_version), vec![])),
+        Some(value) => Ok((Some(value.entry_version), deserialise(&value.content)?)),
+        None => Ok((None, vec![])),
+    }
+}
+
+fn deserialise(data: &[u8]) -> Result<Vec<TrashedFile>, SerialisationError> {
+    file::deserialise_compat(data, |trash: Vec<LegacyTrashedFile>| {
+        trash
+            .into_iter()
+            .map(|trashed| TrashedFile {
+                file: File::from(trashed.file),
+                deleted: trashed.deleted,
+            })
+            .collect()
+    })
+}
@@ -0,0 +1,277 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use client::{Client, MDataInfo};
+use errors::CoreError;
+use ffi::nfs::Usage as FfiUsage;
+use futures::{Future, future};
+use futures::future::Loop;
+use maidsafe_utilities::serialisation::{deserialise, serialise};
+use nfs::{File, NfsError, NfsFuture, dir, shard};
+use routing::{ClientError, EntryAction, Value};
+use utils::FutureExt;
+
+/// Maximum number of attempts to update the usage record when it keeps being
+/// updated concurrently.
+pub const MAX_ATTEMPTS: usize = 10;
+
+/// Usage of a directory: the number and total size of the files in it.
+/// Directories don't track their usage unless it is enabled with
+/// `file_helper::set_quota`. Once enabled, the usage record is updated in the
+/// same mutation as the files themselves, by the clients which enable usage
+/// tracking with `Client::set_usage_tracking`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Number of files in the directory.
+    pub files: u64,
+    /// Total size of the files in the directory, in bytes.
+    pub size: u64,
+    /// Soft quota of the directory.
+    pub quota: Quota,
+}
+
+/// Soft limits on the usage of a directory. Mutations which would increase
+/// the usage past any of them fail with `NfsError::QuotaExceeded` before the
+/// files are mutated, which takes fetching the usage record and the changed
+/// files first. Mutations which don't increase the usage are always allowed,
+/// so a directory over its quota can be cleaned up. Quotas are only enforced
+/// by clients which enable usage tracking.
+/// Writers opened with `file_helper::write_in` enforce the size limit already
+/// while the data is written.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quota {
+    /// Maximum number of files. `None` means unlimited.
+    pub max_files: Option<u64>,
+    /// Maximum total size of the files, in bytes. `None` means unlimited.
+    pub max_size: Option<u64>,
+}
+
+impl Usage {
+    /// Returns `true` if the usage exceeds any limit of the quota.
+    pub fn exceeds_quota(&self) -> bool {
+        self.quota.max_files.map_or(false, |max| self.files > max) ||
+            self.quota.max_size.map_or(false, |max| self.size > max)
+    }
+
+    /// Construct FFI wrapper for the native rust `Usage`.
+    pub fn into_repr_c(self) -> FfiUsage {
+        FfiUsage {
+            files: self.files,
+            size: self.size,
+            has_max_files: self.quota.max_files.is_some(),
+            max_files: self.quota.max_files.unwrap_or(0),
+            has_max_size: self.quota.max_size.is_some(),
+            max_size: self.quota.max_size.unwrap_or(0),
+        }
+    }
+
+    fn add(&mut self, file: &File) {
+        self.files += 1;
+        self.size += file.size();
+    }
+
+    fn apply(&self, delta: Delta) -> Usage {
+        Usage {
+            files: add_signed(self.files, delta.files),
+            size: add_signed(self.size, delta.size),
+            quota: self.quota,
+        }
+    }
+}
+
+/// Change of the usage of a directory caused by changes of its files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Delta {
+    files: i64,
+    size: i64,
+}
+
+impl Delta {
+    fn add(&mut self, file: &File) {
+        self.files += 1;
+        self.size += file.size() as i64;
+    }
+
+    fn remove(&mut self, file: &File) {
+        self.files -= 1;
+        self.size -= file.size() as i64;
+    }
+
+    fn grows(&self) -> bool {
+        self.files > 0 || self.size > 0
+    }
+}
+
+fn add_signed(value: u64, delta: i64) -> u64 {
+    if delta < 0 {
+        value.saturating_sub(-delta as u64)
+    } else {
+        value.saturating_add(delta as u64)
+    }
+}
+
+/// Fetch the usage record of the directory together with the version of its
+/// entry. Returns `None` if the directory doesn't track its usage.
+pub fn fetch<T: 'static>(
+    client: &Client<T>,
+    dir: &MDataInfo,
+) -> Box<NfsFuture<Option<(u64, Usage)>>> {
+    dir::fetch_entry(client, dir, dir::USAGE_KEY)
+        .and_then(|value| match value {
+            Some(ref value) if !value.content.is_empty() => {
+                Ok(Some((value.entry_version, deserialise(&value.content)?)))
+            }
+            _ => Ok(None),
+        })
+        .into_box()
+}
+
+/// Compute the usage of the directory from its current files.
+pub fn compute(files: &[(String, u64, File)], quota: Quota) -> Usage {
+    let mut usage = Usage {
+        files: 0,
+        size: 0,
+        quota,
+    };
+    for &(_, _, ref file) in files {
+        usage.add(file);
+    }
+    usage
+}
+
+/// Prepare an action updating the usage record of the directory to account
+/// for the given changes of its files. Each change pairs the name of a file
+/// with its new version, or `None` if the file is being deleted.
+/// Fails with `QuotaExceeded` if the changes would increase the usage past the
+/// quota. Returns `None` if the directory doesn't track its usage, or if the
+/// client doesn't enable usage tracking, in which case nothing is fetched.
+/// The change of the usage is returned together with the action, so that it
+/// can be applied to the fresh record with `apply` if the action is rejected
+/// because of a concurrent update of the record.
+pub fn update_action<T: 'static>(
+    client: &Client<T>,
+    dir: &MDataInfo,
+    changes: Vec<(String, Option<File>)>,
+) -> Box<NfsFuture<Option<(Delta, Vec<u8>, EntryAction)>>> {
+    if !client.usage_tracking() {
+        return ok!(None);
+    }
+
+    let client = client.clone();
+    let dir = dir.clone();
+
+    fetch(&client, &dir)
+        .and_then(move |usage| {
+            let (version, usage) = match usage {
+                Some(usage) => usage,
+                None => return ok!(None),
+            };
+
+            let current_futs = changes
+                .iter()
+                .map(|&(ref name, _)| shard::fetch_entry(&client, &dir, name.as_bytes()))
+                .collect::<Vec<_>>();
+
+            future::join_all(current_futs)
+                .and_then(move |current| {
+                    let mut delta = Delta::default();
+
+                    for (value, (_, file)) in current.into_iter().zip(changes) {
+                        match value {
+                            Some(ref value) if !value.content.is_empty() => {
+                                delta.remove(&File::deserialise(&value.content)?);
+                            }
+                            _ => (),
+                        }
+                        if let Some(file) = file {
+                            delta.add(&file);
+                        }
+                    }
+
+                    if delta.grows() && usage.apply(delta).exceeds_quota() {
+                        return Err(NfsError::QuotaExceeded);
+                    }
+
+                    let (key, action) = action(&dir, version, &usage, delta)?;
+                    Ok(Some((delta, key, action)))
+                })
+                .into_box()
+        })
+        .into_box()
+}
+
+/// Apply the change of the usage to the current usage record of the
+/// directory, in a mutation of its own. If the record is updated concurrently,
+/// the change is applied again to the fresh record instead of overwriting it.
+/// The quota is not checked, as the files are expected to be already changed.
+pub fn apply<T: 'static>(client: &Client<T>, dir: &MDataInfo, delta: Delta) -> Box<NfsFuture<()>> {
+    let client = client.clone();
+    let dir = dir.clone();
+
+    future::loop_fn(1, move |attempts| {
+        let client2 = client.clone();
+        let dir2 = dir.clone();
+
+        fetch(&client, &dir)
+            .and_then(move |usage| {
+                let (version, usage) = match usage {
+                    Some(usage) => usage,
+                    None => return ok!(Loop::Break(())),
+                };
+                let (key, action) = fry!(action(&dir2, version, &usage, delta));
+
+                client2
+                    .mutate_mdata_entries(dir2.name, dir2.type_tag, btree_map![key => action])
+                    .map(|_| Loop::Break(()))
+                    .or_else(move |error| match error {
+                        CoreError::RoutingClientError(ClientError::InvalidEntryActions(_))
+                            if attempts < MAX_ATTEMPTS => Ok(Loop::Continue(attempts + 1)),
+                        error => Err(NfsError::from(error)),
+                    })
+                    .into_box()
+            })
+            .into_box()
+    }).into_box()
+}
+
+/// Returns `true` if the mutation failed only because the action on the usage
+/// record identified by `key` was rejected, in which case the usage has to be
+/// updated again from the fresh record.
+pub fn is_conflict(error: &CoreError, key: &[u8]) -> bool {
+    match *error {
+        CoreError::RoutingClientError(ClientError::InvalidEntryActions(ref errors)) => {
+            errors.len() == 1 && errors.contains_key(key)
+        }
+        _ => false,
+    }
+}
+
+// Build the action applying `delta` to the usage record at `version`.
+fn action(
+    dir: &MDataInfo,
+    version: u64,
+    usage: &Usage,
+    delta: Delta,
+) -> Result<(Vec<u8>, EntryAction), NfsError> {
+    let value = Value {
+        content: dir.enc_entry_value(&serialise(&usage.apply(delta))?)?,
+        entry_version: version + 1,
+    };
+    let key = dir.enc_entry_key(dir::USAGE_KEY)?;
+
+    Ok((key, EntryAction::Update(value)))
+}
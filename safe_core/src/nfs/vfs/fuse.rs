@@ -0,0 +1,326 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Thin FUSE binding mapping inode-based kernel requests to paths.
+
+use chrono::{DateTime, Utc};
+use fuse::{self, FileAttr, FileType, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory,
+           ReplyEmpty, ReplyEntry, ReplyOpen, ReplyWrite, Request};
+use libc::{self, c_int};
+use nfs::NfsError;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io;
+use std::path::Path;
+use super::{Attr, FileKind, Filesystem};
+use time::Timespec;
+
+// How long the kernel may cache attributes and entries.
+const TTL: Timespec = Timespec { sec: 1, nsec: 0 };
+const ROOT_INO: u64 = 1;
+
+/// Mount `fs` at `mountpoint`. Blocks until the filesystem is unmounted.
+pub fn mount<F, P>(fs: F, mountpoint: P) -> io::Result<()>
+where
+    F: Filesystem,
+    P: AsRef<Path>,
+{
+    fuse::mount(Fuse::new(fs), &mountpoint, &[])
+}
+
+// Fail the request with the errno corresponding to the error.
+macro_rules! try_reply {
+    ($reply:ident, $res:expr) => {
+        match $res {
+            Ok(value) => value,
+            Err(err) => return $reply.error(errno(&err)),
+        }
+    }
+}
+
+// Fail the request with `ENOENT` if the inode is unknown.
+macro_rules! try_path {
+    ($reply:ident, $path:expr) => {
+        match $path {
+            Some(path) => path,
+            None => return $reply.error(libc::ENOENT),
+        }
+    }
+}
+
+struct Fuse<F> {
+    fs: F,
+    paths: HashMap<u64, String>,
+    inodes: HashMap<String, u64>,
+    next_ino: u64,
+}
+
+impl<F: Filesystem> Fuse<F> {
+    fn new(fs: F) -> Self {
+        let mut paths = HashMap::new();
+        let mut inodes = HashMap::new();
+        let _ = paths.insert(ROOT_INO, String::new());
+        let _ = inodes.insert(String::new(), ROOT_INO);
+
+        Fuse {
+            fs,
+            paths,
+            inodes,
+            next_ino: ROOT_INO + 1,
+        }
+    }
+
+    // Get the inode of `path`, allocating a new one if needed.
+    fn ino(&mut self, path: &str) -> u64 {
+        if let Some(ino) = self.inodes.get(path) {
+            return *ino;
+        }
+
+        let ino = self.next_ino;
+        self.next_ino += 1;
+        let _ = self.paths.insert(ino, path.to_string());
+        let _ = self.inodes.insert(path.to_string(), ino);
+        ino
+    }
+
+    fn path(&self, ino: u64) -> Option<String> {
+        self.paths.get(&ino).cloned()
+    }
+
+    fn child(&self, parent: u64, name: &OsStr) -> Option<String> {
+        match (self.paths.get(&parent), name.to_str()) {
+            (Some(parent), Some(name)) => Some(format!("{}/{}", parent, name)),
+            _ => None,
+        }
+    }
+
+    fn file_attr(&mut self, req: &Request, path: &str, attr: &Attr) -> FileAttr {
+        let crtime = attr.created.as_ref().map_or(Timespec::new(0, 0), timespec);
+        let mtime = attr.modified.as_ref().map_or(crtime, timespec);
+        let (kind, perm) = match attr.kind {
+            FileKind::File => (FileType::RegularFile, 0o644),
+            FileKind::Dir => (FileType::Directory, 0o755),
+            FileKind::Symlink => (FileType::Symlink, 0o777),
+        };
+
+        FileAttr {
+            ino: self.ino(path),
+            size: attr.size,
+            blocks: (attr.size + 511) / 512,
+            atime: mtime,
+            mtime,
+            ctime: mtime,
+            crtime,
+            kind,
+            perm,
+            nlink: 1,
+            uid: req.uid(),
+            gid: req.gid(),
+            rdev: 0,
+            flags: 0,
+        }
+    }
+
+    // Update the inode table after the entry at `from` moved to `to`.
+    fn moved(&mut self, from: &str, to: &str) {
+        let prefix = format!("{}/", from);
+        let moved: Vec<_> = self.inodes
+            .keys()
+            .filter(|path| *path == from || path.starts_with(&prefix))
+            .cloned()
+            .collect();
+
+        for old in moved {
+            let new = format!("{}{}", to, &old[from.len()..]);
+            if let Some(ino) = self.inodes.remove(&old) {
+                let _ = self.paths.insert(ino, new.clone());
+                let _ = self.inodes.insert(new, ino);
+            }
+        }
+    }
+}
+
+impl<F: Filesystem> fuse::Filesystem for Fuse<F> {
+    fn lookup(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let path = try_path!(reply, self.child(parent, name));
+        let attr = try_reply!(reply, self.fs.getattr(&path));
+        reply.entry(&TTL, &self.file_attr(req, &path, &attr), 0);
+    }
+
+    fn getattr(&mut self, req: &Request, ino: u64, reply: ReplyAttr) {
+        let path = try_path!(reply, self.path(ino));
+        let attr = try_reply!(reply, self.fs.getattr(&path));
+        reply.attr(&TTL, &self.file_attr(req, &path, &attr));
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let path = try_path!(reply, self.path(ino));
+        let entries = try_reply!(reply, self.fs.readdir(&path));
+
+        let mut all = vec![
+            (ino, FileType::Directory, ".".to_string()),
+            (ino, FileType::Directory, "..".to_string()),
+        ];
+        for (name, attr) in entries {
+            let kind = match attr.kind {
+                FileKind::File => FileType::RegularFile,
+                FileKind::Dir => FileType::Directory,
+                FileKind::Symlink => FileType::Symlink,
+            };
+            let child_ino = self.ino(&format!("{}/{}", path, name));
+            all.push((child_ino, kind, name));
+        }
+
+        let entries = all.into_iter().enumerate().skip(offset as usize);
+        for (index, (child_ino, kind, name)) in entries {
+            if reply.add(child_ino, index as i64 + 1, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    fn open(&mut self, _req: &Request, ino: u64, _flags: u32, reply: ReplyOpen) {
+        let path = try_path!(reply, self.path(ino));
+        let fh = try_reply!(reply, self.fs.open(&path, false));
+        reply.opened(fh, 0);
+    }
+
+    fn create(
+        &mut self,
+        req: &Request,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _flags: u32,
+        reply: ReplyCreate,
+    ) {
+        let path = try_path!(reply, self.child(parent, name));
+        let fh = try_reply!(reply, self.fs.open(&path, true));
+        let attr = try_reply!(reply, self.fs.getattr(&path));
+        reply.created(&TTL, &self.file_attr(req, &path, &attr), 0, fh, 0);
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        reply: ReplyData,
+    ) {
+        let data = try_reply!(reply, self.fs.read(fh, offset as u64, u64::from(size)));
+        reply.data(&data);
+    }
+
+    fn write(
+        &mut self,
+        _req: &Request,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        data: &[u8],
+        _flags: u32,
+        reply: ReplyWrite,
+    ) {
+        let written = try_reply!(reply, self.fs.write(fh, offset as u64, data));
+        reply.written(written as u32);
+    }
+
+    fn flush(&mut self, _req: &Request, _ino: u64, fh: u64, _lock_owner: u64, reply: ReplyEmpty) {
+        try_reply!(reply, self.fs.fsync(fh));
+        reply.ok();
+    }
+
+    fn fsync(&mut self, _req: &Request, _ino: u64, fh: u64, _datasync: bool, reply: ReplyEmpty) {
+        try_reply!(reply, self.fs.fsync(fh));
+        reply.ok();
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request,
+        _ino: u64,
+        fh: u64,
+        _flags: u32,
+        _lock_owner: u64,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        try_reply!(reply, self.fs.release(fh));
+        reply.ok();
+    }
+
+    fn mkdir(&mut self, req: &Request, parent: u64, name: &OsStr, _mode: u32, reply: ReplyEntry) {
+        let path = try_path!(reply, self.child(parent, name));
+        try_reply!(reply, self.fs.mkdir(&path));
+        let attr = try_reply!(reply, self.fs.getattr(&path));
+        reply.entry(&TTL, &self.file_attr(req, &path, &attr), 0);
+    }
+
+    fn unlink(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let path = try_path!(reply, self.child(parent, name));
+        try_reply!(reply, self.fs.unlink(&path));
+        if let Some(ino) = self.inodes.remove(&path) {
+            let _ = self.paths.remove(&ino);
+        }
+        reply.ok();
+    }
+
+    fn rename(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        reply: ReplyEmpty,
+    ) {
+        let from = try_path!(reply, self.child(parent, name));
+        let to = try_path!(reply, self.child(newparent, newname));
+        try_reply!(reply, self.fs.rename(&from, &to));
+        self.moved(&from, &to);
+        reply.ok();
+    }
+}
+
+fn timespec(time: &DateTime<Utc>) -> Timespec {
+    Timespec::new(time.timestamp(), time.timestamp_subsec_nanos() as i32)
+}
+
+fn errno(error: &NfsError) -> c_int {
+    match *error {
+        NfsError::FileNotFound |
+        NfsError::DirectoryNotFound => libc::ENOENT,
+        NfsError::FileExists |
+        NfsError::DirectoryExists => libc::EEXIST,
+        NfsError::DirectoryNotEmpty => libc::ENOTEMPTY,
+        NfsError::InvalidRange |
+        NfsError::InvalidPath => libc::EINVAL,
+        NfsError::LinkCycle => libc::ELOOP,
+        NfsError::QuotaExceeded => libc::EDQUOT,
+        _ => libc::EIO,
+    }
+}
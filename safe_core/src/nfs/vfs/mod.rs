@@ -0,0 +1,439 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Synchronous, path-based filesystem interface on top of `nfs`, suitable for
+//! driving from FUSE-like callbacks which can't return futures.
+
+#[cfg(feature = "use-fuse")]
+mod fuse;
+#[cfg(test)]
+mod tests;
+
+#[cfg(feature = "use-fuse")]
+pub use self::fuse::mount;
+
+use chrono::{DateTime, Utc};
+use client::{Client, MDataInfo};
+use errors::CoreError;
+use event_loop::{CoreMsg, CoreMsgTx};
+use futures::Future;
+use futures::stream::{self, Stream};
+use nfs::{File, Mode, NfsError, NfsFuture, dir_helper, file_helper};
+use nfs::dir_helper::Node;
+use std::cmp;
+use std::collections::HashMap;
+use std::sync::mpsc;
+use utils::FutureExt;
+
+/// Handle of an open file.
+pub type FileHandle = u64;
+
+/// Kind of a filesystem entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind {
+    /// Regular file.
+    File,
+    /// Directory.
+    Dir,
+    /// Soft link.
+    Symlink,
+}
+
+/// Attributes of a filesystem entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attr {
+    /// Kind of the entry.
+    pub kind: FileKind,
+    /// Size in bytes. Always zero for directories and links.
+    pub size: u64,
+    /// Creation time. Only known for files.
+    pub created: Option<DateTime<Utc>>,
+    /// Modification time. Only known for files.
+    pub modified: Option<DateTime<Utc>>,
+}
+
+impl<'a> From<&'a Node> for Attr {
+    fn from(node: &'a Node) -> Attr {
+        match *node {
+            Node::File { ref file, .. } => Attr {
+                kind: FileKind::File,
+                size: file.size(),
+                created: Some(*file.created_time()),
+                modified: Some(*file.modified_time()),
+            },
+            Node::Dir { .. } => Attr {
+                kind: FileKind::Dir,
+                size: 0,
+                created: None,
+                modified: None,
+            },
+            Node::Link { .. } => Attr {
+                kind: FileKind::Symlink,
+                size: 0,
+                created: None,
+                modified: None,
+            },
+        }
+    }
+}
+
+/// Blocking, path-based filesystem operations. Paths are `/`-separated and
+/// relative to the root of the filesystem.
+pub trait Filesystem {
+    /// Get the attributes of the entry `name` in the directory at `parent`.
+    fn lookup(&self, parent: &str, name: &str) -> Result<Attr, NfsError>;
+
+    /// Get the attributes of the entry at `path`.
+    fn getattr(&self, path: &str) -> Result<Attr, NfsError>;
+
+    /// List the entries of the directory at `path`.
+    fn readdir(&self, path: &str) -> Result<Vec<(String, Attr)>, NfsError>;
+
+    /// Open the file at `path`. If `create` is `true` and the file doesn't
+    /// exist, an empty one is created first.
+    fn open(&mut self, path: &str, create: bool) -> Result<FileHandle, NfsError>;
+
+    /// Read up to `size` bytes at `offset` from an open file. Fewer bytes are
+    /// returned if the range crosses the end of the file.
+    fn read(&self, fh: FileHandle, offset: u64, size: u64) -> Result<Vec<u8>, NfsError>;
+
+    /// Write `data` at `offset` to an open file, returning the number of bytes
+    /// written.
+    fn write(&mut self, fh: FileHandle, offset: u64, data: &[u8]) -> Result<usize, NfsError>;
+
+    /// Store the changes made through an open file so far, keeping it open.
+    fn fsync(&mut self, fh: FileHandle) -> Result<(), NfsError>;
+
+    /// Close an open file, storing any changes made through it.
+    fn release(&mut self, fh: FileHandle) -> Result<(), NfsError>;
+
+    /// Create the directory at `path`.
+    fn mkdir(&self, path: &str) -> Result<(), NfsError>;
+
+    /// Remove the file or link at `path`.
+    fn unlink(&self, path: &str) -> Result<(), NfsError>;
+
+    /// Move the entry at `from` to `to`.
+    fn rename(&self, from: &str, to: &str) -> Result<(), NfsError>;
+}
+
+/// `Filesystem` backed by a `Client` running in a core event loop. Each call
+/// is sent to the event loop and blocks until it completes, so it must not
+/// be used from the event loop thread itself.
+///
+/// Writes are buffered in memory for each open file and only stored, all at
+/// once, when the file is synced or released.
+pub struct SyncFs<T> {
+    core_tx: CoreMsgTx<T>,
+    root: MDataInfo,
+    handles: HashMap<FileHandle, OpenFile>,
+    next_handle: FileHandle,
+}
+
+struct OpenFile {
+    parent: MDataInfo,
+    name: String,
+    version: u64,
+    file: File,
+    // Writes not stored yet, in the order they were made. Consecutive writes
+    // are merged into one.
+    writes: Vec<(u64, Vec<u8>)>,
+    // Size of the file including the writes not stored yet.
+    size: u64,
+}
+
+impl OpenFile {
+    fn write(&mut self, offset: u64, data: &[u8]) {
+        self.size = cmp::max(self.size, offset + data.len() as u64);
+
+        if let Some(&mut (last_offset, ref mut last_data)) = self.writes.last_mut() {
+            if last_offset + last_data.len() as u64 == offset {
+                last_data.extend_from_slice(data);
+                return;
+            }
+        }
+        self.writes.push((offset, data.to_vec()));
+    }
+
+    // Apply the writes not stored yet to `data` read at `offset`.
+    fn overlay(&self, offset: u64, data: &mut [u8]) {
+        let end = offset + data.len() as u64;
+
+        for &(write_offset, ref write_data) in &self.writes {
+            let start = cmp::max(offset, write_offset);
+            let stop = cmp::min(end, write_offset + write_data.len() as u64);
+            if start >= stop {
+                continue;
+            }
+            data[(start - offset) as usize..(stop - offset) as usize].copy_from_slice(
+                &write_data[(start - write_offset) as usize..(stop - write_offset) as usize],
+            );
+        }
+    }
+}
+
+impl<T: 'static> SyncFs<T> {
+    /// Create new filesystem rooted at the directory `root`, using the client
+    /// of the event loop `core_tx` sends to.
+    pub fn new(core_tx: CoreMsgTx<T>, root: MDataInfo) -> Self {
+        SyncFs {
+            core_tx,
+            root,
+            handles: HashMap::new(),
+            next_handle: 0,
+        }
+    }
+
+    // Run the future returned by `f` in the event loop and wait for its result.
+    fn run<F, R>(&self, f: F) -> Result<R, NfsError>
+    where
+        F: FnOnce(&Client<T>) -> Box<NfsFuture<R>> + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let msg = CoreMsg::new(move |client, _| {
+            let fut = f(client)
+                .then(move |result| {
+                    let _ = tx.send(result);
+                    Ok(())
+                })
+                .into_box();
+            Some(fut)
+        });
+
+        self.core_tx.unbounded_send(msg).map_err(CoreError::from)?;
+        rx.recv().map_err(CoreError::from)?
+    }
+
+    fn handle(&self, fh: FileHandle) -> Result<&OpenFile, NfsError> {
+        self.handles.get(&fh).ok_or_else(
+            || NfsError::from("Invalid file handle"),
+        )
+    }
+
+    // Store the writes made through the handle with a single writer and
+    // update the file in its directory.
+    fn flush(&self, handle: &OpenFile) -> Result<Option<File>, NfsError> {
+        if handle.writes.is_empty() {
+            return Ok(None);
+        }
+
+        let parent = handle.parent.clone();
+        let name = handle.name.clone();
+        let version = handle.version;
+        let file = handle.file.clone();
+        let writes = handle.writes.clone();
+
+        self.run(move |client| {
+            let client2 = client.clone();
+
+            file_helper::write_in(client.clone(), &parent, file, Mode::Modify)
+                .and_then(move |writer| {
+                    stream::iter_ok::<_, NfsError>(writes)
+                        .fold(writer, |writer, (offset, data)| {
+                            writer.write_at(offset, &data).map(move |_| writer)
+                        })
+                        .and_then(|writer| writer.close())
+                })
+                .and_then(move |file| {
+                    file_helper::update(client2, parent, name, &file, version + 1)
+                        .map(move |_| Some(file))
+                })
+                .into_box()
+        })
+    }
+}
+
+impl<T: 'static> Filesystem for SyncFs<T> {
+    fn lookup(&self, parent: &str, name: &str) -> Result<Attr, NfsError> {
+        self.getattr(&format!("{}/{}", parent, name))
+    }
+
+    fn getattr(&self, path: &str) -> Result<Attr, NfsError> {
+        let root = self.root.clone();
+        let path = path.to_string();
+        let node = self.run(move |client| {
+            dir_helper::stat(client.clone(), root, path)
+        })?;
+        Ok(Attr::from(&node))
+    }
+
+    fn readdir(&self, path: &str) -> Result<Vec<(String, Attr)>, NfsError> {
+        let root = self.root.clone();
+        let path = path.to_string();
+        let nodes = self.run(move |client| {
+            dir_helper::list(client.clone(), root, path)
+        })?;
+        Ok(
+            nodes
+                .iter()
+                .map(|(name, node)| (name.clone(), Attr::from(node)))
+                .collect(),
+        )
+    }
+
+    fn open(&mut self, path: &str, create: bool) -> Result<FileHandle, NfsError> {
+        let (parent, name) = split_path(path)?;
+        let root = self.root.clone();
+        let name2 = name.clone();
+
+        let (parent, version, file) = self.run(move |client| {
+            let client = client.clone();
+            dir_helper::open_dir(client.clone(), root, parent)
+                .and_then(move |parent| {
+                    let parent2 = parent.clone();
+                    file_helper::fetch(client.clone(), parent.clone(), name2.clone())
+                        .then(move |res| match res {
+                            Ok((version, file)) => ok!((version, file)),
+                            Err(NfsError::FileNotFound) if create => {
+                                // The new entry may reuse the slot of a deleted
+                                // one, so its version has to be fetched.
+                                let file = File::new(Vec::new());
+                                file_helper::insert(client.clone(), parent2.clone(), &name2, &file)
+                                    .and_then(move |_| file_helper::fetch(client, parent2, name2))
+                                    .into_box()
+                            }
+                            Err(err) => err!(err),
+                        })
+                        .map(move |(version, file)| (parent, version, file))
+                })
+                .into_box()
+        })?;
+
+        let fh = self.next_handle;
+        self.next_handle += 1;
+        let size = file.size();
+        let _ = self.handles.insert(
+            fh,
+            OpenFile {
+                parent,
+                name,
+                version,
+                file,
+                writes: Vec::new(),
+                size,
+            },
+        );
+        Ok(fh)
+    }
+
+    fn read(&self, fh: FileHandle, offset: u64, size: u64) -> Result<Vec<u8>, NfsError> {
+        let handle = self.handle(fh)?;
+        let end = cmp::min(offset.saturating_add(size), handle.size);
+        if offset >= end {
+            return Ok(Vec::new());
+        }
+
+        let mut data = if offset < handle.file.size() {
+            let file = handle.file.clone();
+            let key = handle.parent.enc_key().cloned();
+            self.run(move |client| {
+                file_helper::read(client.clone(), &file, key)
+                    .and_then(move |reader| reader.read_clamped(offset, end - offset))
+                    .into_box()
+            })?
+        } else {
+            Vec::new()
+        };
+
+        // Anything past the stored data was written through the handle or
+        // is a hole.
+        data.resize((end - offset) as usize, 0);
+        handle.overlay(offset, &mut data);
+        Ok(data)
+    }
+
+    fn write(&mut self, fh: FileHandle, offset: u64, data: &[u8]) -> Result<usize, NfsError> {
+        let handle = self.handles.get_mut(&fh).ok_or_else(
+            || NfsError::from("Invalid file handle"),
+        )?;
+        handle.write(offset, data);
+        Ok(data.len())
+    }
+
+    fn fsync(&mut self, fh: FileHandle) -> Result<(), NfsError> {
+        let file = self.flush(self.handle(fh)?)?;
+
+        if let (Some(file), Some(handle)) = (file, self.handles.get_mut(&fh)) {
+            handle.file = file;
+            handle.version += 1;
+            handle.writes.clear();
+        }
+        Ok(())
+    }
+
+    fn release(&mut self, fh: FileHandle) -> Result<(), NfsError> {
+        let result = self.fsync(fh);
+        let _ = self.handles.remove(&fh);
+        result
+    }
+
+    fn mkdir(&self, path: &str) -> Result<(), NfsError> {
+        let root = self.root.clone();
+        let path = path.to_string();
+        self.run(move |client| {
+            dir_helper::mkdir(client.clone(), root, path, false)
+                .map(|_| ())
+                .into_box()
+        })
+    }
+
+    fn unlink(&self, path: &str) -> Result<(), NfsError> {
+        let (parent, name) = split_path(path)?;
+        let root = self.root.clone();
+
+        self.run(move |client| {
+            let client = client.clone();
+            dir_helper::open_dir(client.clone(), root, parent)
+                .and_then(move |parent| {
+                    dir_helper::stat(client.clone(), parent.clone(), name.clone())
+                        .and_then(move |node| match node {
+                            Node::File { version, .. } => {
+                                file_helper::delete(&client, &parent, name, version + 1)
+                            }
+                            Node::Link { version, .. } => {
+                                file_helper::delete_link(&client, &parent, name, version + 1)
+                            }
+                            Node::Dir { .. } => err!(NfsError::from("Is a directory")),
+                        })
+                })
+                .into_box()
+        })
+    }
+
+    fn rename(&self, from: &str, to: &str) -> Result<(), NfsError> {
+        let root = self.root.clone();
+        let from = from.to_string();
+        let to = to.to_string();
+        self.run(move |client| dir_helper::rename(client.clone(), root, from, to))
+    }
+}
+
+// Split `path` into the path of the parent directory and the entry name.
+fn split_path(path: &str) -> Result<(String, String), NfsError> {
+    let path = path.trim_right_matches('/');
+    let (parent, name) = match path.rfind('/') {
+        Some(pos) => (&path[..pos], &path[pos + 1..]),
+        None => ("", path),
+    };
+
+    if name.is_empty() {
+        Err(NfsError::from("Invalid path"))
+    } else {
+        Ok((parent.to_string(), name.to_string()))
+    }
+}
@@ -0,0 +1,220 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use DIR_TAG;
+use client::{Client, MDataInfo};
+use event_loop::{self, CoreMsg, CoreMsgTx};
+use futures::sync::mpsc;
+use maidsafe_utilities::thread;
+use nfs::{NfsError, create_dir};
+use std::sync::mpsc as std_mpsc;
+use super::{FileKind, Filesystem, SyncFs};
+use tokio_core::reactor::Core;
+use utils;
+
+// Run `f` with a filesystem rooted at a new directory of a random registered
+// client. The client lives in its own event loop thread, as it would when
+// serving FUSE requests.
+fn run_fs<F>(f: F)
+where
+    F: FnOnce(&mut SyncFs<()>),
+{
+    let (tx, rx) = std_mpsc::channel();
+
+    let _joiner = thread::named("Core Event Loop", move || {
+        let mut el = unwrap!(Core::new());
+        let el_h = el.handle();
+
+        let (core_tx, core_rx) = mpsc::unbounded();
+        let (net_tx, _net_rx) = mpsc::unbounded();
+
+        let acc_locator = unwrap!(utils::generate_random_string(10));
+        let acc_password = unwrap!(utils::generate_random_string(10));
+        let invitation = unwrap!(utils::generate_random_string(10));
+        let client = unwrap!(Client::registered(
+            &acc_locator,
+            &acc_password,
+            &invitation,
+            el_h,
+            core_tx.clone(),
+            net_tx,
+        ));
+
+        let root = unwrap!(MDataInfo::random_private(DIR_TAG));
+        unwrap!(el.run(create_dir(&client, &root, btree_map![], btree_map![])));
+        unwrap!(tx.send((core_tx, root)));
+
+        event_loop::run(el, &client, &(), core_rx);
+    });
+
+    let (core_tx, root) = unwrap!(rx.recv());
+    // Stops the event loop before the thread is joined, even if `f` panics.
+    let _terminator = Terminator(core_tx.clone());
+
+    let mut fs = SyncFs::new(core_tx, root);
+    f(&mut fs);
+}
+
+struct Terminator(CoreMsgTx<()>);
+
+impl Drop for Terminator {
+    fn drop(&mut self) {
+        let _ = self.0.unbounded_send(CoreMsg::build_terminator());
+    }
+}
+
+// Create, write, read back and reopen a file.
+#[test]
+fn vfs_files() {
+    run_fs(|fs| {
+        match fs.open("/hello.txt", false) {
+            Err(NfsError::FileNotFound) => (),
+            Err(x) => panic!("Unexpected: {:?}", x),
+            Ok(_) => panic!("Unexpected success"),
+        }
+
+        let fh = unwrap!(fs.open("/hello.txt", true));
+        assert_eq!(unwrap!(fs.write(fh, 0, b"hello")), 5);
+        assert_eq!(unwrap!(fs.write(fh, 5, b" world")), 6);
+        assert_eq!(unwrap!(fs.read(fh, 0, 100)), b"hello world".to_vec());
+
+        // Changes are only stored on release.
+        assert_eq!(unwrap!(fs.getattr("/hello.txt")).size, 0);
+        unwrap!(fs.release(fh));
+
+        let attr = unwrap!(fs.lookup("/", "hello.txt"));
+        assert_eq!(attr.kind, FileKind::File);
+        assert_eq!(attr.size, 11);
+        assert!(attr.modified.is_some());
+
+        let fh = unwrap!(fs.open("hello.txt", true));
+        assert_eq!(unwrap!(fs.read(fh, 6, 5)), b"world".to_vec());
+        assert_eq!(unwrap!(fs.write(fh, 0, b"HELLO")), 5);
+        unwrap!(fs.release(fh));
+
+        let fh = unwrap!(fs.open("/hello.txt", false));
+        assert_eq!(unwrap!(fs.read(fh, 0, 11)), b"HELLO world".to_vec());
+        assert_eq!(unwrap!(fs.read(fh, 20, 5)), Vec::<u8>::new());
+        unwrap!(fs.release(fh));
+
+        assert!(fs.release(fh).is_err());
+    });
+}
+
+// Create directories, move files around and remove them.
+#[test]
+fn vfs_dirs() {
+    run_fs(|fs| {
+        unwrap!(fs.mkdir("/docs"));
+        unwrap!(fs.mkdir("/docs/old"));
+        match fs.mkdir("/docs") {
+            Err(NfsError::DirectoryExists) => (),
+            Err(x) => panic!("Unexpected: {:?}", x),
+            Ok(_) => panic!("Unexpected success"),
+        }
+
+        let fh = unwrap!(fs.open("/docs/a.txt", true));
+        unwrap!(fs.release(fh));
+
+        let entries = unwrap!(fs.readdir("/docs"));
+        let names: Vec<_> = entries.iter().map(|&(ref name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["a.txt", "old"]);
+        assert_eq!(entries[1].1.kind, FileKind::Dir);
+
+        unwrap!(fs.rename("/docs/a.txt", "/docs/old/b.txt"));
+        assert_eq!(unwrap!(fs.readdir("/docs")).len(), 1);
+        assert_eq!(
+            unwrap!(fs.getattr("/docs/old/b.txt")).kind,
+            FileKind::File
+        );
+
+        assert!(fs.unlink("/docs/old").is_err());
+        unwrap!(fs.unlink("/docs/old/b.txt"));
+        match fs.getattr("/docs/old/b.txt") {
+            Err(NfsError::FileNotFound) => (),
+            Err(x) => panic!("Unexpected: {:?}", x),
+            Ok(_) => panic!("Unexpected success"),
+        }
+        assert!(unwrap!(fs.readdir("/docs/old")).is_empty());
+    });
+}
+
+// Recreate a file after unlinking it, which reuses the entry of the deleted
+// file, and write to it.
+#[test]
+fn vfs_unlink_and_recreate() {
+    run_fs(|fs| {
+        let fh = unwrap!(fs.open("/a.txt", true));
+        assert_eq!(unwrap!(fs.write(fh, 0, b"one")), 3);
+        unwrap!(fs.release(fh));
+        unwrap!(fs.unlink("/a.txt"));
+
+        for content in &[&b"two"[..], &b"three"[..]] {
+            let fh = unwrap!(fs.open("/a.txt", true));
+            assert_eq!(unwrap!(fs.write(fh, 0, content)), content.len());
+            unwrap!(fs.release(fh));
+
+            let fh = unwrap!(fs.open("/a.txt", false));
+            assert_eq!(unwrap!(fs.read(fh, 0, 10)), content.to_vec());
+            unwrap!(fs.release(fh));
+            unwrap!(fs.unlink("/a.txt"));
+        }
+    });
+}
+
+// Write a file in many small sequential and overlapping writes, syncing it
+// halfway, and read it back both before and after it is stored.
+#[test]
+fn vfs_multiple_writes() {
+    const CHUNK_SIZE: usize = 1000;
+    const NUM_CHUNKS: usize = 100;
+
+    run_fs(|fs| {
+        let fh = unwrap!(fs.open("/big.bin", true));
+        let mut expected = Vec::with_capacity(CHUNK_SIZE * NUM_CHUNKS);
+
+        for index in 0..NUM_CHUNKS {
+            let chunk = vec![index as u8; CHUNK_SIZE];
+            let offset = (index * CHUNK_SIZE) as u64;
+            assert_eq!(unwrap!(fs.write(fh, offset, &chunk)), CHUNK_SIZE);
+            expected.extend_from_slice(&chunk);
+
+            if index == NUM_CHUNKS / 2 {
+                unwrap!(fs.fsync(fh));
+                let size = ((index + 1) * CHUNK_SIZE) as u64;
+                assert_eq!(unwrap!(fs.getattr("/big.bin")).size, size);
+            }
+        }
+
+        // Overwrite a range spanning both stored and buffered data.
+        let offset = NUM_CHUNKS / 2 * CHUNK_SIZE - 10;
+        assert_eq!(unwrap!(fs.write(fh, offset as u64, &[0xff; 20])), 20);
+        for byte in &mut expected[offset..offset + 20] {
+            *byte = 0xff;
+        }
+
+        let size = expected.len() as u64;
+        assert_eq!(unwrap!(fs.read(fh, 0, size + 10)), expected);
+        unwrap!(fs.release(fh));
+
+        assert_eq!(unwrap!(fs.getattr("/big.bin")).size, size);
+        let fh = unwrap!(fs.open("/big.bin", false));
+        assert_eq!(unwrap!(fs.read(fh, 0, size)), expected);
+        assert_eq!(unwrap!(fs.read(fh, offset as u64, 20)), vec![0xff; 20]);
+        unwrap!(fs.release(fh));
+    });
+}
@@ -19,17 +19,38 @@ use chrono::Utc;
 use client::Client;
 use crypto::shared_secretbox;
 use futures::Future;
-use nfs::{File, NfsFuture, data_map};
-use self_encryption::SequentialEncryptor;
+use futures::future::{self, Loop};
+use nfs::{File, NfsError, NfsFuture, data_map};
+use nfs::digest;
+use self_encryption::{DataMap, SelfEncryptor, SequentialEncryptor};
 use self_encryption_storage::SelfEncryptionStorage;
-use utils::FutureExt;
+use routing::XorName;
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::rc::Rc;
+use tiny_keccak::Keccak;
+use utils::{self, FutureExt};
 
-/// Mode of the writer
+/// Files of at most this size are stored inline in the `File` itself, which
+/// saves storing their data-map on the network.
+pub const MAX_INLINE_SIZE: usize = 2048;
+
+// Size of the chunks of zeros written when filling a hole.
+const HOLE_CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// Mode of the writer.
 pub enum Mode {
     /// Will create new data
     Overwrite,
-    /// Will append content to the existing data
+    /// Will append content to the existing data. The digest of the file is
+    /// not kept, as that would require reading all the existing data; use
+    /// `Reader::digest` to compute it when needed.
     Append,
+    /// Will modify the existing data in place. Allows writing at arbitrary
+    /// offsets and truncating the data. As with `Append`, the digest of the
+    /// file is not kept. Extending the file by truncating it leaves a hole
+    /// which isn't stored on the network and reads as zeros.
+    Modify,
 }
 
 /// Writer is used to write contents to a File and especially in chunks if the
@@ -37,8 +58,16 @@ pub enum Mode {
 pub struct Writer<T> {
     client: Client<T>,
     file: File,
-    self_encryptor: SequentialEncryptor<SelfEncryptionStorage<T>>,
+    self_encryptor: Encryptor<T>,
     encryption_key: Option<shared_secretbox::Key>,
+    // Digest of the data written so far. Only kept when the file is written
+    // from scratch, otherwise the file is left without a digest.
+    hasher: Option<RefCell<Keccak>>,
+    // Size of the file. Anything past the data held by the self-encryptor is
+    // a hole, which is only filled with zeros once data is written after it.
+    size: Cell<u64>,
+    // Size the file must not grow past, if any.
+    size_limit: Option<u64>,
 }
 
 impl<T: 'static> Writer<T> {
@@ -52,62 +81,270 @@ impl<T: 'static> Writer<T> {
     ) -> Box<NfsFuture<Writer<T>>> {
         let fut = match mode {
             Mode::Append => {
-                data_map::get(&client, file.data_map_name(), encryption_key.clone())
-                    .map(Some)
+                data_map::get_for_file(&client, &file, encryption_key.clone())
+                    .and_then(move |data_map| {
+                        SequentialEncryptor::new(storage, Some(data_map))
+                            .map_err(From::from)
+                            .map(|self_encryptor| {
+                                (Encryptor::Sequential(Rc::new(self_encryptor)), None)
+                            })
+                    })
+                    .into_box()
+            }
+            Mode::Overwrite => {
+                SequentialEncryptor::new(storage, None)
+                    .map(|self_encryptor| {
+                        (
+                            Encryptor::Sequential(Rc::new(self_encryptor)),
+                            Some(digest::hasher()),
+                        )
+                    })
+                    .map_err(From::from)
+                    .into_box()
+            }
+            Mode::Modify => {
+                data_map::get_for_file(&client, &file, encryption_key.clone())
+                    .and_then(move |data_map| {
+                        let self_encryptor = SelfEncryptor::new(storage, data_map)?;
+                        Ok((Encryptor::RandomAccess(self_encryptor), None))
+                    })
                     .into_box()
             }
-            Mode::Overwrite => ok!(None),
         };
         let client = client.clone();
-        fut.and_then(move |data_map| {
-            SequentialEncryptor::new(storage, data_map).map_err(From::from)
-        }).map(move |self_encryptor| {
-                Writer {
-                    client,
-                    file,
-                    self_encryptor,
-                    encryption_key,
-                }
-            })
-            .map_err(From::from)
-            .into_box()
+        let size = match mode {
+            Mode::Overwrite => 0,
+            Mode::Append | Mode::Modify => file.size(),
+        };
+        fut.map(move |(self_encryptor, hasher)| {
+            let size = cmp::max(size, self_encryptor.len());
+            Writer {
+                client,
+                file,
+                self_encryptor,
+                encryption_key,
+                hasher: hasher.map(RefCell::new),
+                size: Cell::new(size),
+                size_limit: None,
+            }
+        }).into_box()
     }
 
-    /// Data of a file/blob can be written in smaller chunks
+    /// Limit the size of the file. Writes and truncations which would make the
+    /// file grow past the limit fail with `NfsError::QuotaExceeded` before any
+    /// data is uploaded.
+    pub fn with_size_limit(mut self, size_limit: u64) -> Self {
+        self.size_limit = Some(size_limit);
+        self
+    }
+
+    /// Data of a file/blob can be written in smaller chunks. In `Mode::Modify`
+    /// the data is written at the current end of the file.
     pub fn write(&self, data: &[u8]) -> Box<NfsFuture<()>> {
         trace!(
             "Writer writing file data of size {} into self-encryptor.",
             data.len()
         );
-        self.self_encryptor
-            .write(data)
-            .map_err(From::from)
-            .into_box()
+        fry!(self.check_size(self.size.get() + data.len() as u64));
+        if let Some(ref hasher) = self.hasher {
+            hasher.borrow_mut().update(data);
+        }
+        let size = self.size.get();
+        self.size.set(size + data.len() as u64);
+
+        match self.self_encryptor {
+            Encryptor::Sequential(ref self_encryptor) => {
+                let hole_size = size - self_encryptor.len();
+                if hole_size > 0 {
+                    // The hole at the end of a sparse file has to be filled
+                    // before appending to it.
+                    let self_encryptor = Rc::clone(self_encryptor);
+                    let data = data.to_vec();
+                    fill_hole(Rc::clone(&self_encryptor), hole_size)
+                        .and_then(move |_| self_encryptor.write(&data).map_err(From::from))
+                        .into_box()
+                } else {
+                    self_encryptor.write(data).map_err(From::from).into_box()
+                }
+            }
+            Encryptor::RandomAccess(ref self_encryptor) => {
+                self_encryptor
+                    .write(data, size)
+                    .map_err(From::from)
+                    .into_box()
+            }
+        }
+    }
+
+    /// Write data at the given offset, overwriting any existing data in the
+    /// range. Writing past the current end of the file extends it.
+    /// Only available in `Mode::Modify`.
+    pub fn write_at(&self, offset: u64, data: &[u8]) -> Box<NfsFuture<()>> {
+        trace!(
+            "Writer writing file data of size {} at offset {}.",
+            data.len(),
+            offset
+        );
+        match self.self_encryptor {
+            Encryptor::RandomAccess(ref self_encryptor) => {
+                let end = offset + data.len() as u64;
+                fry!(self.check_size(end));
+                if end > self.size.get() {
+                    self.size.set(end);
+                }
+                self_encryptor
+                    .write(data, offset)
+                    .map_err(From::from)
+                    .into_box()
+            }
+            Encryptor::Sequential(_) => err!(NfsError::from("Writer is not in Modify mode")),
+        }
+    }
+
+    /// Truncate the file to the given size. If the file is shorter, it is
+    /// extended with a hole which reads as zeros. The hole is not stored on
+    /// the network unless data is written past it.
+    /// Only available in `Mode::Modify`.
+    pub fn truncate(&self, size: u64) -> Box<NfsFuture<()>> {
+        trace!("Writer truncating file to size {}.", size);
+        match self.self_encryptor {
+            Encryptor::RandomAccess(ref self_encryptor) => {
+                fry!(self.check_size(size));
+                self.size.set(size);
+                if size >= self_encryptor.len() {
+                    return ok!(());
+                }
+                self_encryptor
+                    .truncate(size)
+                    .map_err(From::from)
+                    .into_box()
+            }
+            Encryptor::Sequential(_) => err!(NfsError::from("Writer is not in Modify mode")),
+        }
+    }
+
+    /// Returns the current size of the file, including any hole at its end.
+    pub fn size(&self) -> u64 {
+        self.size.get()
+    }
+
+    // Fail if growing the file to `size` would exceed its size limit.
+    fn check_size(&self, size: u64) -> Result<(), NfsError> {
+        match self.size_limit {
+            Some(limit) if size > limit && size > self.size.get() => Err(NfsError::QuotaExceeded),
+            _ => Ok(()),
+        }
     }
 
     /// close is invoked only after all the data is completely written. The
     /// file/blob is saved only when the close is invoked. Returns the final
     /// `File` with the data_map stored on the network.
+    /// Files without any stored data (empty files or files consisting of a
+    /// hole only) are saved without touching the network at all.
     pub fn close(self) -> Box<NfsFuture<File>> {
         trace!("Writer induced self-encryptor close.");
 
+        let size = self.size();
+        let data_size = self.self_encryptor.len();
         let mut file = self.file;
-        let size = self.self_encryptor.len();
         let client = self.client;
         let encryption_key = self.encryption_key;
+        let digest = self.hasher.map(
+            |hasher| digest::finalise(hasher.into_inner()),
+        );
+
+        if data_size == 0 {
+            file.set_data_map_name(XorName::default());
+            file.set_inline_content(None);
+            file.set_data_size(Some(0));
+            file.set_modified_time(Utc::now());
+            file.set_size(size);
+            file.set_digest(digest);
+            return ok!(file);
+        }
 
         self.self_encryptor
             .close()
-            .map_err(From::from)
-            .and_then(move |(data_map, _)| {
-                data_map::put(&client, &data_map, encryption_key)
+            .and_then(move |data_map| match data_map {
+                DataMap::Content(ref content) if content.len() <= MAX_INLINE_SIZE => {
+                    // The inline content is encrypted just like the data-map
+                    // would be.
+                    let content = match encryption_key {
+                        Some(ref key) => fry!(utils::symmetric_encrypt(content, key, None)),
+                        None => content.clone(),
+                    };
+                    ok!((XorName::default(), Some(content), digest))
+                }
+                data_map => {
+                    data_map::put(&client, &data_map, encryption_key)
+                        .map(move |data_map_name| (data_map_name, None, digest))
+                        .into_box()
+                }
             })
-            .map(move |data_map_name| {
+            .map(move |(data_map_name, inline_content, digest)| {
                 file.set_data_map_name(data_map_name);
+                file.set_inline_content(inline_content);
+                file.set_data_size(Some(data_size));
                 file.set_modified_time(Utc::now());
                 file.set_size(size);
+                file.set_digest(digest);
                 file
             })
             .into_box()
     }
 }
+
+// Write `length` zeros through the sequential self-encryptor, a bounded chunk
+// at a time so that large holes aren't held in memory as a whole.
+fn fill_hole<T: 'static>(
+    self_encryptor: Rc<SequentialEncryptor<SelfEncryptionStorage<T>>>,
+    length: u64,
+) -> Box<NfsFuture<()>> {
+    future::loop_fn(length, move |remaining| {
+        if remaining == 0 {
+            return ok!(Loop::Break(()));
+        }
+
+        let length = cmp::min(remaining, HOLE_CHUNK_SIZE);
+        self_encryptor
+            .write(&vec![0; length as usize])
+            .map_err(From::from)
+            .map(move |_| Loop::Continue(remaining - length))
+            .into_box()
+    }).into_box()
+}
+
+// Self-encryptor used by the writer, depending on the mode. The sequential
+// one is shared with the futures filling holes.
+enum Encryptor<T> {
+    Sequential(Rc<SequentialEncryptor<SelfEncryptionStorage<T>>>),
+    RandomAccess(SelfEncryptor<SelfEncryptionStorage<T>>),
+}
+
+impl<T: 'static> Encryptor<T> {
+    fn len(&self) -> u64 {
+        match *self {
+            Encryptor::Sequential(ref self_encryptor) => self_encryptor.len(),
+            Encryptor::RandomAccess(ref self_encryptor) => self_encryptor.len(),
+        }
+    }
+
+    fn close(self) -> Box<NfsFuture<DataMap>> {
+        let fut = match self {
+            Encryptor::Sequential(self_encryptor) => {
+                match Rc::try_unwrap(self_encryptor) {
+                    Ok(self_encryptor) => self_encryptor.close(),
+                    Err(_) => {
+                        return err!(NfsError::from(
+                            "Writer closed while a write is still pending",
+                        ))
+                    }
+                }
+            }
+            Encryptor::RandomAccess(self_encryptor) => self_encryptor.close(),
+        };
+        fut.map(|(data_map, _)| data_map)
+            .map_err(From::from)
+            .into_box()
+    }
+}